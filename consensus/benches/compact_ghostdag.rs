@@ -0,0 +1,168 @@
+//!
+//! Compares the volume of ghostdag data decoded by the hot paths of the protocol on a wide
+//! dag, when reading the full `GhostdagData` of the blocks as it used to be done, and when
+//! reading their compact data.
+//!
+//! Run with `cargo bench -p consensus --features test-util --bench compact_ghostdag`.
+//!
+use consensus::blockdag::{BlockDAG, DbGhostdagManager, ParentsSelectionParams};
+use consensus::genesis::DagGenesisConfig;
+use consensus::test_util::new_test_header;
+use consensus_types::{header::ConsensusHeader, ordering::SortableBlock};
+use database::consensus::GhostdagStoreReader;
+use database::prelude::{FlexiDagStorage, FlexiDagStorageConfig};
+use parking_lot::RwLock;
+use reachability::reachability_service::MTReachabilityService;
+use starcoin_crypto::HashValue as Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The blocks of a layer, each of them referencing every block of the previous layer
+const WIDTH: usize = 16;
+const LAYERS: usize = 100;
+const CACHE_SIZE: u64 = 4096;
+
+/// The decoding done by a workload, all column families together
+struct Measure {
+    deserializations: u64,
+    bytes_deserialized: u64,
+    elapsed: Duration,
+}
+
+fn measure(storage: &FlexiDagStorage, workload: impl FnOnce()) -> Measure {
+    let decoded = |storage: &FlexiDagStorage| {
+        storage
+            .metrics_snapshot()
+            .values()
+            .fold((0, 0), |(count, bytes), metrics| {
+                (
+                    count + metrics.deserializations,
+                    bytes + metrics.bytes_deserialized,
+                )
+            })
+    };
+    let (count_before, bytes_before) = decoded(storage);
+    let start = Instant::now();
+    workload();
+    let elapsed = start.elapsed();
+    let (count_after, bytes_after) = decoded(storage);
+    Measure {
+        deserializations: count_after - count_before,
+        bytes_deserialized: bytes_after - bytes_before,
+        elapsed,
+    }
+}
+
+fn report(workload: &str, full: Measure, compact: Measure) {
+    println!(
+        "{:<24} full: {:>7} records {:>10} bytes {:>8.2?}   compact: {:>7} records {:>10} bytes {:>8.2?}   ({:.1}x fewer bytes)",
+        workload,
+        full.deserializations,
+        full.bytes_deserialized,
+        full.elapsed,
+        compact.deserializations,
+        compact.bytes_deserialized,
+        compact.elapsed,
+        full.bytes_deserialized as f64 / compact.bytes_deserialized.max(1) as f64,
+    );
+}
+
+/// Builds a dag of `LAYERS` layers of `WIDTH` blocks, returning the layers
+fn build_wide_dag(
+    path: &std::path::Path,
+    config: FlexiDagStorageConfig,
+    genesis: &DagGenesisConfig,
+) -> Vec<Vec<Hash>> {
+    let storage = FlexiDagStorage::create_from_path(path, config).unwrap();
+    let genesis_header = genesis.to_header();
+    // Every block references the whole previous layer
+    let mut dag = BlockDAG::new(genesis_header.clone(), genesis.k, storage)
+        .unwrap()
+        .with_parents_selection(ParentsSelectionParams {
+            max_parents: WIDTH,
+            ..ParentsSelectionParams::default()
+        });
+    let mut layers = vec![vec![genesis_header.hash()]];
+    for _ in 0..LAYERS {
+        let parents = layers.last().unwrap().clone();
+        let headers: Vec<_> = (0..WIDTH)
+            .map(|_| new_test_header(parents.clone()))
+            .collect();
+        dag.commit_headers_bulk(&headers).unwrap();
+        layers.push(headers.iter().map(|header| header.hash()).collect());
+    }
+    layers
+}
+
+fn main() {
+    let db_tempdir = tempfile::tempdir().unwrap();
+    let config = FlexiDagStorageConfig::create_with_params(1, 0, CACHE_SIZE);
+    let genesis = DagGenesisConfig::dev();
+    let layers = build_wide_dag(db_tempdir.path(), config.clone(), &genesis);
+    let blocks: Vec<_> = layers.iter().flatten().copied().collect();
+
+    // The dag is reopened so that both paths start with cold caches
+    let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+    let ghostdag_store = storage.ghost_dag_store.clone();
+    let manager = DbGhostdagManager::new(
+        layers[0][0],
+        genesis.k,
+        ghostdag_store.clone(),
+        storage.relations_store.clone(),
+        storage.header_store.clone(),
+        MTReachabilityService::new(Arc::new(RwLock::new(storage.reachability_store.clone()))),
+    );
+    let full_sortable = |hash: Hash| SortableBlock {
+        hash,
+        blue_work: ghostdag_store.get_data(hash).unwrap().blue_work,
+    };
+    println!(
+        "wide dag of {} layers of {} blocks, k = {}",
+        LAYERS, WIDTH, genesis.k
+    );
+
+    let full = measure(&storage, || {
+        for layer in layers.iter() {
+            layer.iter().copied().map(full_sortable).max().unwrap();
+        }
+    });
+    let compact = measure(&storage, || {
+        for layer in layers.iter() {
+            manager.find_selected_parent(layer.iter().copied());
+        }
+    });
+    report("find_selected_parent", full, compact);
+
+    let full = measure(&storage, || {
+        let mut sorted: Vec<_> = blocks.iter().copied().map(full_sortable).collect();
+        sorted.sort();
+    });
+    let compact = measure(&storage, || {
+        manager.sort_blocks(blocks.iter().copied());
+    });
+    report("sort_blocks", full, compact);
+
+    let tip = manager.find_selected_parent(layers.last().unwrap().iter().copied());
+    let full = measure(&storage, || {
+        let mut current = tip;
+        while current != layers[0][0] {
+            current = ghostdag_store.get_data(current).unwrap().selected_parent;
+        }
+    });
+    let compact = measure(&storage, || {
+        let mut current = tip;
+        while current != layers[0][0] {
+            current = ghostdag_store.get_selected_parent(current).unwrap();
+        }
+    });
+    report("selected chain walk", full, compact);
+
+    // The coloring of a new block merging the whole last layer
+    let coloring = measure(&storage, || {
+        manager.ghostdag(layers.last().unwrap());
+    });
+    println!(
+        "{:<24} {:>7} records {:>10} bytes {:>8.2?}",
+        "ghostdag", coloring.deserializations, coloring.bytes_deserialized, coloring.elapsed
+    );
+}
@@ -1,4 +1,9 @@
+pub mod memory_dag;
 pub mod mergeset;
 pub mod protocol;
+pub mod reference;
+pub mod simulator;
 
+#[cfg(test)]
+mod tests;
 mod util;
@@ -0,0 +1,123 @@
+//!
+//! An in-memory DAG running GHOSTDAG, for tests and simulations
+//!
+use crate::protocol::GhostdagManager;
+use consensus_types::{
+    blockhash::{BlockHashes, KType, ORIGIN},
+    ghostdata::GhostdagData,
+    header::CompactHeaderData,
+};
+use database::consensus::{
+    GhostdagStore, GhostdagStoreReader, MemoryGhostdagStore, MemoryHeaderStore,
+    MemoryReachabilityStore, MemoryRelationsStore, RelationsStore,
+};
+use parking_lot::RwLock;
+use reachability::{inquirer, reachability_service::MTReachabilityService};
+use starcoin_crypto::HashValue as Hash;
+use std::sync::Arc;
+
+pub type MemoryGhostdagManager = GhostdagManager<
+    MemoryGhostdagStore,
+    MemoryRelationsStore,
+    MTReachabilityService<MemoryReachabilityStore>,
+    MemoryHeaderStore,
+>;
+
+/// A DAG kept entirely in memory. Blocks must be added in topological order.
+pub struct MemoryDag {
+    genesis: Hash,
+    manager: MemoryGhostdagManager,
+    reachability_store: Arc<RwLock<MemoryReachabilityStore>>,
+    reindex_count: u64,
+}
+
+impl MemoryDag {
+    pub fn new(genesis: Hash, k: KType) -> Self {
+        let origin = Hash::new(ORIGIN);
+        let reachability_store = Arc::new(RwLock::new(MemoryReachabilityStore::new()));
+        inquirer::init(&mut *reachability_store.write()).unwrap();
+        let mut manager = MemoryGhostdagManager::new(
+            genesis,
+            k,
+            MemoryGhostdagStore::new(),
+            MemoryRelationsStore::new(),
+            MemoryHeaderStore::new(),
+            MTReachabilityService::new(reachability_store.clone()),
+        );
+        manager
+            .relations_store
+            .insert(origin, BlockHashes::new(vec![]))
+            .unwrap();
+
+        let mut dag = Self {
+            genesis,
+            manager,
+            reachability_store,
+            reindex_count: 0,
+        };
+        let genesis_data = dag.manager.genesis_ghostdag_data();
+        dag.commit(genesis, &[origin], genesis_data);
+        dag
+    }
+
+    /// Runs GHOSTDAG over `parents` and stores `hash` with the resulting data
+    pub fn add_block(&mut self, hash: Hash, parents: &[Hash]) -> Arc<GhostdagData> {
+        let ghostdag_data = self.manager.ghostdag(parents);
+        self.commit(hash, parents, ghostdag_data)
+    }
+
+    fn commit(&mut self, hash: Hash, parents: &[Hash], data: GhostdagData) -> Arc<GhostdagData> {
+        let data = Arc::new(data);
+        self.manager
+            .headers_store
+            .insert_compact(
+                hash,
+                CompactHeaderData {
+                    timestamp: 0,
+                    difficulty: 1u64.into(),
+                },
+            )
+            .unwrap();
+        self.manager
+            .ghostdag_store
+            .insert(hash, data.clone())
+            .unwrap();
+
+        let mut reachability_store = self.reachability_store.write();
+        if inquirer::requires_reindex(&*reachability_store, data.selected_parent).unwrap() {
+            self.reindex_count += 1;
+        }
+        let mut mergeset = data.unordered_mergeset_without_selected_parent();
+        inquirer::add_block(
+            &mut *reachability_store,
+            hash,
+            data.selected_parent,
+            &mut mergeset,
+        )
+        .unwrap();
+        drop(reachability_store);
+
+        self.manager
+            .relations_store
+            .insert(hash, BlockHashes::new(parents.to_vec()))
+            .unwrap();
+        data
+    }
+
+    pub fn genesis(&self) -> Hash {
+        self.genesis
+    }
+
+    pub fn manager(&self) -> &MemoryGhostdagManager {
+        &self.manager
+    }
+
+    pub fn ghostdag_data(&self, hash: Hash) -> Arc<GhostdagData> {
+        self.manager.ghostdag_store.get_data(hash).unwrap()
+    }
+
+    /// The number of reachability reindexes triggered so far
+    pub fn reindex_count(&self) -> u64 {
+        self.reindex_count
+    }
+}
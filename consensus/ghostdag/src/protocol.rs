@@ -57,6 +57,10 @@ impl<
         }
     }
 
+    pub fn k(&self) -> KType {
+        self.k
+    }
+
     pub fn genesis_ghostdag_data(&self) -> GhostdagData {
         GhostdagData::new(
             0,
@@ -80,17 +84,26 @@ impl<
     }
 
     pub fn find_selected_parent(&self, parents: impl IntoIterator<Item = Hash>) -> Hash {
-        parents
+        self.sortable_blocks(parents.into_iter().collect())
             .into_iter()
-            .map(|parent| SortableBlock {
-                hash: parent,
-                blue_work: self.ghostdag_store.get_blue_work(parent).unwrap(),
-            })
             .max()
             .unwrap()
             .hash
     }
 
+    /// Attaches their blue work to `blocks`, read in one batch from the compact data
+    fn sortable_blocks(&self, blocks: Vec<Hash>) -> Vec<SortableBlock> {
+        let compact_data = self.ghostdag_store.get_compact_data_many(&blocks).unwrap();
+        blocks
+            .into_iter()
+            .zip(compact_data)
+            .map(|(hash, data)| SortableBlock {
+                hash,
+                blue_work: data.blue_work,
+            })
+            .collect()
+    }
+
     /// Runs the GHOSTDAG protocol and calculates the block GhostdagData by the given parents.
     /// The function calculates mergeset blues by iterating over the blocks in
     /// the anticone of the new block selected parent (which is the parent with the
@@ -201,13 +214,18 @@ impl<
             *candidate_blue_anticone_size += 1;
             if *candidate_blue_anticone_size > self.k {
                 // k-cluster violation: The candidate's blue anticone exceeded k
-                return ColoringState::Red;
+                return ColoringState::Red(RedReason::CandidateAnticoneExceeded {
+                    chain_block: chain_block.hash,
+                });
             }
 
             if *candidate_blues_anticone_sizes.get(&block).unwrap() == self.k {
                 // k-cluster violation: A block in candidate's blue anticone already
                 // has k blue blocks in its own anticone
-                return ColoringState::Red;
+                return ColoringState::Red(RedReason::BlueAnticoneFull {
+                    chain_block: chain_block.hash,
+                    blue: block,
+                });
             }
 
             // This is a sanity check that validates that a blue
@@ -237,14 +255,13 @@ impl<
                 panic!("block {block} is not in blue set of the given context");
             }
 
-            current_blues_anticone_sizes = self
+            // Both fields come from a single decoding of the chain block data
+            let data = self
                 .ghostdag_store
-                .get_blues_anticone_sizes(current_selected_parent)
-                .unwrap();
-            current_selected_parent = self
-                .ghostdag_store
-                .get_selected_parent(current_selected_parent)
+                .get_data(current_selected_parent)
                 .unwrap();
+            current_blues_anticone_sizes = HashKTypeMap::clone(&data.blues_anticone_sizes);
+            current_selected_parent = data.selected_parent;
         }
     }
 
@@ -256,7 +273,7 @@ impl<
         // The maximum length of new_block_data.mergeset_blues can be K+1 because
         // it contains the selected parent.
         if new_block_data.mergeset_blues.len() as KType == self.k + 1 {
-            return ColoringOutput::Red;
+            return ColoringOutput::Red(RedReason::MergesetBluesFull);
         }
 
         let mut candidate_blues_anticone_sizes: BlockHashMap<KType> =
@@ -287,7 +304,7 @@ impl<
                         candidate_blues_anticone_sizes,
                     )
                 }
-                ColoringState::Red => return ColoringOutput::Red,
+                ColoringState::Red(reason) => return ColoringOutput::Red(reason),
                 ColoringState::Pending => (), // continue looping
             }
 
@@ -302,13 +319,46 @@ impl<
         }
     }
 
+    /// A diagnostic variant of `check_blue_candidate`: replays the coloring of the mergeset
+    /// of a block with the given parents, and explains the color given to `candidate`.
+    /// Returns `None` if `candidate` is not in the mergeset.
+    pub fn explain_coloring(
+        &self,
+        parents: &[Hash],
+        candidate: Hash,
+    ) -> Option<ColoringExplanation> {
+        let selected_parent = self.find_selected_parent(parents.iter().copied());
+        if candidate == selected_parent {
+            return Some(ColoringExplanation::Blue {
+                blue_anticone_size: 0,
+            });
+        }
+        let mut new_block_data = GhostdagData::new_with_selected_parent(selected_parent, self.k);
+        for blue_candidate in
+            self.ordered_mergeset_without_selected_parent(selected_parent, parents)
+        {
+            let coloring = self.check_blue_candidate(&new_block_data, blue_candidate);
+            if blue_candidate == candidate {
+                return Some(match coloring {
+                    ColoringOutput::Blue(blue_anticone_size, _) => {
+                        ColoringExplanation::Blue { blue_anticone_size }
+                    }
+                    ColoringOutput::Red(reason) => ColoringExplanation::Red(reason),
+                });
+            }
+            if let ColoringOutput::Blue(blue_anticone_size, blues_anticone_sizes) = coloring {
+                new_block_data.add_blue(blue_candidate, blue_anticone_size, &blues_anticone_sizes);
+            } else {
+                new_block_data.add_red(blue_candidate);
+            }
+        }
+        None
+    }
+
     pub fn sort_blocks(&self, blocks: impl IntoIterator<Item = Hash>) -> Vec<Hash> {
-        let mut sorted_blocks: Vec<Hash> = blocks.into_iter().collect();
-        sorted_blocks.sort_by_cached_key(|block| SortableBlock {
-            hash: *block,
-            blue_work: self.ghostdag_store.get_blue_work(*block).unwrap(),
-        });
-        sorted_blocks
+        let mut sorted_blocks = self.sortable_blocks(blocks.into_iter().collect());
+        sorted_blocks.sort();
+        sorted_blocks.into_iter().map(|block| block.hash).collect()
     }
 }
 
@@ -321,12 +371,35 @@ struct ChainBlock<'a> {
 /// Represents the intermediate GHOSTDAG coloring state for the current candidate
 enum ColoringState {
     Blue,
-    Red,
+    Red(RedReason),
     Pending,
 }
 
 /// Represents the final output of GHOSTDAG coloring for the current candidate
 enum ColoringOutput {
     Blue(KType, BlockHashMap<KType>), // (blue anticone size, map of blue anticone sizes for each affected blue)
-    Red,
+    Red(RedReason),
+}
+
+/// The k-cluster violation which got a candidate colored red
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RedReason {
+    /// The mergeset already holds k + 1 blues, the selected parent included
+    MergesetBluesFull,
+    /// The blue anticone of the candidate exceeded k while inspecting the mergeset blues
+    /// of `chain_block`, `None` standing for the new block itself
+    CandidateAnticoneExceeded { chain_block: Option<Hash> },
+    /// `blue`, a mergeset blue of `chain_block` in the anticone of the candidate,
+    /// already has k blues in its own anticone
+    BlueAnticoneFull {
+        chain_block: Option<Hash>,
+        blue: Hash,
+    },
+}
+
+/// Explains the color GHOSTDAG gives to a mergeset block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColoringExplanation {
+    Blue { blue_anticone_size: KType },
+    Red(RedReason),
 }
@@ -0,0 +1,158 @@
+//!
+//! A naive reference implementation of GHOSTDAG, to be used as an oracle when testing
+//! the optimized `GhostdagManager`.
+//!
+//! Every block keeps its full past set, anticones are computed out of these sets and
+//! the k-cluster conditions are checked literally, which makes the implementation
+//! quadratic in both time and memory.
+//!
+use consensus_types::{
+    blockhash::{BlockHashMap, BlockHashSet, BlueWorkType, KType},
+    ordering::SortableBlock,
+};
+use starcoin_crypto::HashValue as Hash;
+
+/// The blue work every blue block adds, mirrors `GhostdagManager::ghostdag`
+const BLUE_WORK_PER_BLOCK: BlueWorkType = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReferenceGhostdagData {
+    pub blue_score: u64,
+    pub blue_work: BlueWorkType,
+    pub selected_parent: Hash,
+    /// The selected parent first, then the merged blues in coloring order
+    pub mergeset_blues: Vec<Hash>,
+    pub mergeset_reds: Vec<Hash>,
+}
+
+pub struct ReferenceGhostdag {
+    k: KType,
+    data: BlockHashMap<ReferenceGhostdagData>,
+    /// The past of every block, the block itself excluded
+    past: BlockHashMap<BlockHashSet>,
+    /// The blue blocks in the past of every block, from the block's worldview
+    blue_past: BlockHashMap<BlockHashSet>,
+}
+
+impl ReferenceGhostdag {
+    pub fn new(genesis: Hash, origin: Hash, k: KType) -> Self {
+        let mut reference = Self {
+            k,
+            data: BlockHashMap::new(),
+            past: BlockHashMap::new(),
+            blue_past: BlockHashMap::new(),
+        };
+        reference.data.insert(
+            genesis,
+            ReferenceGhostdagData {
+                blue_score: 0,
+                blue_work: 0,
+                selected_parent: origin,
+                mergeset_blues: vec![],
+                mergeset_reds: vec![],
+            },
+        );
+        reference.past.insert(genesis, BlockHashSet::new());
+        reference.blue_past.insert(genesis, BlockHashSet::new());
+        reference
+    }
+
+    pub fn get(&self, hash: Hash) -> &ReferenceGhostdagData {
+        &self.data[&hash]
+    }
+
+    pub fn past(&self, hash: Hash) -> &BlockHashSet {
+        &self.past[&hash]
+    }
+
+    pub fn is_in_past(&self, block: Hash, of: Hash) -> bool {
+        self.past[&of].contains(&block)
+    }
+
+    pub fn is_in_anticone(&self, a: Hash, b: Hash) -> bool {
+        a != b && !self.is_in_past(a, b) && !self.is_in_past(b, a)
+    }
+
+    fn sortable(&self, hash: Hash) -> SortableBlock {
+        SortableBlock::new(hash, self.data[&hash].blue_work)
+    }
+
+    /// Colors a new block with the given parents, which must all be known already
+    pub fn add_block(&mut self, hash: Hash, parents: &[Hash]) -> &ReferenceGhostdagData {
+        let mut past = BlockHashSet::new();
+        for parent in parents {
+            past.insert(*parent);
+            past.extend(self.past[parent].iter().copied());
+        }
+
+        let selected_parent = parents
+            .iter()
+            .copied()
+            .max_by_key(|parent| self.sortable(*parent))
+            .expect("a block must have parents");
+        let selected_parent_past = &self.past[&selected_parent];
+        let mut mergeset: Vec<Hash> = past
+            .iter()
+            .copied()
+            .filter(|block| *block != selected_parent && !selected_parent_past.contains(block))
+            .collect();
+        mergeset.sort_by_cached_key(|block| self.sortable(*block));
+
+        let mut blue_past = self.blue_past[&selected_parent].clone();
+        blue_past.insert(selected_parent);
+        let mut mergeset_blues = vec![selected_parent];
+        let mut mergeset_reds = vec![];
+        for candidate in mergeset {
+            if self.is_blue_candidate(&blue_past, mergeset_blues.len(), candidate) {
+                mergeset_blues.push(candidate);
+                blue_past.insert(candidate);
+            } else {
+                mergeset_reds.push(candidate);
+            }
+        }
+
+        let selected_parent_data = &self.data[&selected_parent];
+        let data = ReferenceGhostdagData {
+            blue_score: selected_parent_data.blue_score + mergeset_blues.len() as u64,
+            blue_work: selected_parent_data.blue_work
+                + BLUE_WORK_PER_BLOCK * mergeset_blues.len() as BlueWorkType,
+            selected_parent,
+            mergeset_blues,
+            mergeset_reds,
+        };
+        self.past.insert(hash, past);
+        self.blue_past.insert(hash, blue_past);
+        self.data.insert(hash, data);
+        &self.data[&hash]
+    }
+
+    /// Checks the k-cluster conditions for `candidate` joining the blue set `blues`
+    fn is_blue_candidate(
+        &self,
+        blues: &BlockHashSet,
+        mergeset_blues: usize,
+        candidate: Hash,
+    ) -> bool {
+        // At most k blues can be merged besides the selected parent
+        if mergeset_blues as KType > self.k {
+            return false;
+        }
+        let anticone_blues: Vec<Hash> = blues
+            .iter()
+            .copied()
+            .filter(|blue| self.is_in_anticone(*blue, candidate))
+            .collect();
+        // The anticone of the candidate must not exceed k blues
+        if anticone_blues.len() as KType > self.k {
+            return false;
+        }
+        // Nor may any blue end up with more than k blues in its anticone
+        anticone_blues.iter().all(|blue| {
+            let blue_anticone_size = blues
+                .iter()
+                .filter(|other| self.is_in_anticone(**other, *blue))
+                .count();
+            (blue_anticone_size as KType) < self.k
+        })
+    }
+}
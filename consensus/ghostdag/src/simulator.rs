@@ -0,0 +1,296 @@
+//!
+//! A DAG topology simulator, meant to help choosing the GHOSTDAG K parameter.
+//!
+//! Blocks are mined by a set of miners at a given overall rate (mining times follow a
+//! Poisson process). Every block reaches the other miners after a random network delay,
+//! and each miner references all the tips of its own view when mining.
+//!
+use crate::memory_dag::MemoryDag;
+use consensus_types::blockhash::{BlockHashSet, KType};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use starcoin_crypto::HashValue as Hash;
+use std::fmt;
+
+/// The distribution of the network delay between two miners, in seconds
+#[derive(Clone, Copy, Debug)]
+pub enum DelayDistribution {
+    Constant(f64),
+    Uniform { min: f64, max: f64 },
+    Exponential { mean: f64 },
+}
+
+impl DelayDistribution {
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match *self {
+            DelayDistribution::Constant(delay) => delay,
+            DelayDistribution::Uniform { min, max } => {
+                if max > min {
+                    rng.gen_range(min..max)
+                } else {
+                    min
+                }
+            }
+            DelayDistribution::Exponential { mean } => sample_exponential(rng, mean),
+        }
+    }
+}
+
+fn sample_exponential(rng: &mut impl Rng, mean: f64) -> f64 {
+    // Inverse transform sampling, `1 - u` lies in (0, 1] so the log is always finite
+    -mean * (1.0 - rng.gen::<f64>()).ln()
+}
+
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    /// The number of blocks per second, all miners together
+    pub block_rate: f64,
+    pub delay: DelayDistribution,
+    pub miners: usize,
+    /// The number of blocks to mine, genesis excluded
+    pub blocks: usize,
+    pub seed: u64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            block_rate: 1.0,
+            delay: DelayDistribution::Exponential { mean: 0.5 },
+            miners: 8,
+            blocks: 1000,
+            seed: 0,
+        }
+    }
+}
+
+/// A block of a generated DAG, `timestamp` is the mining time in seconds
+#[derive(Clone, Debug)]
+pub struct SimulatedBlock {
+    pub hash: Hash,
+    pub parents: Vec<Hash>,
+    pub timestamp: f64,
+}
+
+/// The generated DAG, blocks are sorted by mining time, which is a topological order
+#[derive(Clone, Debug)]
+pub struct SimulatedDag {
+    pub genesis: Hash,
+    pub blocks: Vec<SimulatedBlock>,
+}
+
+impl SimulatedDag {
+    /// The blocks which no other block references
+    pub fn tips(&self) -> Vec<Hash> {
+        let referenced: BlockHashSet = self
+            .blocks
+            .iter()
+            .flat_map(|block| block.parents.iter().copied())
+            .collect();
+        let tips: Vec<Hash> = self
+            .blocks
+            .iter()
+            .map(|block| block.hash)
+            .filter(|hash| !referenced.contains(hash))
+            .collect();
+        if tips.is_empty() {
+            vec![self.genesis]
+        } else {
+            tips
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.blocks.last().map_or(0.0, |block| block.timestamp)
+    }
+}
+
+/// Generates a DAG topology according to `config`. The topology does not depend on K.
+pub fn generate_dag(config: &SimulationConfig) -> SimulatedDag {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let miners = config.miners.max(1);
+    let genesis = Hash::from_u64(1);
+
+    // For every block, the time it becomes visible to every miner
+    let mut arrivals: Vec<Vec<f64>> = vec![vec![0.0; miners]];
+    let mut parents_indices: Vec<Vec<usize>> = vec![vec![]];
+    let mut hashes = vec![genesis];
+    let mut blocks = Vec::with_capacity(config.blocks);
+    let mut now = 0.0;
+
+    for index in 1..=config.blocks {
+        now += sample_exponential(&mut rng, 1.0 / config.block_rate);
+        let miner = rng.gen_range(0..miners);
+
+        // The tips of the miner's view
+        let mut has_visible_child = vec![false; index];
+        let visible: Vec<usize> = (0..index)
+            .filter(|&block| arrivals[block][miner] <= now)
+            .collect();
+        for &block in visible.iter() {
+            for &parent in parents_indices[block].iter() {
+                has_visible_child[parent] = true;
+            }
+        }
+        let parents: Vec<usize> = visible
+            .into_iter()
+            .filter(|&block| !has_visible_child[block])
+            .collect();
+
+        // A block can only be seen once its parents were
+        let arrival = (0..miners)
+            .map(|receiver| {
+                let received = if receiver == miner {
+                    now
+                } else {
+                    now + config.delay.sample(&mut rng)
+                };
+                parents
+                    .iter()
+                    .map(|&parent| arrivals[parent][receiver])
+                    .fold(received, f64::max)
+            })
+            .collect();
+
+        let hash = Hash::from_u64(index as u64 + 1);
+        blocks.push(SimulatedBlock {
+            hash,
+            parents: parents.iter().map(|&parent| hashes[parent]).collect(),
+            timestamp: now,
+        });
+        arrivals.push(arrival);
+        parents_indices.push(parents);
+        hashes.push(hash);
+    }
+
+    SimulatedDag { genesis, blocks }
+}
+
+/// GHOSTDAG statistics of a simulated DAG for a given K
+#[derive(Clone, Debug)]
+pub struct SimulationReport {
+    pub k: KType,
+    pub blocks: usize,
+    /// The ratio of red blocks among the blocks merged by the virtual selected chain
+    pub red_ratio: f64,
+    pub mean_mergeset_size: f64,
+    pub max_mergeset_size: usize,
+    /// The blue score of the virtual block
+    pub blue_score: u64,
+    /// The blue score gained per second
+    pub blue_score_rate: f64,
+    pub reindex_count: u64,
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "k={} blocks={} red_ratio={:.4} mergeset_size(mean={:.2}, max={}) blue_score={} ({:.3}/s) reindexes={}",
+            self.k,
+            self.blocks,
+            self.red_ratio,
+            self.mean_mergeset_size,
+            self.max_mergeset_size,
+            self.blue_score,
+            self.blue_score_rate,
+            self.reindex_count
+        )
+    }
+}
+
+/// Runs GHOSTDAG with K = `k` over a generated DAG
+pub fn run_ghostdag(dag: &SimulatedDag, k: KType) -> SimulationReport {
+    let mut memory_dag = MemoryDag::new(dag.genesis, k);
+    let mut mergeset_sizes_sum = 0;
+    let mut max_mergeset_size = 0;
+    for block in dag.blocks.iter() {
+        let data = memory_dag.add_block(block.hash, &block.parents);
+        mergeset_sizes_sum += data.mergeset_size();
+        max_mergeset_size = max_mergeset_size.max(data.mergeset_size());
+    }
+
+    // Walk down the selected chain of the virtual block, counting the merged blocks
+    let virtual_data = memory_dag.manager().ghostdag(&dag.tips());
+    let (mut blues, mut reds) = (0, 0);
+    let mut current = virtual_data.clone();
+    loop {
+        blues += current.mergeset_blues.len();
+        reds += current.mergeset_reds.len();
+        if current.selected_parent == dag.genesis {
+            break;
+        }
+        current = (*memory_dag.ghostdag_data(current.selected_parent)).clone();
+    }
+
+    let duration = dag.duration();
+    SimulationReport {
+        k,
+        blocks: dag.blocks.len(),
+        red_ratio: if blues + reds == 0 {
+            0.0
+        } else {
+            reds as f64 / (blues + reds) as f64
+        },
+        mean_mergeset_size: if dag.blocks.is_empty() {
+            0.0
+        } else {
+            mergeset_sizes_sum as f64 / dag.blocks.len() as f64
+        },
+        max_mergeset_size,
+        blue_score: virtual_data.blue_score,
+        blue_score_rate: if duration > 0.0 {
+            virtual_data.blue_score as f64 / duration
+        } else {
+            0.0
+        },
+        reindex_count: memory_dag.reindex_count(),
+    }
+}
+
+/// Generates a single DAG according to `config` and runs GHOSTDAG over it with every K
+pub fn simulate(config: &SimulationConfig, k_values: &[KType]) -> Vec<SimulationReport> {
+    let dag = generate_dag(config);
+    k_values.iter().map(|&k| run_ghostdag(&dag, k)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate_chain() {
+        // A single miner always sees its own blocks, so the DAG is a chain
+        let config = SimulationConfig {
+            miners: 1,
+            blocks: 100,
+            ..Default::default()
+        };
+        let dag = generate_dag(&config);
+        assert!(dag.blocks.iter().all(|block| block.parents.len() == 1));
+        assert_eq!(dag.tips().len(), 1);
+
+        let report = run_ghostdag(&dag, 0);
+        assert_eq!(report.red_ratio, 0.0);
+        assert_eq!(report.max_mergeset_size, 1);
+        assert_eq!(report.blue_score, 101);
+    }
+
+    #[test]
+    fn test_simulate_wide_dag() {
+        let config = SimulationConfig {
+            block_rate: 10.0,
+            delay: DelayDistribution::Constant(1.0),
+            miners: 10,
+            blocks: 300,
+            seed: 7,
+        };
+        let reports = simulate(&config, &[0, 32]);
+        for report in reports.iter() {
+            println!("{}", report);
+        }
+        assert!(reports[0].red_ratio > 0.0);
+        assert!(reports[1].red_ratio < reports[0].red_ratio);
+        assert!(reports[1].mean_mergeset_size > 1.0);
+        assert!(reports[1].blue_score > reports[0].blue_score);
+    }
+}
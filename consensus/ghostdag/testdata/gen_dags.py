@@ -0,0 +1,139 @@
+#!/usr/bin/env python3
+#
+# Generates the DAG test vectors under `dags/`.
+#
+# These are NOT kaspad's upstream test vectors: they only reuse the JSON format of
+# kaspad's ghostdag tests. The expectations are computed here with a brute-force
+# implementation of the GHOSTDAG definition, which keeps the full past of every block,
+# using the same blue work per block (1024) and the same (blue work, hash) tie-breaking
+# as `GhostdagManager`.
+#
+# Usage: python3 gen_dags.py, from any directory. The output is deterministic.
+#
+import json
+import os
+import random
+
+DAGS_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), "dags")
+BLUE_WORK_PER_BLOCK = 1024
+
+
+def to_hash(block_id):
+    """Block ids are turned into hashes by zero-padding their bytes, as kaspad does"""
+    return block_id.encode().ljust(32, b"\0")
+
+
+def ghostdag(k, genesis, blocks):
+    past = {genesis: set()}
+    blue_past = {genesis: set()}
+    blue_work = {genesis: 0}
+    blue_score = {genesis: 0}
+
+    def in_anticone(a, b):
+        return a != b and a not in past[b] and b not in past[a]
+
+    def sort_key(block):
+        return (blue_work[block], to_hash(block))
+
+    expectations = []
+    for block, parents in blocks:
+        block_past = set()
+        for parent in parents:
+            block_past.add(parent)
+            block_past |= past[parent]
+        selected_parent = max(parents, key=sort_key)
+        mergeset = sorted(
+            (b for b in block_past if b != selected_parent and b not in past[selected_parent]),
+            key=sort_key,
+        )
+
+        blues = [selected_parent]
+        reds = []
+        blues_past = set(blue_past[selected_parent])
+        blues_past.add(selected_parent)
+        for candidate in mergeset:
+            # The candidate may not have more than k blues in its anticone, and no blue
+            # may get more than k blues in its anticone by coloring the candidate blue
+            is_blue = len(blues) <= k
+            if is_blue:
+                blue_anticone = [b for b in blues_past if in_anticone(b, candidate)]
+                is_blue = len(blue_anticone) <= k and all(
+                    sum(1 for other in blues_past if in_anticone(other, blue)) < k
+                    for blue in blue_anticone
+                )
+            if is_blue:
+                blues.append(candidate)
+                blues_past.add(candidate)
+            else:
+                reds.append(candidate)
+
+        past[block] = block_past
+        blue_past[block] = blues_past
+        blue_score[block] = blue_score[selected_parent] + len(blues)
+        blue_work[block] = blue_work[selected_parent] + BLUE_WORK_PER_BLOCK * len(blues)
+        expectations.append(
+            {
+                "ID": block,
+                "ExpectedScore": blue_score[block],
+                "ExpectedSelectedParent": selected_parent,
+                "ExpectedReds": reds,
+                "ExpectedBlues": blues,
+                "Parents": parents,
+            }
+        )
+    return expectations
+
+
+def write(name, k, genesis, blocks):
+    vector = {"K": k, "GenesisID": genesis, "Blocks": ghostdag(k, genesis, blocks)}
+    with open(os.path.join(DAGS_DIR, name), "w") as f:
+        json.dump(vector, f, indent=2)
+        f.write("\n")
+
+
+def random_dag(seed, blocks, window, max_parents):
+    """Each block references up to `max_parents` blocks among the `window` latest ones,
+    the parents in the past of another parent being dropped"""
+    rng = random.Random(seed)
+    ids = ["0"]
+    past = {"0": set()}
+    dag = []
+    for i in range(1, blocks + 1):
+        candidates = ids[-window:]
+        chosen = rng.sample(candidates, rng.randint(1, min(max_parents, len(candidates))))
+        parents = [p for p in chosen if not any(p in past[other] for other in chosen)]
+        block = str(i)
+        block_past = set()
+        for parent in parents:
+            block_past.add(parent)
+            block_past |= past[parent]
+        past[block] = block_past
+        ids.append(block)
+        dag.append((block, parents))
+    return dag
+
+
+# The shape used by the sync tests of smolstc
+SYNC_SHAPE = [
+    ("B", ["A"]),
+    ("C", ["A"]),
+    ("D", ["A"]),
+    ("E", ["A"]),
+    ("F", ["B", "C"]),
+    ("H", ["E", "D", "C"]),
+    ("I", ["E"]),
+    ("J", ["F", "H"]),
+    ("K", ["B", "H", "I"]),
+    ("L", ["D", "I"]),
+    ("M", ["F", "K"]),
+    ("P", ["J", "M"]),
+    ("V", ["K", "C", "L"]),
+]
+
+if __name__ == "__main__":
+    os.makedirs(DAGS_DIR, exist_ok=True)
+    write("sync-shape-k1.json", 1, "A", SYNC_SHAPE)
+    write("sync-shape-k3.json", 3, "A", SYNC_SHAPE)
+    write("random-k0.json", 0, "0", random_dag(1, 30, 3, 2))
+    write("random-k3.json", 3, "0", random_dag(2, 60, 8, 4))
+    write("random-k10.json", 10, "0", random_dag(3, 120, 20, 6))
@@ -1,6 +1,10 @@
-use super::{tree::*, *};
+pub use super::tree::find_common_tree_ancestor;
+use super::{extensions::ReachabilityStoreIntervalExtensions, tree::*, *};
 use consensus_types::{blockhash, interval::Interval, perf};
-use database::consensus::{ReachabilityStore, ReachabilityStoreReader};
+use database::{
+    consensus::{ReachabilityStore, ReachabilityStoreReader},
+    prelude::HashList,
+};
 use starcoin_crypto::HashValue as Hash;
 
 /// Init the reachability store to match the state required by the algorithmic layer.
@@ -76,11 +80,13 @@ fn insert_to_future_covering_set(
     merged_block: Hash,
     new_block: Hash,
 ) -> Result<()> {
-    match binary_search_descendant(
+    // The list borrows the store, so the search completes before the store is written
+    let search = binary_search_descendant(
         store,
-        store.get_future_covering_set(merged_block)?.as_slice(),
+        &store.get_future_covering_list(merged_block)?,
         new_block,
-    )? {
+    )?;
+    match search {
         // We expect the query to not succeed, and to only return the correct insertion index.
         // The existences of a `future covering item` (`FCI`) which is a chain ancestor of `new_block`
         // contradicts `merged_block ∈ mergeset(new_block)`. Similarly, the existence of an FCI
@@ -93,6 +99,60 @@ fn insert_to_future_covering_set(
     }
 }
 
+/// Deletes a block from the DAG reachability data structures. `block` must have no tree
+/// children left and `mergeset_iterator` must yield its mergeset, as given to `add_block`,
+/// hence blocks are expected to be deleted in reverse topological order.
+pub fn delete_block(
+    store: &mut (impl ReachabilityStore + ?Sized),
+    block: Hash,
+    mergeset_iterator: HashIterator,
+) -> Result<()> {
+    if !store.get_children(block)?.is_empty() {
+        return Err(ReachabilityError::DataInconsistency);
+    }
+    let interval = store.get_interval(block)?;
+    let parent = store.get_parent(block)?;
+    let siblings = store.get_children(parent)?;
+    let index = siblings
+        .iter()
+        .position(|sibling| *sibling == block)
+        .ok_or(ReachabilityError::DataInconsistency)?;
+
+    // Give the interval of the block back to an adjacent sibling, which keeps the sibling
+    // intervals consecutive. Without siblings, the parent owns the whole range already.
+    if index > 0 {
+        let sibling = siblings[index - 1];
+        let sibling_interval = store.get_interval(sibling)?;
+        store.set_interval(sibling, sibling_interval.increase_end(interval.size()))?;
+    } else if index + 1 < siblings.len() {
+        let sibling = siblings[index + 1];
+        let sibling_interval = store.get_interval(sibling)?;
+        store.set_interval(sibling, sibling_interval.decrease_start(interval.size()))?;
+    }
+    store.remove_child(parent, block)?;
+
+    for merged_block in mergeset_iterator {
+        if store.has(merged_block)? {
+            store.remove_future_covering_item(merged_block, block)?;
+        }
+    }
+
+    if store.get_reindex_root()? == block {
+        store.set_reindex_root(parent)?;
+    }
+    store.delete(block)?;
+    Ok(())
+}
+
+/// Returns whether adding a new tree child to `block` would trigger a reindexing,
+/// that is, whether `block` has no interval capacity left for new children.
+pub fn requires_reindex(
+    store: &(impl ReachabilityStoreReader + ?Sized),
+    block: Hash,
+) -> Result<bool> {
+    Ok(store.interval_remaining_after(block)?.is_empty())
+}
+
 /// Hint to the reachability algorithm that `hint` is a candidate to become
 /// the `virtual selected parent` (`VSP`). This might affect internal reachability heuristics such
 /// as moving the reindex point. The consensus runtime is expected to call this function
@@ -147,11 +207,7 @@ pub fn is_dag_ancestor_of(
     }
     // Otherwise, use previously registered future blocks to complete the
     // DAG reachability test
-    match binary_search_descendant(
-        store,
-        store.get_future_covering_set(this)?.as_slice(),
-        queried,
-    )? {
+    match binary_search_descendant(store, &store.get_future_covering_list(this)?, queried)? {
         SearchOutput::Found(_, _) => Ok(true),
         SearchOutput::NotFound(_) => Ok(false),
     }
@@ -184,7 +240,7 @@ pub(super) fn get_next_chain_ancestor_unchecked(
     descendant: Hash,
     ancestor: Hash,
 ) -> Result<Hash> {
-    match binary_search_descendant(store, store.get_children(ancestor)?.as_slice(), descendant)? {
+    match binary_search_descendant(store, &store.get_children_list(ancestor)?, descendant)? {
         SearchOutput::Found(hash, _) => Ok(hash),
         SearchOutput::NotFound(_) => Err(ReachabilityError::BadQuery),
     }
@@ -197,7 +253,7 @@ enum SearchOutput {
 
 fn binary_search_descendant(
     store: &(impl ReachabilityStoreReader + ?Sized),
-    ordered_hashes: &[Hash],
+    ordered_hashes: &HashList,
     descendant: Hash,
 ) -> Result<SearchOutput> {
     if cfg!(debug_assertions) {
@@ -211,13 +267,13 @@ fn binary_search_descendant(
     // We use an `unwrap` here since otherwise we need to implement `binary_search`
     // ourselves, which is not worth the effort given that this would be an unrecoverable
     // error anyhow
-    match ordered_hashes.binary_search_by_key(&point, |c| store.get_interval(*c).unwrap().start) {
-        Ok(i) => Ok(SearchOutput::Found(ordered_hashes[i], i)),
+    match ordered_hashes.binary_search_by_key(&point, |c| store.get_interval(c).unwrap().start) {
+        Ok(i) => Ok(SearchOutput::Found(ordered_hashes.hash(i), i)),
         Err(i) => {
             // `i` is where `point` was expected (i.e., point < ordered_hashes[i].interval.start),
             // so we expect `ordered_hashes[i - 1].interval` to be the only candidate to contain `point`
-            if i > 0 && is_chain_ancestor_of(store, ordered_hashes[i - 1], descendant)? {
-                Ok(SearchOutput::Found(ordered_hashes[i - 1], i - 1))
+            if i > 0 && is_chain_ancestor_of(store, ordered_hashes.hash(i - 1), descendant)? {
+                Ok(SearchOutput::Found(ordered_hashes.hash(i - 1), i - 1))
             } else {
                 Ok(SearchOutput::NotFound(i))
             }
@@ -225,10 +281,12 @@ fn binary_search_descendant(
     }
 }
 
-fn assert_hashes_ordered(store: &(impl ReachabilityStoreReader + ?Sized), ordered_hashes: &[Hash]) {
+fn assert_hashes_ordered(
+    store: &(impl ReachabilityStoreReader + ?Sized),
+    ordered_hashes: &HashList,
+) {
     let intervals: Vec<Interval> = ordered_hashes
         .iter()
-        .cloned()
         .map(|c| store.get_interval(c).unwrap())
         .collect();
     debug_assert!(intervals
@@ -332,4 +390,55 @@ mod tests {
         assert!(store.are_anticone(11, 6));
         assert!(store.are_anticone(11, 9));
     }
+
+    #[test]
+    fn test_delete_dag_blocks() {
+        // Arrange
+        let mut store = MemoryReachabilityStore::new();
+        let origin_hash = Hash::new(ORIGIN);
+        let mut builder = DagBuilder::new(&mut store);
+        builder
+            .init()
+            .add_block(DagBlock::new(1.into(), vec![origin_hash]))
+            .add_block(DagBlock::new(2.into(), vec![1.into()]))
+            .add_block(DagBlock::new(3.into(), vec![1.into()]))
+            .add_block(DagBlock::new(4.into(), vec![2.into(), 3.into()]))
+            .add_block(DagBlock::new(5.into(), vec![4.into()]))
+            .add_block(DagBlock::new(6.into(), vec![1.into()]))
+            .add_block(DagBlock::new(7.into(), vec![5.into(), 6.into()]))
+            .add_block(DagBlock::new(8.into(), vec![1.into()]))
+            .add_block(DagBlock::new(9.into(), vec![1.into()]))
+            .add_block(DagBlock::new(10.into(), vec![7.into(), 8.into(), 9.into()]))
+            .add_block(DagBlock::new(11.into(), vec![1.into()]))
+            .add_block(DagBlock::new(12.into(), vec![11.into(), 10.into()]));
+
+        // Act, delete 7 and its future in reverse topological order
+        builder
+            .delete_block(12.into())
+            .delete_block(10.into())
+            .delete_block(7.into());
+        builder.add_block(DagBlock::new(
+            13.into(),
+            vec![5.into(), 6.into(), 11.into()],
+        ));
+
+        // Assert
+        store.validate_intervals(origin_hash).unwrap();
+        for deleted in [7u64, 10, 12] {
+            assert!(!store.has(deleted.into()).unwrap());
+        }
+        assert!(store.has(store.get_reindex_root().unwrap()).unwrap());
+        assert!(store.in_past_of(5, 13));
+        assert!(store.in_past_of(6, 13));
+        assert!(store.in_past_of(11, 13));
+        assert!(store.are_anticone(6, 8));
+        assert!(store.are_anticone(9, 13));
+        for merged in [6u64, 8, 9] {
+            assert!(store
+                .get_future_covering_set(merged.into())
+                .unwrap()
+                .iter()
+                .all(|fci| store.has(*fci).unwrap()));
+        }
+    }
 }
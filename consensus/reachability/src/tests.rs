@@ -143,6 +143,15 @@ impl<'a, T: ReachabilityStore + ?Sized> DagBuilder<'a, T> {
         self
     }
 
+    /// Deletes a block which no other block of the DAG references
+    pub fn delete_block(&mut self, hash: Hash) -> &mut Self {
+        let selected_parent = self.store.get_parent(hash).unwrap();
+        let mergeset = self.mergeset(&self.map[&hash], selected_parent);
+        delete_block(self.store, hash, &mut mergeset.iter().cloned()).unwrap();
+        self.map.remove(&hash);
+        self
+    }
+
     fn mergeset(&self, block: &DagBlock, selected_parent: Hash) -> Vec<Hash> {
         let mut queue: VecDeque<Hash> = block
             .parents
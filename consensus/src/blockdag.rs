@@ -1,19 +1,31 @@
 use anyhow::bail;
 use consensus_types::{
-    blockhash::{BlockHashes, KType, ORIGIN},
+    blockhash::{BlockHashSet, BlockHashes, ChainPath, KType, ORIGIN},
+    ghostdata::GhostdagData,
     header::{ConsensusHeader, Header},
+    interval::Interval,
+    status::BlockStatus,
 };
 use database::consensus::{
-    DbGhostdagStore, DbHeadersStore, DbReachabilityStore, DbRelationsStore, GhostdagStore,
-    HeaderStore, ReachabilityStoreReader, RelationsStore, RelationsStoreReader,
+    BlockIndexStore, DbBlockIndexStore, DbGhostdagStore, DbHeadersStore, DbReachabilityStore,
+    DbRelationsStore, DbSelectedChainStore, DbStatusesStore, DbTipsStore, GhostdagStore,
+    GhostdagStoreReader, HeaderStore, ReachabilityStore, ReachabilityStoreReader, RelationsStore,
+    RelationsStoreReader, SelectedChainStore, SelectedChainStoreReader, StagingGhostdagStore,
+    StagingHeadersStore, StagingReachabilityStore, StagingRelationsStore, StatusesStore,
+    StatusesStoreReader, TipsStore, TipsStoreReader,
 };
-use database::prelude::FlexiDagStorage;
-use ghostdag::protocol::GhostdagManager;
+use database::prelude::{DbBatch, FlexiDagStorage, StoreError, StoreMetrics};
+use ghostdag::protocol::{ColoringExplanation, GhostdagManager};
 use parking_lot::RwLock;
 use reachability::{inquirer, reachability_service::MTReachabilityService};
 use starcoin_crypto::HashValue as Hash;
+use starcoin_types::U256;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::Path;
 use std::sync::Arc;
 
 pub type DbGhostdagManager = GhostdagManager<
@@ -22,26 +34,79 @@ pub type DbGhostdagManager = GhostdagManager<
     MTReachabilityService<DbReachabilityStore>,
     DbHeadersStore,
 >;
+
+/// A ghostdag manager reading the blocks staged by a bulk import on top of the dag
+type StagingGhostdagManager<'a> = GhostdagManager<
+    StagingGhostdagStore,
+    StagingRelationsStore,
+    MTReachabilityService<StagingReachabilityStore<'a>>,
+    StagingHeadersStore,
+>;
+
+/// Bounds applied when selecting the parents of a new block template
+#[derive(Clone, Copy, Debug)]
+pub struct ParentsSelectionParams {
+    /// The maximum number of parents a block may reference
+    pub max_parents: usize,
+    /// The maximum size of the mergeset of a block, selected parent included
+    pub mergeset_size_limit: u64,
+    /// The blue score depth of the merge depth root of a block. Red blocks out of the
+    /// future of the root may only be merged along with a blue block of their future
+    /// which is in the future of the root
+    pub merge_depth: u64,
+}
+
+impl Default for ParentsSelectionParams {
+    fn default() -> Self {
+        Self {
+            max_parents: 10,
+            mergeset_size_limit: 180,
+            merge_depth: 3600,
+        }
+    }
+}
+
+/// The parents a new block should reference, together with the ghostdag data
+/// the block will get once it is committed
+#[derive(Clone, Debug)]
+pub struct BlockTemplateParents {
+    pub parents: Vec<Hash>,
+    pub ghostdag_data: GhostdagData,
+}
+
 pub struct BlockDAG {
     genesis: Header,
     ghostdag_manager: DbGhostdagManager,
     relations_store: DbRelationsStore,
     reachability_store: DbReachabilityStore,
+    /// The lock of the reachability store of the ghostdag manager, taken by the writers
+    /// of the reachability data
+    reachability_lock: Arc<RwLock<DbReachabilityStore>>,
     ghostdag_store: DbGhostdagStore,
     header_store: DbHeadersStore,
+    tips_store: DbTipsStore,
+    selected_chain_store: DbSelectedChainStore,
+    statuses_store: DbStatusesStore,
+    block_index_store: DbBlockIndexStore,
+    storage: FlexiDagStorage,
+    parents_selection: ParentsSelectionParams,
     /// orphan blocks, parent hash -> orphan block
     missing_blocks: HashMap<Hash, HashSet<Header>>,
 }
 
 impl BlockDAG {
-    pub fn new(genesis: Header, k: KType, db: FlexiDagStorage) -> Self {
+    pub fn new(genesis: Header, k: KType, db: FlexiDagStorage) -> anyhow::Result<Self> {
         let ghostdag_store = db.ghost_dag_store.clone();
         let header_store = db.header_store.clone();
         let relations_store = db.relations_store.clone();
-        let mut reachability_store = db.reachability_store;
-        inquirer::init(&mut reachability_store).unwrap();
-        let reachability_service =
-            MTReachabilityService::new(Arc::new(RwLock::new(reachability_store.clone())));
+        let tips_store = db.tips_store.clone();
+        let selected_chain_store = db.selected_chain_store.clone();
+        let statuses_store = db.statuses_store.clone();
+        let block_index_store = db.block_index_store.clone();
+        let mut reachability_store = db.reachability_store.clone();
+        inquirer::init(&mut reachability_store)?;
+        let reachability_lock = Arc::new(RwLock::new(reachability_store.clone()));
+        let reachability_service = MTReachabilityService::new(reachability_lock.clone());
         let ghostdag_manager = DbGhostdagManager::new(
             genesis.hash(),
             k,
@@ -56,22 +121,57 @@ impl BlockDAG {
             ghostdag_manager,
             relations_store,
             reachability_store,
+            reachability_lock,
             ghostdag_store,
             header_store,
+            tips_store,
+            selected_chain_store,
+            statuses_store,
+            block_index_store,
+            storage: db,
+            parents_selection: ParentsSelectionParams::default(),
             missing_blocks: HashMap::new(),
         };
-        dag.init_with_genesis();
-        dag
+        dag.init_with_genesis()?;
+        Ok(dag)
     }
 
-    pub fn init_with_genesis(&mut self) {
-        if self.relations_store.has(Hash::new(ORIGIN)).unwrap() {
-            return;
+    pub fn with_parents_selection(mut self, params: ParentsSelectionParams) -> Self {
+        self.parents_selection = params;
+        self
+    }
+
+    /// Initializes an empty database with the configured genesis and K, or checks that an
+    /// existing database was initialized with the very same genesis and K.
+    pub fn init_with_genesis(&mut self) -> anyhow::Result<()> {
+        let origin = Hash::new(ORIGIN);
+        if !self.relations_store.has(origin)? {
+            self.relations_store
+                .insert(origin, BlockHashes::new(vec![]))?;
+        }
+        // The genesis is the only block having the origin as its parent
+        let stored_genesis = self.relations_store.get_children(origin)?;
+        match stored_genesis.as_slice() {
+            [] => self.commit_header(&self.genesis.clone()),
+            [hash] if *hash == self.genesis.hash() => (),
+            _ => bail!(
+                "the database was initialized with genesis {:?}, which differs from the configured genesis {}",
+                stored_genesis,
+                self.genesis.hash()
+            ),
+        }
+        let k = self.ghostdag_manager.k();
+        match self.ghostdag_store.get_k() {
+            Ok(stored_k) if stored_k == k => Ok(()),
+            Ok(stored_k) => bail!(
+                "the database was initialized with k = {}, which differs from the configured k = {}",
+                stored_k,
+                k
+            ),
+            // Databases created before K was recorded
+            Err(StoreError::KeyNotFound(_)) => Ok(self.ghostdag_store.set_k(k)?),
+            Err(error) => Err(error.into()),
         }
-        self.relations_store
-            .insert(Hash::new(ORIGIN), BlockHashes::new(vec![]))
-            .unwrap();
-        self.commit_header(&self.genesis.clone())
     }
 
     pub fn commit_header(&mut self, header: &Header) {
@@ -89,18 +189,20 @@ impl BlockDAG {
             .unwrap();
 
         // Update reachability store
-        let mut reachability_store = self.reachability_store.clone();
-        let mut merge_set = ghostdag_data
+        let mut reachability_store = self.reachability_lock.write();
+        let merge_set = ghostdag_data
             .unordered_mergeset_without_selected_parent()
-            .filter(|hash| self.reachability_store.has(*hash).unwrap());
+            .filter(|hash| reachability_store.has(*hash).unwrap())
+            .collect::<Vec<_>>();
 
         inquirer::add_block(
-            &mut reachability_store,
+            &mut *reachability_store,
             header.hash(),
             ghostdag_data.selected_parent,
-            &mut merge_set,
+            &mut merge_set.into_iter(),
         )
         .unwrap();
+        drop(reachability_store);
 
         // store relations
         self.relations_store
@@ -110,17 +212,482 @@ impl BlockDAG {
         self.header_store
             .insert(header.hash(), Arc::new(header.to_owned()), 0)
             .unwrap();
+        self.block_index_store
+            .insert(header.hash(), ghostdag_data.blue_score, header.timestamp())
+            .unwrap();
+        // Update the dag tips and the selected chain of the virtual block
+        if header.hash() == self.genesis.hash() {
+            self.statuses_store
+                .set(header.hash(), BlockStatus::Valid)
+                .unwrap();
+            self.tips_store.init(header.hash()).unwrap();
+            self.selected_chain_store.init(header.hash()).unwrap();
+        } else {
+            self.statuses_store
+                .set(header.hash(), BlockStatus::HeaderOnly)
+                .unwrap();
+            let tips = self
+                .tips_store
+                .add_tip(header.hash(), parents_hash)
+                .unwrap();
+            self.update_selected_chain(&tips).unwrap();
+        }
+    }
+
+    /// Moves the selected chain index onto the selected chain of the heaviest tip,
+    /// which is the selected parent of the virtual block
+    fn update_selected_chain(&mut self, tips: &BlockHashSet) -> anyhow::Result<()> {
+        if let Some(changes) = self.selected_chain_changes(tips)? {
+            let mut batch = DbBatch::default();
+            self.selected_chain_store
+                .apply_changes_batch(&mut batch, &changes)?;
+            set_chain_statuses_batch(
+                &mut self.statuses_store,
+                &mut batch,
+                &changes.removed,
+                &changes.added,
+            )?;
+            self.storage.write_batch(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the changes moving the selected chain index onto the selected chain of the
+    /// heaviest of `tips`, or `None` if the index is there already
+    fn selected_chain_changes(&self, tips: &BlockHashSet) -> anyhow::Result<Option<ChainPath>> {
+        let new_tip = self
+            .ghostdag_manager
+            .find_selected_parent(tips.iter().copied());
+        let (_, old_tip) = self.selected_chain_store.get_tip()?;
+        if new_tip == old_tip {
+            return Ok(None);
+        }
+        Ok(Some(chain_path(
+            &self.reachability_store,
+            &self.ghostdag_store,
+            old_tip,
+            new_tip,
+        )?))
+    }
+
+    /// Commits a batch of headers sorted in topological order, with the very same result
+    /// as committing them one by one with `commit_header`. The GHOSTDAG and reachability
+    /// data of the batch are computed in memory, then everything is written at once with
+    /// a single sync, bypassing the caches. The headers are checked as by `connect_block`,
+    /// nothing but the status of a rejected header being written if one fails.
+    pub fn commit_headers_bulk(&mut self, headers: &[Header]) -> anyhow::Result<()> {
+        if headers.is_empty() {
+            return Ok(());
+        }
+        for header in headers {
+            self.check_header(header)?;
+        }
+        let genesis = self.genesis.hash();
+        let ghostdag_store = StagingGhostdagStore::new(self.ghostdag_store.clone());
+        let mut relations_store = StagingRelationsStore::new(self.relations_store.clone());
+        let header_store = StagingHeadersStore::new(self.header_store.clone());
+        let reachability_store = Arc::new(RwLock::new(StagingReachabilityStore::new(
+            self.reachability_lock.upgradable_read(),
+        )));
+        let ghostdag_manager = StagingGhostdagManager::new(
+            genesis,
+            self.ghostdag_manager.k(),
+            ghostdag_store.clone(),
+            relations_store.clone(),
+            header_store.clone(),
+            MTReachabilityService::new(reachability_store.clone()),
+        );
+
+        let mut tips = BlockHashSet::clone(&self.tips_store.get()?);
+        let (_, old_tip) = self.selected_chain_store.get_tip()?;
+        let mut selected_tip = old_tip;
+        // The blocks which left the selected chain or joined it, as committing the headers
+        // one by one would have moved it
+        let (mut left_chain, mut joined_chain) = (BlockHashSet::new(), BlockHashSet::new());
+        let mut indexed = Vec::with_capacity(headers.len());
+        for header in headers {
+            let hash = header.hash();
+            if hash == genesis {
+                bail!("the genesis cannot be imported");
+            }
+            let parents_hash = header.parents_hash();
+            for parent in parents_hash {
+                if !relations_store.has(*parent)? {
+                    bail!(
+                        "parent {} of block {} is unknown, the headers must be sorted in topological order",
+                        parent,
+                        hash
+                    );
+                }
+            }
+
+            let ghostdag_data = Arc::new(ghostdag_manager.ghostdag(parents_hash));
+            ghostdag_store.insert(hash, ghostdag_data.clone())?;
+
+            let mut reachability = reachability_store.write();
+            let mut merge_set = vec![];
+            for block in ghostdag_data.unordered_mergeset_without_selected_parent() {
+                if reachability.has(block)? {
+                    merge_set.push(block);
+                }
+            }
+            inquirer::add_block(
+                &mut *reachability,
+                hash,
+                ghostdag_data.selected_parent,
+                &mut merge_set.into_iter(),
+            )?;
+            drop(reachability);
+
+            relations_store.insert(hash, BlockHashes::new(parents_hash.to_vec()))?;
+            header_store.insert(hash, Arc::new(header.clone()), 0)?;
+            indexed.push((hash, ghostdag_data.blue_score, header.timestamp()));
+            for parent in parents_hash {
+                tips.remove(parent);
+            }
+            tips.insert(hash);
+
+            let new_tip = ghostdag_manager.find_selected_parent(tips.iter().copied());
+            if new_tip != selected_tip {
+                let changes = chain_path(
+                    &*reachability_store.read(),
+                    &ghostdag_store,
+                    selected_tip,
+                    new_tip,
+                )?;
+                for block in changes.removed {
+                    joined_chain.remove(&block);
+                    left_chain.insert(block);
+                }
+                for block in changes.added {
+                    left_chain.remove(&block);
+                    joined_chain.insert(block);
+                }
+                selected_tip = new_tip;
+            }
+        }
+
+        let new_tip = selected_tip;
+        let chain_changes = if new_tip != old_tip {
+            Some(chain_path(
+                &*reachability_store.read(),
+                &ghostdag_store,
+                old_tip,
+                new_tip,
+            )?)
+        } else {
+            None
+        };
+
+        // The manager holds the only other reference to the staged reachability store
+        drop(ghostdag_manager);
+        let reachability_store = match Arc::try_unwrap(reachability_store) {
+            Ok(reachability_store) => reachability_store.into_inner(),
+            Err(_) => unreachable!("the staged reachability store is still shared"),
+        };
+        let mut batch = DbBatch::default();
+        // The reachability store stays locked until the batch is written
+        let reachability_store = reachability_store.commit(&mut batch)?;
+        ghostdag_store.commit(&mut batch)?;
+        relations_store.commit(&mut batch)?;
+        header_store.commit(&mut batch)?;
+        for (hash, blue_score, timestamp) in indexed {
+            self.block_index_store
+                .insert_batch(&mut batch, hash, blue_score, timestamp)?;
+            self.statuses_store
+                .set_batch(&mut batch, hash, BlockStatus::HeaderOnly)?;
+        }
+        set_chain_statuses_batch(
+            &mut self.statuses_store,
+            &mut batch,
+            &left_chain.into_iter().collect::<Vec<_>>(),
+            &joined_chain.into_iter().collect::<Vec<_>>(),
+        )?;
+        self.tips_store.set_batch(&mut batch, tips)?;
+        if let Some(changes) = chain_changes {
+            self.selected_chain_store
+                .apply_changes_batch(&mut batch, &changes)?;
+        }
+        self.storage.write_batch_sync(batch)?;
+        drop(reachability_store);
+        Ok(())
+    }
+
+    /// Removes `block` and every block of its future from the dag, then repairs the
+    /// children of the remaining parents, the reachability intervals, the tips and the
+    /// selected chain index, all in one atomic batch. Returns the removed blocks in
+    /// topological order.
+    pub fn remove_block_and_future(&mut self, block: Hash) -> anyhow::Result<Vec<Hash>> {
+        if block == self.genesis.hash() {
+            bail!("the genesis cannot be removed");
+        }
+        if !self.relations_store.has(block)? {
+            bail!("block {} is not in the dag", block);
+        }
+
+        let mut future = BlockHashSet::from_iter([block]);
+        let mut queue = VecDeque::from([block]);
+        while let Some(current) = queue.pop_front() {
+            for child in self.relations_store.get_children(current)?.iter() {
+                if future.insert(*child) {
+                    queue.push_back(*child);
+                }
+            }
+        }
+        let removed = self.ghostdag_manager.sort_blocks(future.iter().copied());
+
+        // Remaining parents left without children become tips again
+        let mut tips: BlockHashSet = self
+            .tips_store
+            .get()?
+            .iter()
+            .copied()
+            .filter(|tip| !future.contains(tip))
+            .collect();
+        for hash in removed.iter() {
+            for parent in self.relations_store.get_parents(*hash)?.iter() {
+                if future.contains(parent) {
+                    continue;
+                }
+                let children = self.relations_store.get_children(*parent)?;
+                if children.iter().all(|child| future.contains(child)) {
+                    tips.insert(*parent);
+                }
+            }
+        }
+        let chain_changes = self.selected_chain_changes(&tips)?;
+        let mut removed_data = Vec::with_capacity(removed.len());
+        let mut indexed = Vec::with_capacity(removed.len());
+        for hash in removed.iter() {
+            let ghostdag_data = self.ghostdag_store.get_data(*hash)?;
+            let timestamp = self.header_store.get_header(*hash)?.timestamp();
+            indexed.push((*hash, ghostdag_data.blue_score, timestamp));
+            removed_data.push(ghostdag_data);
+        }
+
+        // Reachability deletions are staged first, as they are the most likely to fail
+        let mut batch = DbBatch::default();
+        let mut staging = StagingReachabilityStore::new(self.reachability_lock.upgradable_read());
+        for (hash, ghostdag_data) in removed.iter().zip(removed_data).rev() {
+            inquirer::delete_block(
+                &mut staging,
+                *hash,
+                &mut ghostdag_data.unordered_mergeset_without_selected_parent(),
+            )?;
+        }
+        // The reachability store stays locked until the batch is written
+        let reachability_store = staging.commit(&mut batch)?;
+
+        self.block_index_store.delete_batch(&mut batch, &indexed)?;
+
+        self.relations_store.delete_batch(&mut batch, &future)?;
+        self.ghostdag_store.delete_batch(&mut batch, &removed)?;
+        self.header_store.delete_batch(&mut batch, &removed)?;
+        self.statuses_store.delete_batch(&mut batch, &removed)?;
+        self.tips_store.set_batch(&mut batch, tips)?;
+        if let Some(changes) = chain_changes {
+            self.selected_chain_store
+                .apply_changes_batch(&mut batch, &changes)?;
+            // The removed blocks leave the selected chain along with their statuses
+            let disqualified: Vec<_> = changes
+                .removed
+                .iter()
+                .copied()
+                .filter(|hash| !future.contains(hash))
+                .collect();
+            set_chain_statuses_batch(
+                &mut self.statuses_store,
+                &mut batch,
+                &disqualified,
+                &changes.added,
+            )?;
+        }
+        self.storage.write_batch(batch)?;
+        drop(reachability_store);
+        Ok(removed)
+    }
+
+    /// Creates a consistent checkpoint of the dag storage in `backup_path`, while the dag
+    /// keeps accepting blocks
+    pub fn create_checkpoint(&self, backup_path: &Path) -> anyhow::Result<()> {
+        Ok(self.storage.create_checkpoint(backup_path)?)
+    }
+
+    /// The blocks whose blue score is in `[low, high]`, by ascending blue score
+    pub fn blocks_by_blue_score(&self, low: u64, high: u64) -> anyhow::Result<Vec<Hash>> {
+        let blocks = self.block_index_store.blue_score_range(low, high)?;
+        Ok(blocks
+            .map(|block| block.map(|(_, hash)| hash))
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// The blocks whose timestamp is in `[low, high]`, by ascending timestamp
+    pub fn blocks_by_timestamp(&self, low: u64, high: u64) -> anyhow::Result<Vec<Hash>> {
+        let blocks = self.block_index_store.timestamp_range(low, high)?;
+        Ok(blocks
+            .map(|block| block.map(|(_, hash)| hash))
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// The cache and db access counters of the dag stores
+    pub fn store_metrics(&self) -> BTreeMap<&'static str, StoreMetrics> {
+        self.storage.metrics_snapshot()
+    }
+
+    /// Selects the parents of a new block out of the current tips.
+    ///
+    /// Tips are considered by descending blue work, the heaviest one becoming the selected
+    /// parent. A tip is skipped if referencing it would exceed `mergeset_size_limit`, or
+    /// would merge a red block violating the bounded merge depth.
+    pub fn block_template_parents(&self) -> anyhow::Result<BlockTemplateParents> {
+        let tips = self.tips_store.get()?;
+        let mut candidates = self
+            .ghostdag_manager
+            .sort_blocks(tips.iter().copied())
+            .into_iter()
+            .rev();
+        let selected_parent = match candidates.next() {
+            Some(hash) => hash,
+            None => bail!("the dag has no tips, was it initialized?"),
+        };
+
+        let mut parents = vec![selected_parent];
+        let mut ghostdag_data = self.ghostdag_manager.ghostdag(&parents);
+        if self.violates_merge_bounds(&ghostdag_data)? {
+            bail!(
+                "the mergeset of the selected parent {} alone violates the merge bounds",
+                selected_parent
+            );
+        }
+        for candidate in candidates {
+            if parents.len() >= self.parents_selection.max_parents {
+                break;
+            }
+            parents.push(candidate);
+            let candidate_data = self.ghostdag_manager.ghostdag(&parents);
+            if self.violates_merge_bounds(&candidate_data)? {
+                parents.pop();
+                continue;
+            }
+            ghostdag_data = candidate_data;
+        }
+
+        Ok(BlockTemplateParents {
+            parents,
+            ghostdag_data,
+        })
+    }
+
+    fn violates_merge_bounds(&self, ghostdag_data: &GhostdagData) -> anyhow::Result<bool> {
+        if ghostdag_data.mergeset_size() as u64 > self.parents_selection.mergeset_size_limit {
+            return Ok(true);
+        }
+        let merge_depth_root = self.merge_depth_root(ghostdag_data)?;
+        let mut kosherizing_blues = None;
+        for red in ghostdag_data.mergeset_reds.iter().copied() {
+            if inquirer::is_dag_ancestor_of(&self.reachability_store, merge_depth_root, red)? {
+                continue;
+            }
+            // Collected lazily, since reds out of the future of the root are rare
+            if kosherizing_blues.is_none() {
+                kosherizing_blues = Some(self.kosherizing_blues(ghostdag_data, merge_depth_root)?);
+            }
+            let mut kosherized = false;
+            for blue in kosherizing_blues.iter().flatten() {
+                if inquirer::is_dag_ancestor_of(&self.reachability_store, red, *blue)? {
+                    kosherized = true;
+                    break;
+                }
+            }
+            if !kosherized {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns the latest block of the selected chain of a block having `ghostdag_data`
+    /// which lags behind it by at least `merge_depth` in blue score, as kaspa's
+    /// `MergeDepthManager::calc_merge_depth_root` does with the genesis as pruning point.
+    fn merge_depth_root(&self, ghostdag_data: &GhostdagData) -> anyhow::Result<Hash> {
+        let depth = self.parents_selection.merge_depth;
+        let genesis = self.genesis.hash();
+        if ghostdag_data.blue_score < depth {
+            return Ok(genesis);
+        }
+        let mut current = ghostdag_data.selected_parent;
+        while current != genesis {
+            let data = self.ghostdag_store.get_compact_data(current)?;
+            if data.blue_score + depth <= ghostdag_data.blue_score {
+                break;
+            }
+            current = data.selected_parent;
+        }
+        Ok(current)
+    }
+
+    /// Returns the blues of the mergeset which are in the selected chain future of
+    /// `merge_depth_root`, a red block may only be merged if one of them is in its future
+    fn kosherizing_blues(
+        &self,
+        ghostdag_data: &GhostdagData,
+        merge_depth_root: Hash,
+    ) -> anyhow::Result<Vec<Hash>> {
+        let mut blues = vec![];
+        for blue in ghostdag_data.mergeset_blues.iter().copied() {
+            if inquirer::is_chain_ancestor_of(&self.reachability_store, merge_depth_root, blue)? {
+                blues.push(blue);
+            }
+        }
+        Ok(blues)
     }
+
     fn is_in_dag(&self, hash: Hash) -> anyhow::Result<bool> {
-        return Ok(true);
+        Ok(self.relations_store.has(hash)?)
+    }
+
+    /// Returns the parents of `header` which are not in the dag yet
+    pub fn missing_parents(&self, header: &Header) -> anyhow::Result<Vec<Hash>> {
+        let mut missing = vec![];
+        for parent in header.parents_hash() {
+            if !self.is_in_dag(*parent)? {
+                missing.push(*parent);
+            }
+        }
+        Ok(missing)
     }
+
     pub fn verify_header(&self, header: &Header) -> anyhow::Result<()> {
-        //TODO: implemented it
+        //TODO: verify the header against its parents once they are known
+        let parents = header.parents_hash();
+        if parents.is_empty() {
+            bail!("the header has no parents");
+        }
+        if !header.commits_to_parents() {
+            bail!("the parent hash of the header does not commit to its parents");
+        }
+        if parents.len() > self.parents_selection.max_parents {
+            bail!(
+                "the header has {} parents, more than the maximum of {}",
+                parents.len(),
+                self.parents_selection.max_parents
+            );
+        }
+        if parents.iter().collect::<HashSet<_>>().len() != parents.len() {
+            bail!("the header references the same parent twice");
+        }
         Ok(())
     }
 
+    /// Verifies and commits `header`. A header failing verification, or having an invalid
+    /// parent, is recorded as invalid with the reason and rejected. A header not meeting
+    /// its difficulty is rejected without recording anything, since it costs nothing to
+    /// forge one with the hash of another block. Blocks of the dag are ignored.
     pub fn connect_block(&mut self, header: &Header) -> anyhow::Result<()> {
-        let _ = self.verify_header(header)?;
+        if self.has_block(header.hash())? {
+            return Ok(());
+        }
+        self.check_header(header)?;
         let is_orphan_block = self.update_orphans(header)?;
         if is_orphan_block {
             return Ok(());
@@ -130,24 +697,70 @@ impl BlockDAG {
         Ok(())
     }
 
-    pub fn check_missing_block(&mut self, header: &Header) -> anyhow::Result<()> {
-        if let Some(orphans) = self.missing_blocks.remove(&header.hash()) {
-            for orphan in orphans.iter() {
-                let is_orphan = self.is_orphan(&orphan)?;
-                if !is_orphan {
-                    self.commit_header(header);
-                }
+    /// The checks of `connect_block`, which records the headers failing them as invalid
+    fn check_header(&mut self, header: &Header) -> anyhow::Result<()> {
+        let hash = header.hash();
+        if !check_pow(header) {
+            bail!("block {} does not meet its difficulty", hash);
+        }
+        if let Some(BlockStatus::Invalid(reason)) = self.get_block_status(hash)? {
+            bail!("block {} is known to be invalid: {}", hash, reason);
+        }
+        if let Err(error) = self.verify_header(header) {
+            self.record_invalid(header, error.to_string())?;
+            return Err(error);
+        }
+        // Children of invalid blocks are invalid as well, so checking the parents is
+        // enough to reject any block having an invalid ancestor
+        for parent in header.parents_hash() {
+            if let Some(BlockStatus::Invalid(_)) = self.get_block_status(*parent)? {
+                let reason = format!("the parent {} is invalid", parent);
+                self.record_invalid(header, reason.clone())?;
+                bail!("block {} is rejected: {}", hash, reason);
             }
         }
         Ok(())
     }
-    fn is_orphan(&self, header: &Header) -> anyhow::Result<bool> {
+
+    /// Records `header` as invalid for `reason`, unless it does not commit to its parents,
+    /// as it may carry the id of a valid block, or one of its parents is unknown. Peers
+    /// choose the difficulty of their headers, so recording the ones out of the known dag
+    /// would let them grow the statuses without bound.
+    fn record_invalid(&mut self, header: &Header, reason: String) -> anyhow::Result<()> {
+        if !header.commits_to_parents() {
+            return Ok(());
+        }
         for parent in header.parents_hash() {
-            if !self.is_in_dag(parent.to_owned())? {
-                return Ok(false);
+            if !self.statuses_store.has(*parent)? {
+                return Ok(());
+            }
+        }
+        self.statuses_store
+            .set(header.hash(), BlockStatus::Invalid(reason))?;
+        Ok(())
+    }
+
+    /// Commits the orphans which were waiting for `header`, and then the ones waiting for
+    /// them in turn
+    pub fn check_missing_block(&mut self, header: &Header) -> anyhow::Result<()> {
+        let mut connected = vec![header.hash()];
+        while let Some(hash) = connected.pop() {
+            if let Some(orphans) = self.missing_blocks.remove(&hash) {
+                for orphan in orphans {
+                    // An orphan missing several parents waits in the entries of the others
+                    if self.is_orphan(&orphan)? || self.is_in_dag(orphan.hash())? {
+                        continue;
+                    }
+                    self.commit_header(&orphan);
+                    connected.push(orphan.hash());
+                }
             }
         }
-        return Ok(true);
+        Ok(())
+    }
+
+    fn is_orphan(&self, header: &Header) -> anyhow::Result<bool> {
+        Ok(!self.missing_parents(header)?.is_empty())
     }
 
     fn update_orphans(&mut self, block_header: &Header) -> anyhow::Result<bool> {
@@ -199,22 +812,263 @@ impl BlockDAG {
         }
     }
 
+    /// Returns the status of `hash`, or `None` if the block is unknown
+    pub fn get_block_status(&self, hash: Hash) -> anyhow::Result<Option<BlockStatus>> {
+        match self.statuses_store.get(hash) {
+            Ok(status) => Ok(Some(status)),
+            Err(StoreError::KeyNotFound(_)) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Updates the status of a committed block, once its body was validated or it was
+    /// disqualified from the selected chain. The statuses of invalid blocks never change.
+    pub fn set_block_status(&mut self, hash: Hash, status: BlockStatus) -> anyhow::Result<()> {
+        match self.get_block_status(hash)? {
+            Some(current) if current.is_committed() && status.is_committed() => {
+                self.statuses_store.set(hash, status)?;
+                Ok(())
+            }
+            Some(current) => bail!(
+                "the status of block {} cannot change from {:?} to {:?}",
+                hash,
+                current,
+                status
+            ),
+            None => bail!("block {} is unknown", hash),
+        }
+    }
+
     pub fn get_genesis_hash(&self) -> Hash {
         self.genesis.hash()
     }
+
+    /// Returns whether `hash` was committed to the dag
+    pub fn has_block(&self, hash: Hash) -> anyhow::Result<bool> {
+        Ok(self.header_store.has(hash)?)
+    }
+
+    pub fn get_ghostdag_data(&self, hash: Hash) -> anyhow::Result<Arc<GhostdagData>> {
+        Ok(self.ghostdag_store.get_data(hash)?)
+    }
+
+    pub fn get_reachability_interval(&self, hash: Hash) -> anyhow::Result<Interval> {
+        Ok(self.reachability_store.get_interval(hash)?)
+    }
+
+    /// Sorts `blocks` by ascending blue work, which is a topological order
+    pub fn sort_blocks(&self, blocks: impl IntoIterator<Item = Hash>) -> Vec<Hash> {
+        self.ghostdag_manager.sort_blocks(blocks)
+    }
+
+    /// Returns whether `a` is in the past of `b`, a block not being in its own past
+    pub fn is_in_past(&self, a: Hash, b: Hash) -> anyhow::Result<bool> {
+        Ok(a != b && inquirer::is_dag_ancestor_of(&self.reachability_store, a, b)?)
+    }
+
+    /// Returns whether `a` and `b` are in the anticone of each other
+    pub fn is_in_anticone(&self, a: Hash, b: Hash) -> anyhow::Result<bool> {
+        Ok(a != b && !self.is_in_past(a, b)? && !self.is_in_past(b, a)?)
+    }
+
+    /// Returns the anticone of `block` restricted to the blocks of `within` and their past,
+    /// sorted in topological order.
+    pub fn anticone(&self, block: Hash, within: &[Hash]) -> anyhow::Result<Vec<Hash>> {
+        let mut anticone = vec![];
+        let mut visited: BlockHashSet = within.iter().copied().collect();
+        let mut queue: VecDeque<Hash> = within.iter().copied().collect();
+        while let Some(current) = queue.pop_front() {
+            // The past of an ancestor of `block` is in the past of `block` as well
+            if inquirer::is_dag_ancestor_of(&self.reachability_store, current, block)? {
+                continue;
+            }
+            if !self.is_in_past(block, current)? {
+                anticone.push(current);
+            }
+            for parent in self.relations_store.get_parents(current)?.iter() {
+                if visited.insert(*parent) {
+                    queue.push_back(*parent);
+                }
+            }
+        }
+        Ok(self.ghostdag_manager.sort_blocks(anticone))
+    }
+
+    /// Returns an estimate of the size of the past of `block`, read in constant time: its
+    /// blue score, which counts the blue blocks of its past. Red blocks are left out, so
+    /// this is a lower bound, which is tight as long as few blocks are red.
+    pub fn past_size_estimate(&self, block: Hash) -> anyhow::Result<u64> {
+        Ok(self.ghostdag_store.get_blue_score(block)?)
+    }
+
+    /// Returns the most recent block of both the selected chains of `a` and `b`
+    pub fn common_chain_ancestor(&self, a: Hash, b: Hash) -> anyhow::Result<Hash> {
+        Ok(inquirer::find_common_tree_ancestor(
+            &self.reachability_store,
+            a,
+            b,
+        )?)
+    }
+
+    /// Returns the blocks of `past(high) ∪ {high}` which are not in `past(low) ∪ {low}`,
+    /// sorted in topological order.
+    pub fn blocks_between(&self, low: Hash, high: Hash) -> anyhow::Result<Vec<Hash>> {
+        let mut blocks = vec![];
+        let mut visited = BlockHashSet::from_iter([high]);
+        let mut queue = VecDeque::from([high]);
+        while let Some(current) = queue.pop_front() {
+            if inquirer::is_dag_ancestor_of(&self.reachability_store, current, low)? {
+                continue;
+            }
+            blocks.push(current);
+            for parent in self.relations_store.get_parents(current)?.iter() {
+                if visited.insert(*parent) {
+                    queue.push_back(*parent);
+                }
+            }
+        }
+        Ok(self.ghostdag_manager.sort_blocks(blocks))
+    }
+
+    /// Returns the block at position `index` of the selected chain of the virtual block,
+    /// genesis being at index 0
+    pub fn chain_block_at(&self, index: u64) -> anyhow::Result<Option<Hash>> {
+        match self.selected_chain_store.get_by_index(index) {
+            Ok(hash) => Ok(Some(hash)),
+            Err(StoreError::KeyNotFound(_)) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Returns the position of `block` on the selected chain of the virtual block,
+    /// or `None` if it is not a chain block
+    pub fn chain_index_of(&self, block: Hash) -> anyhow::Result<Option<u64>> {
+        match self.selected_chain_store.get_by_hash(block) {
+            Ok(index) => Ok(Some(index)),
+            Err(StoreError::KeyNotFound(_)) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Returns the highest block of the selected chain whose blue score is at most `score`.
+    /// Blue scores strictly increase along the chain, so this is a binary search.
+    pub fn chain_block_by_blue_score(&self, score: u64) -> anyhow::Result<Hash> {
+        let (mut low, mut high) = (0, self.selected_chain_store.get_tip()?.0);
+        // The genesis has a blue score of 0, so `low` always satisfies the bound
+        while low < high {
+            let middle = low + (high - low + 1) / 2;
+            let block = self.selected_chain_store.get_by_index(middle)?;
+            if self.ghostdag_store.get_blue_score(block)? <= score {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        Ok(self.selected_chain_store.get_by_index(low)?)
+    }
+
+    /// Iterates over the selected chain blocks at the positions of `range`, positions past
+    /// the selected chain tip being ignored
+    pub fn chain_blocks(
+        &self,
+        range: Range<u64>,
+    ) -> anyhow::Result<impl Iterator<Item = anyhow::Result<Hash>> + '_> {
+        let (highest_index, _) = self.selected_chain_store.get_tip()?;
+        let end = range.end.min(highest_index + 1);
+        Ok((range.start..end).map(|index| Ok(self.selected_chain_store.get_by_index(index)?)))
+    }
+
+    /// Explains why `candidate`, a block in the mergeset of `block`, was colored blue or red
+    /// by `block`. Returns `None` if `candidate` is not in the mergeset of `block`.
+    pub fn explain_coloring(
+        &self,
+        block: Hash,
+        candidate: Hash,
+    ) -> anyhow::Result<Option<ColoringExplanation>> {
+        let parents = self.relations_store.get_parents(block)?;
+        Ok(self.ghostdag_manager.explain_coloring(&parents, candidate))
+    }
+}
+
+/// Checks that the hash of `header` meets the difficulty written in it
+pub fn check_pow(header: &Header) -> bool {
+    let difficulty = header.difficulty();
+    if difficulty.is_zero() {
+        return false;
+    }
+    U256::from_big_endian(header.hash().as_slice()) <= U256::max_value() / difficulty
+}
+
+/// Disqualifies the blocks of `removed`, which left the selected chain, and brings the
+/// disqualified blocks of `added` back to header-only, their body being validated again
+/// once they are on the selected chain
+fn set_chain_statuses_batch(
+    statuses_store: &mut DbStatusesStore,
+    batch: &mut DbBatch,
+    removed: &[Hash],
+    added: &[Hash],
+) -> anyhow::Result<()> {
+    for hash in removed {
+        let reason = "it left the selected chain of the virtual block".to_string();
+        statuses_store.set_batch(batch, *hash, BlockStatus::Disqualified(reason))?;
+    }
+    for hash in added {
+        match statuses_store.get(*hash) {
+            Ok(BlockStatus::Disqualified(_)) => {
+                statuses_store.set_batch(batch, *hash, BlockStatus::HeaderOnly)?
+            }
+            // Blocks staged by a bulk import are not stored yet
+            Ok(_) | Err(StoreError::KeyNotFound(_)) => (),
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the changes moving a selected chain from `old_tip` onto the selected chain of
+/// `new_tip`
+fn chain_path(
+    reachability_store: &impl ReachabilityStore,
+    ghostdag_store: &impl GhostdagStoreReader,
+    old_tip: Hash,
+    new_tip: Hash,
+) -> anyhow::Result<ChainPath> {
+    let split_point = inquirer::find_common_tree_ancestor(reachability_store, old_tip, new_tip)?;
+    let mut removed = vec![];
+    let mut current = old_tip;
+    while current != split_point {
+        removed.push(current);
+        current = ghostdag_store.get_selected_parent(current)?;
+    }
+    let mut added = vec![];
+    let mut current = new_tip;
+    while current != split_point {
+        added.push(current);
+        current = ghostdag_store.get_selected_parent(current)?;
+    }
+    added.reverse();
+    Ok(ChainPath { added, removed })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use database::prelude::{FlexiDagStorage, FlexiDagStorageConfig};
+    use crate::dot::{render_dot, DotRegion};
+    use crate::genesis::{DagGenesisConfig, GenesisNetwork};
+    use crate::rebuild::{rebuild_dag_data, RebuildConfig, RebuildProgress};
+    use crate::test_util::{
+        add_test_block, new_test_dag, new_test_header, new_test_header_with_difficulty,
+    };
+    use database::prelude::{check_storage, FlexiDagStorage, FlexiDagStorageConfig};
+    use ghostdag::protocol::RedReason;
     use starcoin_types::block::BlockHeader;
     use std::{env, fs};
     #[test]
     fn base_test() {
-        let genesis = Header::new(BlockHeader::random(), vec![Hash::new(ORIGIN)]);
+        let genesis_config = DagGenesisConfig::dev();
+        let genesis = genesis_config.to_header();
         let genesis_hash = genesis.hash();
-        let k = 16;
+        let k = genesis_config.k;
         let db_path = env::temp_dir().join("smolstc");
         println!("db path:{}", db_path.to_string_lossy());
         if db_path
@@ -227,9 +1081,772 @@ mod tests {
         let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
         let db = FlexiDagStorage::create_from_path(db_path, config)
             .expect("Failed to create flexidag storage");
-        let mut dag = BlockDAG::new(genesis, k, db);
+        let mut dag = BlockDAG::new(genesis, k, db).expect("Failed to init the dag");
 
         let block = Header::new(BlockHeader::random(), vec![genesis_hash]);
         dag.commit_header(&block);
     }
+
+    #[test]
+    fn test_reopen_with_genesis() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let dev = DagGenesisConfig::dev();
+
+        let db = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone())
+            .expect("Failed to create flexidag storage");
+        let dag = BlockDAG::new(dev.to_header(), dev.k, db).unwrap();
+        assert_eq!(dag.get_genesis_hash(), dev.to_header().hash());
+        drop(dag);
+
+        // Reopening with the same genesis is fine
+        let db = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone())
+            .expect("Failed to reopen flexidag storage");
+        let dag = BlockDAG::new(dev.to_header(), dev.k, db).unwrap();
+        drop(dag);
+
+        // A different k must be refused
+        let db = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone())
+            .expect("Failed to reopen flexidag storage");
+        assert!(BlockDAG::new(dev.to_header(), dev.k + 1, db).is_err());
+
+        // A different genesis must be refused
+        let test = DagGenesisConfig::for_network(GenesisNetwork::Test);
+        let db = FlexiDagStorage::create_from_path(db_tempdir.path(), config)
+            .expect("Failed to reopen flexidag storage");
+        assert!(BlockDAG::new(test.to_header(), test.k, db).is_err());
+    }
+
+    #[test]
+    fn test_block_template_parents() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let db = FlexiDagStorage::create_from_path(db_tempdir.path(), config)
+            .expect("Failed to create flexidag storage");
+        let genesis_config = DagGenesisConfig::dev();
+        let genesis = genesis_config.to_header();
+        let mut dag = BlockDAG::new(genesis.clone(), genesis_config.k, db).unwrap();
+
+        let template = dag.block_template_parents().unwrap();
+        assert_eq!(template.parents, vec![genesis.hash()]);
+        assert_eq!(template.ghostdag_data.blue_score, 1);
+
+        let tips: Vec<_> = (0..3)
+            .map(|_| {
+                let block = Header::new(BlockHeader::random(), vec![genesis.hash()]);
+                dag.commit_header(&block);
+                block.hash()
+            })
+            .collect();
+        let template = dag.block_template_parents().unwrap();
+        assert_eq!(template.parents.len(), 3);
+        assert!(tips.iter().all(|tip| template.parents.contains(tip)));
+        assert_eq!(template.ghostdag_data.blue_score, 4);
+        assert_eq!(template.ghostdag_data.selected_parent, template.parents[0]);
+
+        let dag = dag.with_parents_selection(ParentsSelectionParams {
+            max_parents: 2,
+            ..Default::default()
+        });
+        let template = dag.block_template_parents().unwrap();
+        assert_eq!(template.parents.len(), 2);
+        assert_eq!(template.ghostdag_data.blue_score, 3);
+
+        let dag = dag.with_parents_selection(ParentsSelectionParams {
+            mergeset_size_limit: 1,
+            ..Default::default()
+        });
+        let template = dag.block_template_parents().unwrap();
+        assert_eq!(template.parents.len(), 1);
+    }
+
+    #[test]
+    fn test_block_template_drops_deep_red_tip() {
+        // k = 0: a side block of the genesis is red for any block merging the chain
+        let (mut dag, genesis, _dir) = new_test_dag(0);
+        let side = add_test_block(&mut dag, vec![genesis.hash()]);
+        let mut chain_tip = genesis.hash();
+        for _ in 0..5 {
+            chain_tip = add_test_block(&mut dag, vec![chain_tip]);
+        }
+
+        let template = dag.block_template_parents().unwrap();
+        assert_eq!(template.parents, vec![chain_tip, side]);
+        assert_eq!(template.ghostdag_data.mergeset_reds.as_slice(), &[side]);
+
+        // The merge depth root is the chain block of blue score 4, the side block is out of
+        // its future and no blue of the mergeset kosherizes it
+        let dag = dag.with_parents_selection(ParentsSelectionParams {
+            merge_depth: 2,
+            ..Default::default()
+        });
+        let template = dag.block_template_parents().unwrap();
+        assert_eq!(template.parents, vec![chain_tip]);
+        assert!(template.ghostdag_data.mergeset_reds.is_empty());
+    }
+
+    #[test]
+    fn test_explain_coloring() {
+        // k = 1: the candidate sees two blues in its anticone, b1 and b2
+        let (mut dag, genesis, _dir) = new_test_dag(1);
+        let b1 = add_test_block(&mut dag, vec![genesis.hash()]);
+        let b2 = add_test_block(&mut dag, vec![b1]);
+        let c1 = add_test_block(&mut dag, vec![genesis.hash()]);
+        let e = add_test_block(&mut dag, vec![b2, c1]);
+        assert_eq!(
+            dag.explain_coloring(e, b2).unwrap(),
+            Some(ColoringExplanation::Blue {
+                blue_anticone_size: 0
+            })
+        );
+        assert_eq!(
+            dag.explain_coloring(e, c1).unwrap(),
+            Some(ColoringExplanation::Red(
+                RedReason::CandidateAnticoneExceeded {
+                    chain_block: Some(b2)
+                }
+            ))
+        );
+        assert_eq!(dag.explain_coloring(e, genesis.hash()).unwrap(), None);
+
+        // k = 2: the selected parent of s already has 2 blues in its anticone
+        let (mut dag, genesis, _dir) = new_test_dag(2);
+        let a = add_test_block(&mut dag, vec![genesis.hash()]);
+        let b = add_test_block(&mut dag, vec![genesis.hash()]);
+        let c = add_test_block(&mut dag, vec![genesis.hash()]);
+        let s = add_test_block(&mut dag, vec![a, b, c]);
+        let d = add_test_block(&mut dag, vec![genesis.hash()]);
+        let e = add_test_block(&mut dag, vec![s, d]);
+        let s_selected_parent = dag.ghostdag_store.get_selected_parent(s).unwrap();
+        assert_eq!(
+            dag.explain_coloring(e, d).unwrap(),
+            Some(ColoringExplanation::Red(RedReason::BlueAnticoneFull {
+                chain_block: Some(s),
+                blue: s_selected_parent,
+            }))
+        );
+
+        // k = 0: the selected parent leaves no room for another blue
+        let (mut dag, genesis, _dir) = new_test_dag(0);
+        let x = add_test_block(&mut dag, vec![genesis.hash()]);
+        let y = add_test_block(&mut dag, vec![genesis.hash()]);
+        let e = add_test_block(&mut dag, vec![x, y]);
+        let selected_parent = dag.ghostdag_store.get_selected_parent(e).unwrap();
+        let merged = if selected_parent == x { y } else { x };
+        assert_eq!(
+            dag.explain_coloring(e, merged).unwrap(),
+            Some(ColoringExplanation::Red(RedReason::MergesetBluesFull))
+        );
+    }
+
+    /// The dag shape of the basic dag in smolstc's `sync_block_dag.rs`
+    fn new_sync_shape_dag(dag: &mut BlockDAG, genesis: Hash) -> HashMap<&'static str, Hash> {
+        let shape: [(&str, &[&str]); 13] = [
+            ("b", &["g"]),
+            ("c", &["g"]),
+            ("d", &["g"]),
+            ("e", &["g"]),
+            ("f", &["b", "c"]),
+            ("h", &["e", "d", "c"]),
+            ("i", &["e"]),
+            ("k", &["b", "h", "i"]),
+            ("l", &["d", "i"]),
+            ("j", &["f", "h"]),
+            ("m", &["f", "k"]),
+            ("p", &["j", "m"]),
+            ("v", &["k", "c", "l"]),
+        ];
+        let mut blocks = HashMap::from([("g", genesis)]);
+        for (name, parents) in shape {
+            let parents = parents.iter().map(|parent| blocks[parent]).collect();
+            let hash = add_test_block(dag, parents);
+            blocks.insert(name, hash);
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_dag_queries() {
+        let (mut dag, genesis, _dir) = new_test_dag(DagGenesisConfig::dev().k);
+        let blocks = new_sync_shape_dag(&mut dag, genesis.hash());
+        let hashes =
+            |names: &[&str]| -> HashSet<Hash> { names.iter().map(|name| blocks[name]).collect() };
+
+        assert!(dag.is_in_past(blocks["b"], blocks["f"]).unwrap());
+        assert!(dag.is_in_past(blocks["g"], blocks["v"]).unwrap());
+        assert!(!dag.is_in_past(blocks["f"], blocks["b"]).unwrap());
+        assert!(!dag.is_in_past(blocks["f"], blocks["f"]).unwrap());
+        assert!(!dag.is_in_past(blocks["f"], blocks["v"]).unwrap());
+        assert!(dag.is_in_anticone(blocks["f"], blocks["v"]).unwrap());
+
+        let anticone = dag
+            .anticone(blocks["f"], &[blocks["p"], blocks["v"]])
+            .unwrap();
+        assert_eq!(
+            anticone.iter().copied().collect::<HashSet<_>>(),
+            hashes(&["d", "e", "h", "i", "k", "l", "v"])
+        );
+        assert!(dag
+            .anticone(blocks["f"], &[blocks["b"]])
+            .unwrap()
+            .is_empty());
+
+        // Every block is blue with this k, so the estimates are exact
+        assert_eq!(dag.past_size_estimate(blocks["g"]).unwrap(), 0);
+        assert_eq!(dag.past_size_estimate(blocks["b"]).unwrap(), 1);
+        assert_eq!(dag.past_size_estimate(blocks["v"]).unwrap(), 9);
+        assert_eq!(dag.past_size_estimate(blocks["p"]).unwrap(), 11);
+
+        assert_eq!(
+            dag.common_chain_ancestor(blocks["b"], blocks["c"]).unwrap(),
+            blocks["g"]
+        );
+        // The selected parent of l is i, which has the higher blue work
+        assert_eq!(
+            dag.common_chain_ancestor(blocks["i"], blocks["l"]).unwrap(),
+            blocks["i"]
+        );
+        assert_eq!(
+            dag.common_chain_ancestor(blocks["l"], blocks["e"]).unwrap(),
+            blocks["e"]
+        );
+
+        let between = dag.blocks_between(blocks["f"], blocks["p"]).unwrap();
+        assert_eq!(
+            between.iter().copied().collect::<HashSet<_>>(),
+            hashes(&["d", "e", "h", "i", "j", "k", "m", "p"])
+        );
+        // Parents always come first
+        for (index, block) in between.iter().enumerate() {
+            for parent in dag.get_parents(*block).unwrap() {
+                assert!(!between[index..].contains(&parent));
+            }
+        }
+        assert_eq!(
+            dag.blocks_between(blocks["g"], blocks["b"]).unwrap(),
+            vec![blocks["b"]]
+        );
+    }
+
+    #[test]
+    fn test_selected_chain_index() {
+        let (mut dag, genesis, _dir) = new_test_dag(DagGenesisConfig::dev().k);
+        let blocks = new_sync_shape_dag(&mut dag, genesis.hash());
+
+        // The index follows the selected chain of the heaviest tip
+        let tips = [blocks["p"], blocks["v"]];
+        let tip = dag.ghostdag_manager.find_selected_parent(tips);
+        let mut expected = vec![tip];
+        while *expected.last().unwrap() != genesis.hash() {
+            let parent = dag
+                .ghostdag_store
+                .get_selected_parent(*expected.last().unwrap())
+                .unwrap();
+            expected.push(parent);
+        }
+        expected.reverse();
+        let chain = dag
+            .chain_blocks(0..u64::MAX)
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(chain, expected);
+        for (index, block) in chain.iter().enumerate() {
+            assert_eq!(dag.chain_block_at(index as u64).unwrap(), Some(*block));
+            assert_eq!(dag.chain_index_of(*block).unwrap(), Some(index as u64));
+            let blue_score = dag.ghostdag_store.get_blue_score(*block).unwrap();
+            assert_eq!(dag.chain_block_by_blue_score(blue_score).unwrap(), *block);
+        }
+        assert_eq!(dag.chain_block_at(chain.len() as u64).unwrap(), None);
+        assert_eq!(
+            dag.chain_block_by_blue_score(u64::MAX).unwrap(),
+            *chain.last().unwrap()
+        );
+        assert_eq!(
+            dag.chain_blocks(1..3)
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap(),
+            chain[1..3]
+        );
+    }
+
+    #[test]
+    fn test_selected_chain_reorg() {
+        let (mut dag, genesis, _dir) = new_test_dag(0);
+        let a1 = add_test_block(&mut dag, vec![genesis.hash()]);
+        let a2 = add_test_block(&mut dag, vec![a1]);
+        assert_eq!(dag.chain_block_at(2).unwrap(), Some(a2));
+
+        // A heavier side chain takes over
+        let b1 = add_test_block(&mut dag, vec![genesis.hash()]);
+        let b2 = add_test_block(&mut dag, vec![b1]);
+        let b3 = add_test_block(&mut dag, vec![b2]);
+        let chain = dag
+            .chain_blocks(0..10)
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(chain, vec![genesis.hash(), b1, b2, b3]);
+        assert_eq!(dag.chain_index_of(a1).unwrap(), None);
+        assert_eq!(dag.chain_index_of(a2).unwrap(), None);
+        assert_eq!(dag.chain_block_by_blue_score(2).unwrap(), b2);
+
+        // The blocks which left the selected chain are disqualified until they join it again
+        let disqualified = |dag: &BlockDAG, hash| {
+            matches!(
+                dag.get_block_status(hash).unwrap(),
+                Some(BlockStatus::Disqualified(_))
+            )
+        };
+        assert!(disqualified(&dag, a1) && disqualified(&dag, a2));
+        assert!(!disqualified(&dag, b1));
+        let a3 = add_test_block(&mut dag, vec![a2]);
+        let a4 = add_test_block(&mut dag, vec![a3]);
+        assert_eq!(dag.chain_block_at(4).unwrap(), Some(a4));
+        assert_eq!(
+            dag.get_block_status(a1).unwrap(),
+            Some(BlockStatus::HeaderOnly)
+        );
+        assert!([b1, b2, b3].iter().all(|hash| disqualified(&dag, *hash)));
+
+        // A bulk import moves the statuses as committing the headers one by one does
+        let (mut bulk_dag, _, _bulk_dir) = new_test_dag(0);
+        let blocks = [a1, a2, b1, b2, b3, a3, a4];
+        let headers: Vec<_> = blocks
+            .iter()
+            .map(|hash| dag.get_block_header(*hash).unwrap())
+            .collect();
+        bulk_dag.commit_headers_bulk(&headers).unwrap();
+        for hash in blocks {
+            assert_eq!(
+                bulk_dag.get_block_status(hash).unwrap(),
+                dag.get_block_status(hash).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_block_status() {
+        let (mut dag, genesis, _dir) = new_test_dag(DagGenesisConfig::dev().k);
+        assert_eq!(
+            dag.get_block_status(genesis.hash()).unwrap(),
+            Some(BlockStatus::Valid)
+        );
+
+        let valid = new_test_header(vec![genesis.hash()]);
+        dag.connect_block(&valid).unwrap();
+        assert_eq!(
+            dag.get_block_status(valid.hash()).unwrap(),
+            Some(BlockStatus::HeaderOnly)
+        );
+        dag.set_block_status(valid.hash(), BlockStatus::Valid)
+            .unwrap();
+        assert_eq!(
+            dag.get_block_status(valid.hash()).unwrap(),
+            Some(BlockStatus::Valid)
+        );
+        let reason = "its body does not apply on the selected chain".to_string();
+        dag.set_block_status(valid.hash(), BlockStatus::Disqualified(reason.clone()))
+            .unwrap();
+        assert_eq!(
+            dag.get_block_status(valid.hash()).unwrap(),
+            Some(BlockStatus::Disqualified(reason))
+        );
+
+        let invalid = new_test_header(vec![genesis.hash(), genesis.hash()]);
+        assert!(dag.connect_block(&invalid).is_err());
+        assert!(matches!(
+            dag.get_block_status(invalid.hash()).unwrap(),
+            Some(BlockStatus::Invalid(_))
+        ));
+        assert!(dag.connect_block(&invalid).is_err());
+        assert!(dag
+            .set_block_status(invalid.hash(), BlockStatus::Valid)
+            .is_err());
+
+        // Unsolved headers are rejected without being recorded
+        let unsolved = new_test_header_with_difficulty(
+            vec![genesis.hash(), genesis.hash()],
+            U256::max_value(),
+        );
+        assert!(dag.connect_block(&unsolved).is_err());
+        assert_eq!(dag.get_block_status(unsolved.hash()).unwrap(), None);
+
+        // So are headers out of the known dag
+        let unknown = Hash::random();
+        let detached = new_test_header(vec![unknown, unknown]);
+        assert!(dag.connect_block(&detached).is_err());
+        assert_eq!(dag.get_block_status(detached.hash()).unwrap(), None);
+
+        // A header reusing the id of another block with other parents is rejected without
+        // being recorded, and the other block can still be connected
+        let mined = new_test_header(vec![genesis.hash()]);
+        let forged = Header::new(mined.block_header().clone(), vec![valid.hash()]);
+        assert!(dag.connect_block(&forged).is_err());
+        assert_eq!(dag.get_block_status(mined.hash()).unwrap(), None);
+        dag.connect_block(&mined).unwrap();
+        assert_eq!(
+            dag.get_block_status(mined.hash()).unwrap(),
+            Some(BlockStatus::HeaderOnly)
+        );
+
+        // Blocks of the dag are ignored when connected again
+        dag.connect_block(&mined).unwrap();
+        dag.connect_block(&forged).unwrap();
+        assert_eq!(dag.get_parents(mined.hash()).unwrap(), vec![genesis.hash()]);
+
+        // Blocks referencing an invalid block are rejected as well
+        let child = new_test_header(vec![valid.hash(), invalid.hash()]);
+        assert!(dag.connect_block(&child).is_err());
+        assert_eq!(
+            dag.get_block_status(child.hash()).unwrap(),
+            Some(BlockStatus::Invalid(format!(
+                "the parent {} is invalid",
+                invalid.hash()
+            )))
+        );
+        assert_eq!(dag.get_block_status(Hash::random()).unwrap(), None);
+        assert!(!dag.tips_store.get().unwrap().contains(&child.hash()));
+    }
+
+    #[test]
+    fn test_connect_orphans() {
+        let (mut dag, genesis, _dir) = new_test_dag(DagGenesisConfig::dev().k);
+        let parent = new_test_header(vec![genesis.hash()]);
+        let other_parent = new_test_header(vec![genesis.hash()]);
+        let child = new_test_header(vec![parent.hash(), other_parent.hash()]);
+        let grandchild = new_test_header(vec![child.hash()]);
+
+        // Blocks with unknown parents are kept aside until all their parents are connected
+        dag.connect_block(&grandchild).unwrap();
+        dag.connect_block(&child).unwrap();
+        assert_eq!(
+            dag.missing_parents(&child).unwrap(),
+            vec![parent.hash(), other_parent.hash()]
+        );
+        dag.connect_block(&parent).unwrap();
+        assert!(!dag.has_block(child.hash()).unwrap());
+
+        dag.connect_block(&other_parent).unwrap();
+        assert!(dag.has_block(child.hash()).unwrap());
+        assert!(dag.has_block(grandchild.hash()).unwrap());
+        assert_eq!(
+            dag.tips_store
+                .get()
+                .unwrap()
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![grandchild.hash()]
+        );
+        assert!(dag.missing_blocks.is_empty());
+    }
+
+    #[test]
+    fn test_block_indexes() {
+        let (mut dag, genesis, _dir) = new_test_dag(DagGenesisConfig::dev().k);
+        let blocks = new_sync_shape_dag(&mut dag, genesis.hash());
+        let all_blocks = dag.blocks_by_blue_score(0, u64::MAX).unwrap();
+        assert_eq!(all_blocks.len(), blocks.len() + 1);
+        assert_eq!(all_blocks[0], genesis.hash());
+        for (low, high) in [(1, 1), (2, 4), (5, u64::MAX)] {
+            let expected: HashSet<_> = all_blocks
+                .iter()
+                .copied()
+                .filter(|hash| {
+                    let blue_score = dag.get_ghostdag_data(*hash).unwrap().blue_score;
+                    (low..=high).contains(&blue_score)
+                })
+                .collect();
+            let indexed = dag.blocks_by_blue_score(low, high).unwrap();
+            assert_eq!(indexed.len(), expected.len());
+            assert_eq!(indexed.into_iter().collect::<HashSet<_>>(), expected);
+        }
+
+        let timestamps: Vec<_> = dag
+            .blocks_by_timestamp(0, u64::MAX)
+            .unwrap()
+            .iter()
+            .map(|hash| dag.get_block_header(*hash).unwrap().timestamp())
+            .collect();
+        assert_eq!(timestamps.len(), all_blocks.len());
+        assert!(timestamps.windows(2).all(|pair| pair[0] <= pair[1]));
+        let (low, high) = (timestamps[3], timestamps[8]);
+        assert!(dag
+            .blocks_by_timestamp(low, high)
+            .unwrap()
+            .iter()
+            .all(|hash| (low..=high).contains(&dag.get_block_header(*hash).unwrap().timestamp())));
+
+        // Removed blocks leave the indexes
+        let removed = dag.remove_block_and_future(blocks["h"]).unwrap();
+        let remaining = dag.blocks_by_blue_score(0, u64::MAX).unwrap();
+        assert_eq!(remaining.len(), all_blocks.len() - removed.len());
+        assert!(remaining.iter().all(|hash| !removed.contains(hash)));
+        assert_eq!(
+            dag.blocks_by_timestamp(0, u64::MAX).unwrap().len(),
+            remaining.len()
+        );
+    }
+
+    #[test]
+    fn test_remove_block_and_future() {
+        let (mut dag, genesis, _dir) = new_test_dag(DagGenesisConfig::dev().k);
+        let blocks = new_sync_shape_dag(&mut dag, genesis.hash());
+        let hashes =
+            |names: &[&str]| -> HashSet<Hash> { names.iter().map(|name| blocks[name]).collect() };
+        let h_header = dag.get_block_header(blocks["h"]).unwrap();
+        assert!(check_storage(&dag.storage).unwrap().is_consistent());
+
+        let removed = dag.remove_block_and_future(blocks["h"]).unwrap();
+        let report = check_storage(&dag.storage).unwrap();
+        assert!(report.is_consistent(), "{:?}", report.issues);
+        assert_eq!(
+            removed.iter().copied().collect::<HashSet<_>>(),
+            hashes(&["h", "j", "k", "m", "p", "v"])
+        );
+        assert_eq!(removed[0], blocks["h"]);
+        for hash in removed.iter() {
+            assert!(!dag.relations_store.has(*hash).unwrap());
+            assert!(!dag.ghostdag_store.has(*hash).unwrap());
+            assert!(!dag.header_store.has(*hash).unwrap());
+            assert!(!dag.reachability_store.has(*hash).unwrap());
+            assert_eq!(dag.get_block_status(*hash).unwrap(), None);
+            assert_eq!(dag.chain_index_of(*hash).unwrap(), None);
+        }
+
+        // The children of the remaining parents, the tips and the chain are repaired
+        assert_eq!(dag.get_children(blocks["e"]).unwrap(), vec![blocks["i"]]);
+        assert_eq!(dag.get_children(blocks["c"]).unwrap(), vec![blocks["f"]]);
+        assert_eq!(dag.get_children(blocks["d"]).unwrap(), vec![blocks["l"]]);
+        assert_eq!(
+            dag.tips_store
+                .get()
+                .unwrap()
+                .iter()
+                .copied()
+                .collect::<HashSet<_>>(),
+            hashes(&["f", "l"])
+        );
+        let chain = dag
+            .chain_blocks(0..u64::MAX)
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            *chain.last().unwrap(),
+            dag.ghostdag_manager
+                .find_selected_parent([blocks["f"], blocks["l"]])
+        );
+
+        // The dag keeps working, removed blocks can even be added back
+        dag.commit_header(&h_header);
+        let tip = add_test_block(&mut dag, vec![blocks["f"], blocks["h"], blocks["l"]]);
+        assert!(dag.is_in_past(blocks["h"], tip).unwrap());
+        assert!(dag.is_in_past(blocks["c"], tip).unwrap());
+        assert!(dag.is_in_anticone(blocks["f"], blocks["h"]).unwrap());
+        assert_eq!(dag.chain_block_by_blue_score(u64::MAX).unwrap(), tip);
+
+        assert!(check_storage(&dag.storage).unwrap().is_consistent());
+
+        assert!(dag.remove_block_and_future(genesis.hash()).is_err());
+        assert!(dag.remove_block_and_future(blocks["p"]).is_err());
+    }
+
+    #[test]
+    fn test_rebuild_dag_data() {
+        let k = DagGenesisConfig::dev().k;
+        let (mut dag, genesis, _dir) = new_test_dag(k);
+        let blocks = new_sync_shape_dag(&mut dag, genesis.hash());
+        let snapshot = |dag: &BlockDAG| {
+            let mut data: Vec<_> = blocks
+                .values()
+                .map(|hash| {
+                    let data = dag.ghostdag_store.get_data(*hash).unwrap();
+                    let mut blues = (*data.mergeset_blues).clone();
+                    blues.sort();
+                    let mut reds = (*data.mergeset_reds).clone();
+                    reds.sort();
+                    (*hash, data.blue_score, data.selected_parent, blues, reds)
+                })
+                .collect();
+            data.sort();
+            data
+        };
+        let chain = |dag: &BlockDAG| {
+            dag.chain_blocks(0..u64::MAX)
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap()
+        };
+        let (expected_data, expected_chain) = (snapshot(&dag), chain(&dag));
+
+        let mut reports = vec![];
+        let config = RebuildConfig { k, batch_size: 5 };
+        rebuild_dag_data(&dag.storage, config, |progress| reports.push(progress)).unwrap();
+        assert_eq!(
+            reports,
+            [5, 10, 14].map(|processed| RebuildProgress {
+                processed,
+                total: 14
+            })
+        );
+        assert_eq!(snapshot(&dag), expected_data);
+        assert_eq!(chain(&dag), expected_chain);
+        assert!(dag.is_in_past(blocks["c"], blocks["v"]).unwrap());
+        assert!(dag.is_in_anticone(blocks["f"], blocks["h"]).unwrap());
+        assert!(check_storage(&dag.storage).unwrap().is_consistent());
+
+        // With k = 0, only the selected parents are blue
+        let config = RebuildConfig {
+            k: 0,
+            batch_size: 100,
+        };
+        rebuild_dag_data(&dag.storage, config, |_| ()).unwrap();
+        for hash in blocks.values() {
+            let data = dag.ghostdag_store.get_data(*hash).unwrap();
+            assert!(data.mergeset_blues.len() <= 1);
+        }
+        assert!(check_storage(&dag.storage).unwrap().is_consistent());
+        // The new k is recorded, so that the dag cannot be reopened with the former one
+        assert_eq!(dag.storage.ghost_dag_store.get_k().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_commit_headers_bulk() {
+        let k = DagGenesisConfig::dev().k;
+        let (mut dag, genesis, _dir) = new_test_dag(k);
+        let blocks = new_sync_shape_dag(&mut dag, genesis.hash());
+        let headers: Vec<_> = dag
+            .sort_blocks(
+                blocks
+                    .values()
+                    .copied()
+                    .filter(|hash| *hash != genesis.hash()),
+            )
+            .into_iter()
+            .map(|hash| dag.get_block_header(hash).unwrap())
+            .collect();
+        let snapshot = |dag: &BlockDAG| {
+            let mut data: Vec<_> = blocks
+                .values()
+                .map(|hash| {
+                    let mut children = dag.get_children(*hash).unwrap();
+                    children.sort();
+                    let data = dag.get_ghostdag_data(*hash).unwrap();
+                    (
+                        *hash,
+                        (
+                            data.blue_score,
+                            data.blue_work,
+                            data.selected_parent,
+                            (*data.mergeset_blues).clone(),
+                            (*data.mergeset_reds).clone(),
+                        ),
+                        dag.get_reachability_interval(*hash).unwrap(),
+                        children,
+                        dag.get_block_status(*hash).unwrap(),
+                    )
+                })
+                .collect();
+            data.sort_by_key(|(hash, ..)| *hash);
+            let mut tips: Vec<_> = dag.tips_store.get().unwrap().iter().copied().collect();
+            tips.sort();
+            let chain = dag
+                .chain_blocks(0..u64::MAX)
+                .unwrap()
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap();
+            let by_blue_score = dag.blocks_by_blue_score(0, u64::MAX).unwrap();
+            (data, tips, chain, by_blue_score)
+        };
+
+        // Some blocks are committed one by one first, the others in two bulks
+        let (mut bulk_dag, _, _bulk_dir) = new_test_dag(k);
+        for header in headers[..3].iter() {
+            bulk_dag.commit_header(header);
+        }
+        bulk_dag.commit_headers_bulk(&headers[3..8]).unwrap();
+        bulk_dag.commit_headers_bulk(&headers[8..]).unwrap();
+        assert_eq!(snapshot(&bulk_dag), snapshot(&dag));
+        assert!(check_storage(&bulk_dag.storage).unwrap().is_consistent());
+
+        // A batch which is not sorted is rejected without writing anything
+        let (mut bulk_dag, _, _bulk_dir) = new_test_dag(k);
+        let mut unsorted = headers.clone();
+        unsorted.swap(0, headers.len() - 1);
+        assert!(bulk_dag.commit_headers_bulk(&unsorted).is_err());
+        assert!(!bulk_dag.has_block(headers[0].hash()).unwrap());
+        assert!(bulk_dag.commit_headers_bulk(&headers[..1]).is_ok());
+        assert!(bulk_dag.commit_headers_bulk(&headers[..1]).is_err());
+
+        // Headers failing verification or having an invalid parent are rejected as by
+        // `connect_block`, along with the rest of their batch
+        let invalid = new_test_header(vec![headers[0].hash(), headers[0].hash()]);
+        assert!(bulk_dag
+            .commit_headers_bulk(&[headers[1].clone(), invalid.clone()])
+            .is_err());
+        assert!(matches!(
+            bulk_dag.get_block_status(invalid.hash()).unwrap(),
+            Some(BlockStatus::Invalid(_))
+        ));
+        assert!(!bulk_dag.has_block(headers[1].hash()).unwrap());
+        let child = new_test_header(vec![headers[0].hash(), invalid.hash()]);
+        assert!(bulk_dag.commit_headers_bulk(&[child.clone()]).is_err());
+        assert_eq!(
+            bulk_dag.get_block_status(child.hash()).unwrap(),
+            Some(BlockStatus::Invalid(format!(
+                "the parent {} is invalid",
+                invalid.hash()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_render_dot() {
+        let (mut dag, genesis, _dir) = new_test_dag(DagGenesisConfig::dev().k);
+        let blocks = new_sync_shape_dag(&mut dag, genesis.hash());
+        let node = |name: &str| format!("\"{}\" [label=", blocks[name]);
+        let edge =
+            |child: &str, parent: &str| format!("\"{}\" -> \"{}\"", blocks[child], blocks[parent]);
+
+        let region = DotRegion::Range {
+            low: blocks["c"],
+            high: blocks["j"],
+        };
+        let dot = render_dot(&dag, region, blocks["j"]).unwrap();
+        assert!(dot.starts_with("digraph dag {"));
+        for name in ["b", "d", "e", "f", "h", "j"] {
+            assert!(dot.contains(&node(name)), "{name} is missing");
+        }
+        assert!(!dot.contains(&node("c")));
+        assert!(!dot.contains(&node("k")));
+        assert!(dot.contains(&edge("j", "f")));
+        assert!(!dot.contains(&edge("f", "c")));
+        let selected_parent = dag.ghostdag_store.get_selected_parent(blocks["j"]).unwrap();
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [penwidth=3]",
+            blocks["j"], selected_parent
+        )));
+
+        // Blocks outside of the past of the context are gray
+        let region = DotRegion::Around {
+            block: blocks["k"],
+            radius: 1,
+        };
+        let dot = render_dot(&dag, region, blocks["h"]).unwrap();
+        for name in ["b", "h", "i", "k", "m", "v"] {
+            assert!(dot.contains(&node(name)), "{name} is missing");
+        }
+        let line = |name: &str| {
+            dot.lines()
+                .find(|line| line.contains(&node(name)))
+                .unwrap()
+                .to_string()
+        };
+        assert!(line("h").contains("fillcolor=lightblue, penwidth=3"));
+        assert!(line("k").contains("fillcolor=lightgray"));
+        assert!(line("i").contains("fillcolor=lightgray"));
+    }
 }
@@ -0,0 +1,136 @@
+//!
+//! Rendering of dag regions in the GraphViz DOT language, for debugging sync and
+//! coloring issues.
+//!
+//! Blocks are colored as seen by a context block: blue and red blocks of its past are
+//! filled accordingly, blocks outside of its past are left gray. Edges go from a block to
+//! its parents, the edge to the selected parent being drawn bold. Labels hold the short
+//! hash, the blue score and the reachability interval of the block.
+//!
+use crate::blockdag::BlockDAG;
+use consensus_types::blockhash::{BlockHashMap, BlockHashSet};
+use starcoin_crypto::HashValue as Hash;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// The blocks to render
+#[derive(Clone, Copy, Debug)]
+pub enum DotRegion {
+    /// The blocks of `past(high) ∪ {high}` which are not in `past(low) ∪ {low}`
+    Range { low: Hash, high: Hash },
+    /// The blocks at most `radius` parent or child links away from `block`
+    Around { block: Hash, radius: usize },
+}
+
+impl DotRegion {
+    pub fn blocks(&self, dag: &BlockDAG) -> anyhow::Result<Vec<Hash>> {
+        match *self {
+            DotRegion::Range { low, high } => dag.blocks_between(low, high),
+            DotRegion::Around { block, radius } => {
+                let mut visited = BlockHashSet::from_iter([block]);
+                let mut queue = VecDeque::from([(block, 0)]);
+                while let Some((current, distance)) = queue.pop_front() {
+                    if distance == radius {
+                        continue;
+                    }
+                    let mut neighbours = dag.get_parents(current)?;
+                    neighbours.extend(dag.get_children(current)?);
+                    for neighbour in neighbours {
+                        // The origin is not a block
+                        if dag.has_block(neighbour)? && visited.insert(neighbour) {
+                            queue.push_back((neighbour, distance + 1));
+                        }
+                    }
+                }
+                Ok(dag.sort_blocks(visited))
+            }
+        }
+    }
+}
+
+/// Renders the blocks of `region` as a DOT digraph, colored as seen by `context`
+pub fn render_dot(dag: &BlockDAG, region: DotRegion, context: Hash) -> anyhow::Result<String> {
+    let blocks = region.blocks(dag)?;
+    let rendered: BlockHashSet = blocks.iter().copied().collect();
+    let colors = colors_seen_by(dag, context, &blocks)?;
+
+    let mut dot = String::new();
+    writeln!(dot, "digraph dag {{")?;
+    writeln!(dot, "    rankdir=RL;")?;
+    writeln!(
+        dot,
+        "    node [shape=box, style=filled, fontname=\"monospace\"];"
+    )?;
+    for hash in blocks.iter() {
+        let data = dag.get_ghostdag_data(*hash)?;
+        let interval = dag.get_reachability_interval(*hash)?;
+        let fill_color = match colors.get(hash) {
+            Some(true) => "lightblue",
+            Some(false) => "lightcoral",
+            None => "lightgray",
+        };
+        let border = if *hash == context { ", penwidth=3" } else { "" };
+        writeln!(
+            dot,
+            "    \"{}\" [label=\"{}\\nblue score {}\\n[{}, {}]\", fillcolor={}{}];",
+            hash,
+            short_hash(*hash),
+            data.blue_score,
+            interval.start,
+            interval.end,
+            fill_color,
+            border
+        )?;
+    }
+    for hash in blocks.iter() {
+        let selected_parent = dag.get_ghostdag_data(*hash)?.selected_parent;
+        for parent in dag.get_parents(*hash)? {
+            if !rendered.contains(&parent) {
+                continue;
+            }
+            let style = if parent == selected_parent {
+                " [penwidth=3]"
+            } else {
+                " [style=dashed]"
+            };
+            writeln!(dot, "    \"{}\" -> \"{}\"{};", hash, parent, style)?;
+        }
+    }
+    writeln!(dot, "}}")?;
+    Ok(dot)
+}
+
+/// Colors the blocks of `blocks` which are in the past of `context`, `true` standing for
+/// blue. The selected chain of `context` is walked down until every such block was found
+/// in the mergeset of a chain block.
+fn colors_seen_by(
+    dag: &BlockDAG,
+    context: Hash,
+    blocks: &[Hash],
+) -> anyhow::Result<BlockHashMap<bool>> {
+    let mut remaining = BlockHashSet::new();
+    for hash in blocks.iter() {
+        if dag.is_in_past(*hash, context)? {
+            remaining.insert(*hash);
+        }
+    }
+    let mut colors = BlockHashMap::from_iter([(context, true)]);
+    let mut current = context;
+    while !remaining.is_empty() && current != dag.get_genesis_hash() {
+        let data = dag.get_ghostdag_data(current)?;
+        for blue in data.mergeset_blues.iter() {
+            remaining.remove(blue);
+            colors.insert(*blue, true);
+        }
+        for red in data.mergeset_reds.iter() {
+            remaining.remove(red);
+            colors.insert(*red, false);
+        }
+        current = data.selected_parent;
+    }
+    Ok(colors)
+}
+
+fn short_hash(hash: Hash) -> String {
+    hash.to_hex()[..8].to_string()
+}
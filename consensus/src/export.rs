@@ -0,0 +1,198 @@
+//!
+//! Export and import of dag blocks in a portable JSON lines format.
+//!
+//! Every line holds one `ExportedBlock`, blocks being written in topological order so
+//! that a file can be imported while it is read. The ghostdag data may be included to
+//! check that the importing node colors the blocks the same way.
+//!
+use crate::blockdag::BlockDAG;
+use anyhow::{bail, Context};
+use consensus_types::{
+    blockhash::{BlockHashSet, ORIGIN},
+    ghostdata::GhostdagData,
+    header::{ConsensusHeader, Header},
+};
+use serde::{Deserialize, Serialize};
+use starcoin_crypto::HashValue as Hash;
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportedBlock {
+    pub header: Header,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ghostdag_data: Option<GhostdagData>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExportOptions {
+    /// Only export this block and its future, the whole dag is exported if `None`
+    pub from: Option<Hash>,
+    pub with_ghostdag_data: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub imported: u64,
+    /// Blocks which were in the dag already
+    pub skipped: u64,
+}
+
+/// Writes the blocks selected by `options` to `writer`, one JSON line per block in
+/// topological order. Returns the number of exported blocks.
+pub fn export_dag(
+    dag: &BlockDAG,
+    options: &ExportOptions,
+    mut writer: impl Write,
+) -> anyhow::Result<u64> {
+    let from = options.from.unwrap_or_else(|| dag.get_genesis_hash());
+    if !dag.has_block(from)? {
+        bail!("block {} is not in the dag", from);
+    }
+    let mut future = BlockHashSet::from_iter([from]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(current) = queue.pop_front() {
+        for child in dag.get_children(current)? {
+            if future.insert(child) {
+                queue.push_back(child);
+            }
+        }
+    }
+
+    let blocks = dag.sort_blocks(future);
+    for hash in blocks.iter() {
+        let ghostdag_data = if options.with_ghostdag_data {
+            Some((*dag.get_ghostdag_data(*hash)?).clone())
+        } else {
+            None
+        };
+        let block = ExportedBlock {
+            header: dag.get_block_header(*hash)?,
+            ghostdag_data,
+        };
+        serde_json::to_writer(&mut writer, &block)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(blocks.len() as u64)
+}
+
+/// Connects the blocks read from `reader` to `dag`, so that they are verified as any
+/// other block. Blocks already in the dag are skipped, and the ghostdag data of the file,
+/// when present, must match the one computed by `dag`.
+pub fn import_dag(dag: &mut BlockDAG, reader: impl BufRead) -> anyhow::Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let block: ExportedBlock = serde_json::from_str(&line)
+            .with_context(|| format!("failed to parse line {}", index + 1))?;
+        let hash = block.header.hash();
+
+        if dag.has_block(hash)? {
+            summary.skipped += 1;
+        } else {
+            for parent in block.header.parents_hash() {
+                if *parent == Hash::new(ORIGIN) {
+                    bail!("block {} is the genesis of another dag", hash);
+                }
+                if !dag.has_block(*parent)? {
+                    bail!("block {} references the unknown parent {}", hash, parent);
+                }
+            }
+            dag.connect_block(&block.header)?;
+            summary.imported += 1;
+        }
+
+        if let Some(expected) = block.ghostdag_data {
+            let actual = dag.get_ghostdag_data(hash)?;
+            if actual.blue_score != expected.blue_score
+                || actual.blue_work != expected.blue_work
+                || actual.selected_parent != expected.selected_parent
+                || actual.mergeset_blues != expected.mergeset_blues
+                || actual.mergeset_reds != expected.mergeset_reds
+            {
+                bail!(
+                    "the ghostdag data of block {} differs from the exported one: {:?} != {:?}",
+                    hash,
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::DagGenesisConfig;
+    use crate::test_util::{add_test_block, new_test_dag};
+
+    #[test]
+    fn test_export_import() {
+        let k = DagGenesisConfig::dev().k;
+        let (mut dag, genesis, _dir) = new_test_dag(k);
+        let genesis = genesis.hash();
+        let a = add_test_block(&mut dag, vec![genesis]);
+        let b = add_test_block(&mut dag, vec![genesis]);
+        let c = add_test_block(&mut dag, vec![a, b]);
+        let d = add_test_block(&mut dag, vec![b]);
+        let e = add_test_block(&mut dag, vec![c, d]);
+
+        let options = ExportOptions {
+            from: None,
+            with_ghostdag_data: true,
+        };
+        let mut file = vec![];
+        assert_eq!(export_dag(&dag, &options, &mut file).unwrap(), 6);
+
+        let (mut imported_dag, _, _imported_dir) = new_test_dag(k);
+        let summary = import_dag(&mut imported_dag, file.as_slice()).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                imported: 5,
+                skipped: 1
+            }
+        );
+        for hash in [a, b, c, d, e] {
+            assert_eq!(
+                imported_dag.get_parents(hash).unwrap(),
+                dag.get_parents(hash).unwrap()
+            );
+        }
+        assert!(imported_dag.is_in_past(b, e).unwrap());
+        // Importing again is a no-op
+        let summary = import_dag(&mut imported_dag, file.as_slice()).unwrap();
+        assert_eq!(summary.skipped, 6);
+
+        // A partial export needs the past of its first block to be imported
+        let options = ExportOptions {
+            from: Some(d),
+            with_ghostdag_data: false,
+        };
+        let mut partial_file = vec![];
+        assert_eq!(export_dag(&dag, &options, &mut partial_file).unwrap(), 2);
+        let (mut other_dag, _, _other_dir) = new_test_dag(k);
+        assert!(import_dag(&mut other_dag, partial_file.as_slice()).is_err());
+
+        // Tampered ghostdag data is detected
+        let tampered: Vec<String> = String::from_utf8(file)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let mut block: ExportedBlock = serde_json::from_str(line).unwrap();
+                if block.header.hash() == e {
+                    block.ghostdag_data.as_mut().unwrap().blue_score += 1;
+                }
+                serde_json::to_string(&block).unwrap()
+            })
+            .collect();
+        let (mut other_dag, _, _other_dir) = new_test_dag(k);
+        assert!(import_dag(&mut other_dag, tampered.join("\n").as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{bail, Context};
+use consensus_types::{
+    blockhash::{KType, ORIGIN},
+    header::Header,
+};
+use serde::{Deserialize, Serialize};
+use starcoin_crypto::HashValue as Hash;
+use starcoin_types::{
+    account_address::AccountAddress,
+    block::{BlockHeader, BlockHeaderExtra},
+    genesis_config::ChainId,
+    U256,
+};
+use std::{fmt, fs, path::Path, str::FromStr};
+
+/// Networks shipping with a built-in DAG genesis
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenesisNetwork {
+    Dev,
+    Test,
+}
+
+impl fmt::Display for GenesisNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenesisNetwork::Dev => write!(f, "dev"),
+            GenesisNetwork::Test => write!(f, "test"),
+        }
+    }
+}
+
+impl FromStr for GenesisNetwork {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dev" => Ok(GenesisNetwork::Dev),
+            "test" => Ok(GenesisNetwork::Test),
+            _ => bail!("unknown genesis network: {}", s),
+        }
+    }
+}
+
+/// The definition of a DAG genesis. Every field which ends up in the genesis
+/// header is fixed here, so that all nodes of a network derive the same genesis hash.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DagGenesisConfig {
+    pub chain_id: u8,
+    pub timestamp: u64,
+    pub difficulty: u64,
+    pub nonce: u32,
+    /// The GHOSTDAG K parameter of the network
+    pub k: KType,
+}
+
+impl DagGenesisConfig {
+    pub fn dev() -> Self {
+        Self::for_network(GenesisNetwork::Dev)
+    }
+
+    pub fn for_network(network: GenesisNetwork) -> Self {
+        match network {
+            GenesisNetwork::Dev => Self {
+                chain_id: ChainId::dev().id(),
+                timestamp: 1_690_000_000_000,
+                difficulty: 1,
+                nonce: 0,
+                k: 16,
+            },
+            GenesisNetwork::Test => Self {
+                chain_id: ChainId::test().id(),
+                timestamp: 1_700_000_000_000,
+                difficulty: 10,
+                nonce: 0,
+                k: 18,
+            },
+        }
+    }
+
+    /// Loads a genesis definition from a JSON file
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read genesis file {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse genesis file {}", path.display()))
+    }
+
+    /// Resolves `source` as a built-in network name first, and as a genesis file path otherwise
+    pub fn load(source: &str) -> anyhow::Result<Self> {
+        match source.parse::<GenesisNetwork>() {
+            Ok(network) => Ok(Self::for_network(network)),
+            Err(_) => Self::load_from_file(source),
+        }
+    }
+
+    pub fn to_header(&self) -> Header {
+        Header::new(
+            BlockHeader::new(
+                Hash::zero(),
+                self.timestamp,
+                0,
+                AccountAddress::ZERO,
+                Hash::zero(),
+                Hash::zero(),
+                Hash::zero(),
+                0,
+                U256::from(self.difficulty),
+                Hash::zero(),
+                ChainId::new(self.chain_id),
+                self.nonce,
+                BlockHeaderExtra::default(),
+            ),
+            vec![Hash::new(ORIGIN)],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus_types::header::ConsensusHeader;
+
+    #[test]
+    fn test_genesis_presets() {
+        let dev = DagGenesisConfig::dev();
+        assert_eq!(dev.to_header().hash(), dev.to_header().hash());
+        assert_ne!(
+            dev.to_header().hash(),
+            DagGenesisConfig::for_network(GenesisNetwork::Test)
+                .to_header()
+                .hash()
+        );
+        assert_eq!(DagGenesisConfig::load("dev").unwrap(), dev);
+    }
+
+    #[test]
+    fn test_load_genesis_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("genesis.json");
+        let config = DagGenesisConfig {
+            nonce: 42,
+            ..DagGenesisConfig::dev()
+        };
+        fs::write(&path, serde_json::to_string(&config).unwrap()).unwrap();
+
+        let loaded = DagGenesisConfig::load(path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded, config);
+        assert_eq!(loaded.to_header().hash(), config.to_header().hash());
+        assert!(DagGenesisConfig::load("no-such-network-or-file").is_err());
+    }
+}
@@ -1 +1,7 @@
 pub mod blockdag;
+pub mod dot;
+pub mod export;
+pub mod genesis;
+pub mod rebuild;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
@@ -0,0 +1,195 @@
+//!
+//! An offline rebuild of the data derived from the dag headers.
+//!
+//! The reachability and ghostdag data are cleared, then recomputed block by block by
+//! walking the dag from genesis in topological order, each batch of blocks being written
+//! at once. This repairs corrupted data, and allows changing `k` without syncing the dag
+//! again. The selected chain index and the block indexes, which depend on the ghostdag
+//! data, are rebuilt as well.
+//!
+use crate::blockdag::DbGhostdagManager;
+use anyhow::bail;
+use consensus_types::{
+    blockhash::{BlockHashMap, ChainPath, KType, ORIGIN},
+    header::ConsensusHeader,
+};
+use database::consensus::{
+    DbHeadersStore, DbRelationsStore, GhostdagStore, GhostdagStoreReader, RelationsStoreReader,
+    SelectedChainStore, StagingGhostdagStore, StagingReachabilityStore, TipsStoreReader,
+};
+use database::prelude::{DbBatch, FlexiDagStorage};
+use ghostdag::protocol::GhostdagManager;
+use parking_lot::RwLock;
+use reachability::{inquirer, reachability_service::MTReachabilityService};
+use starcoin_crypto::HashValue as Hash;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// A ghostdag manager reading the blocks of the current batch on top of the rebuilt data
+type BatchGhostdagManager<'a> = GhostdagManager<
+    StagingGhostdagStore,
+    DbRelationsStore,
+    MTReachabilityService<StagingReachabilityStore<'a>>,
+    DbHeadersStore,
+>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RebuildConfig {
+    /// The `k` the ghostdag data is recomputed with. It is recorded in the storage, so the
+    /// dag refuses to open with another `k` afterwards
+    pub k: KType,
+    /// The number of blocks written at once, the progress being reported after each write
+    pub batch_size: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RebuildProgress {
+    pub processed: u64,
+    pub total: u64,
+}
+
+/// Recomputes the reachability and ghostdag data of every block of `storage`, the
+/// selected chain index and the block indexes, reporting the progress to `on_progress`
+/// after every written batch. The dag must not be opened while it is rebuilt.
+pub fn rebuild_dag_data(
+    storage: &FlexiDagStorage,
+    config: RebuildConfig,
+    mut on_progress: impl FnMut(RebuildProgress),
+) -> anyhow::Result<()> {
+    if config.batch_size == 0 {
+        bail!("the batch size must be positive");
+    }
+    let origin = Hash::new(ORIGIN);
+    let genesis = match storage.relations_store.get_children(origin)?.as_slice() {
+        [genesis] => *genesis,
+        children => bail!("expected a single genesis, found {:?}", children),
+    };
+
+    // The number of parents of every block, counted before anything gets cleared
+    let mut pending_parents = BlockHashMap::new();
+    let mut queue = VecDeque::from([genesis]);
+    while let Some(current) = queue.pop_front() {
+        for child in storage.relations_store.get_children(current)?.iter() {
+            if !pending_parents.contains_key(child) {
+                let header = storage.header_store.get_header(*child)?;
+                pending_parents.insert(*child, header.parents_hash().len());
+                queue.push_back(*child);
+            }
+        }
+    }
+    let total = pending_parents.len() as u64 + 1;
+
+    let mut ghostdag_store = storage.ghost_dag_store.clone();
+    ghostdag_store.delete_all()?;
+    ghostdag_store.set_k(config.k)?;
+    let block_index_store = storage.block_index_store.clone();
+    block_index_store.delete_all()?;
+    let mut reachability_store = storage.reachability_store.clone();
+    reachability_store.delete_all()?;
+    inquirer::init(&mut reachability_store)?;
+    let reachability_lock = Arc::new(RwLock::new(reachability_store));
+
+    let mut processed = 0;
+    let mut ready = VecDeque::from([genesis]);
+    while !ready.is_empty() {
+        // The blocks of the batch are staged on top of the rebuilt data, then written at once
+        let ghostdag_staging = StagingGhostdagStore::new(ghostdag_store.clone());
+        let reachability_staging = Arc::new(RwLock::new(StagingReachabilityStore::new(
+            reachability_lock.upgradable_read(),
+        )));
+        let ghostdag_manager = BatchGhostdagManager::new(
+            genesis,
+            config.k,
+            ghostdag_staging.clone(),
+            storage.relations_store.clone(),
+            storage.header_store.clone(),
+            MTReachabilityService::new(reachability_staging.clone()),
+        );
+
+        let mut indexed = Vec::with_capacity(config.batch_size);
+        while indexed.len() < config.batch_size {
+            let block = match ready.pop_front() {
+                Some(block) => block,
+                None => break,
+            };
+            let header = storage.header_store.get_header(block)?;
+            let ghostdag_data = if block == genesis {
+                ghostdag_manager.genesis_ghostdag_data()
+            } else {
+                ghostdag_manager.ghostdag(header.parents_hash())
+            };
+            ghostdag_staging.insert(block, Arc::new(ghostdag_data.clone()))?;
+            let mut mergeset = ghostdag_data.unordered_mergeset_without_selected_parent();
+            inquirer::add_block(
+                &mut *reachability_staging.write(),
+                block,
+                ghostdag_data.selected_parent,
+                &mut mergeset,
+            )?;
+            indexed.push((block, ghostdag_data.blue_score, header.timestamp()));
+
+            for child in storage.relations_store.get_children(block)?.iter() {
+                match pending_parents.get_mut(child) {
+                    Some(pending) if *pending > 0 => {
+                        *pending -= 1;
+                        if *pending == 0 {
+                            ready.push_back(*child);
+                        }
+                    }
+                    _ => bail!("the relations of block {} do not match its header", child),
+                }
+            }
+        }
+
+        // The manager holds the only other reference to the staged reachability store
+        drop(ghostdag_manager);
+        let reachability_staging = match Arc::try_unwrap(reachability_staging) {
+            Ok(reachability_staging) => reachability_staging.into_inner(),
+            Err(_) => unreachable!("the staged reachability store is still shared"),
+        };
+        let mut batch = DbBatch::default();
+        let reachability_store = reachability_staging.commit(&mut batch)?;
+        ghostdag_staging.commit(&mut batch)?;
+        for (block, blue_score, timestamp) in indexed.iter() {
+            block_index_store.insert_batch(&mut batch, *block, *blue_score, *timestamp)?;
+        }
+        storage.write_batch(batch)?;
+        drop(reachability_store);
+
+        processed += indexed.len() as u64;
+        on_progress(RebuildProgress { processed, total });
+    }
+    if processed != total {
+        bail!(
+            "only {} of {} blocks could be rebuilt, the relations do not match the headers",
+            processed,
+            total
+        );
+    }
+
+    // The selected chain is walked down from the heaviest tip
+    let ghostdag_manager = DbGhostdagManager::new(
+        genesis,
+        config.k,
+        ghostdag_store.clone(),
+        storage.relations_store.clone(),
+        storage.header_store.clone(),
+        MTReachabilityService::new(reachability_lock),
+    );
+    let tips = storage.tips_store.get()?;
+    let mut added = vec![];
+    let mut current = ghostdag_manager.find_selected_parent(tips.iter().copied());
+    while current != genesis {
+        added.push(current);
+        current = ghostdag_store.get_selected_parent(current)?;
+    }
+    added.reverse();
+    let mut selected_chain_store = storage.selected_chain_store.clone();
+    selected_chain_store.delete_all()?;
+    selected_chain_store.init(genesis)?;
+    selected_chain_store.apply_changes(&ChainPath {
+        added,
+        removed: vec![],
+    })?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//!
+//! Helpers shared by the tests and the benches of the crate.
+//!
+use crate::{blockdag::BlockDAG, genesis::DagGenesisConfig};
+use consensus_types::{
+    blockhash::KType,
+    header::{parents_commitment, Header},
+};
+use database::prelude::{FlexiDagStorage, FlexiDagStorageConfig};
+use starcoin_crypto::HashValue as Hash;
+use starcoin_types::{
+    account_address::AccountAddress,
+    block::{BlockHeader, BlockHeaderExtra},
+    genesis_config::ChainId,
+    U256,
+};
+
+/// A dag with the dev genesis and the given `k`, stored in a directory removed along with
+/// the returned `TempDir`
+pub fn new_test_dag(k: KType) -> (BlockDAG, Header, tempfile::TempDir) {
+    let db_tempdir = tempfile::tempdir().unwrap();
+    let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+    let db = FlexiDagStorage::create_from_path(db_tempdir.path(), config)
+        .expect("Failed to create flexidag storage");
+    let genesis = DagGenesisConfig::dev().to_header();
+    let dag = BlockDAG::new(genesis.clone(), k, db).unwrap();
+    (dag, genesis, db_tempdir)
+}
+
+/// A header of difficulty 1, which always meets its difficulty
+pub fn new_test_header(parents: Vec<Hash>) -> Header {
+    new_test_header_with_difficulty(parents, U256::one())
+}
+
+/// A header committing to its parents, made distinct from the others by a random state root
+pub fn new_test_header_with_difficulty(parents: Vec<Hash>, difficulty: U256) -> Header {
+    let block_header = BlockHeader::new(
+        parents_commitment(&parents),
+        0,
+        0,
+        AccountAddress::ZERO,
+        Hash::zero(),
+        Hash::zero(),
+        Hash::random(),
+        0,
+        difficulty,
+        Hash::zero(),
+        ChainId::test(),
+        0,
+        BlockHeaderExtra::default(),
+    );
+    Header::new(block_header, parents)
+}
+
+pub fn add_test_block(dag: &mut BlockDAG, parents: Vec<Hash>) -> Hash {
+    let block = new_test_header(parents);
+    dag.commit_header(&block);
+    block.hash()
+}
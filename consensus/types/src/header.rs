@@ -25,9 +25,28 @@ impl Header {
         }
     }
 
+    pub fn block_header(&self) -> &BlockHeader {
+        &self.block_header
+    }
+
     pub fn genesis_hash(&self) -> Hash {
         Hash::new(ORIGIN)
     }
+
+    /// Whether the parent hash of the header is the commitment to its dag parents. The id
+    /// of a header does not cover the dag parents otherwise.
+    pub fn commits_to_parents(&self) -> bool {
+        self.block_header.parent_hash() == parents_commitment(&self.parents_hash)
+    }
+}
+
+/// The commitment to the dag parents a header carries in place of its parent hash
+pub fn parents_commitment(parents: &[Hash]) -> Hash {
+    let bytes: Vec<u8> = parents
+        .iter()
+        .flat_map(|parent| parent.as_slice().iter().copied())
+        .collect();
+    Hash::sha3_256_of(&bytes)
 }
 
 impl ConsensusHeader for Header {
@@ -5,4 +5,5 @@ pub mod interval;
 pub mod ordering;
 pub mod perf;
 pub mod reachability;
+pub mod status;
 pub mod trusted;
@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+/// The validation status of a block known to the dag
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockStatus {
+    /// The header is valid and committed to the dag, the body was not validated yet
+    HeaderOnly,
+    /// The block was fully validated
+    Valid,
+    /// The block failed validation, or has an invalid parent, for the given reason.
+    /// Invalid blocks are never committed to the dag.
+    Invalid(String),
+    /// The block is committed to the dag and can be merged, but lost its place in the
+    /// selected chain, for the given reason
+    Disqualified(String),
+}
+
+impl BlockStatus {
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, BlockStatus::Invalid(_))
+    }
+
+    /// Whether the block was committed to the dag
+    pub fn is_committed(&self) -> bool {
+        !self.is_invalid()
+    }
+}
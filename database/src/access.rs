@@ -1,15 +1,37 @@
-use crate::{cache::DagCache, db::DBStorage, errors::StoreError};
+use crate::{
+    cache::{ByteBudgetCache, CachedBytes, DagCache, DbCache},
+    db::DBStorage,
+    errors::StoreError,
+    format::{Record, RecordFormat},
+    metrics::StoreCounters,
+};
 
 use super::prelude::{Cache, DbWriter};
 use itertools::Itertools;
-use rocksdb::{Direction, IteratorMode, ReadOptions};
+use rocksdb::{DBPinnableSlice, Direction, IteratorMode, ReadOptions};
 use serde::{de::DeserializeOwned, Serialize};
-use starcoin_storage::storage::RawDBStorage;
 use std::{
     collections::hash_map::RandomState, error::Error, hash::BuildHasher, marker::PhantomData,
-    sync::Arc,
+    ops::Deref, sync::Arc,
 };
 
+/// The encoded entry of a `CachedDbAccess`, borrowed from the cache or from the db
+pub enum RecordBytes<'a> {
+    Cached(CachedBytes),
+    Pinned(DBPinnableSlice<'a>),
+}
+
+impl Deref for RecordBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            RecordBytes::Cached(bytes) => bytes,
+            RecordBytes::Pinned(slice) => slice,
+        }
+    }
+}
+
 /// A concurrent DB store access with typed caching.
 #[derive(Clone)]
 pub struct CachedDbAccess<TKey, TData, S = RandomState>
@@ -20,7 +42,10 @@ where
     db: Arc<DBStorage>,
 
     // Cache
-    cache: Cache<TKey>,
+    cache: DbCache<TKey>,
+
+    // Counters shared with the other accesses of the prefix
+    counters: Arc<StoreCounters>,
 
     // DB bucket/path
     prefix: &'static str,
@@ -30,62 +55,160 @@ where
 
 impl<TKey, TData, S> CachedDbAccess<TKey, TData, S>
 where
-    TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]>,
+    TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]> + 'static,
     TData: Clone + Send + Sync + DeserializeOwned,
     S: BuildHasher + Default,
 {
+    /// Creates an access whose cache holds up to `cache_size` entries, unless the storage
+    /// has a byte budget which the cache then shares
     pub fn new(db: Arc<DBStorage>, cache_size: u64, prefix: &'static str) -> Self {
+        let cache = match db.cache_budget() {
+            Some(budget) => DbCache::Bytes(ByteBudgetCache::new(budget.clone())),
+            None => DbCache::Entries(Cache::new_with_capacity(cache_size)),
+        };
         Self {
+            counters: db.store_counters(prefix),
             db,
-            cache: Cache::new_with_capacity(cache_size),
+            cache,
             prefix,
             _phantom: Default::default(),
         }
     }
 
+    /// Accounts the cached entries in the byte budget of the storage with `estimate_size`,
+    /// which estimates the memory used by the decoded data of an entry from its bytes
+    pub fn with_size_estimate(mut self, estimate_size: fn(&[u8]) -> usize) -> Self {
+        if let DbCache::Bytes(cache) = self.cache {
+            self.cache = DbCache::Bytes(cache.with_size_estimate(estimate_size));
+        }
+        self
+    }
+
     pub fn read_from_cache(&self, key: TKey) -> Result<Option<TData>, StoreError>
     where
         TKey: Copy + AsRef<[u8]>,
     {
-        self.cache
-            .get(&key)
-            .map(|b| bincode::deserialize(&b).map_err(StoreError::DeserializationError))
-            .transpose()
+        match self.cache.get(&key) {
+            Some(data) => {
+                self.counters.hit();
+                Ok(Some(self.counters.deserialize(&data)?))
+            }
+            None => {
+                self.counters.miss();
+                Ok(None)
+            }
+        }
     }
 
     pub fn has(&self, key: TKey) -> Result<bool, StoreError>
     where
         TKey: Clone + AsRef<[u8]>,
     {
-        Ok(self.cache.contains_key(&key)
-            || self
-                .db
-                .raw_get_pinned_cf(self.prefix, key)
-                .map_err(|_| StoreError::CFNotExist(self.prefix.to_string()))?
-                .is_some())
+        if self.cache.contains_key(&key) {
+            self.counters.hit();
+            return Ok(true);
+        }
+        self.counters.miss();
+        self.counters.db_read();
+        Ok(self
+            .db
+            .raw_get_pinned_cf(self.prefix, key)
+            .map_err(|_| StoreError::CFNotExist(self.prefix.to_string()))?
+            .is_some())
     }
 
     pub fn read(&self, key: TKey) -> Result<TData, StoreError>
     where
         TKey: Clone + AsRef<[u8]> + ToString,
         TData: DeserializeOwned, // We need `DeserializeOwned` since the slice coming from `db.get_pinned_cf` has short lifetime
+    {
+        self.read_with(key, |bytes| Ok(bincode::deserialize(bytes)?))
+    }
+
+    /// Decodes the entry of `key` with `decode`, straight from the cached bytes or from
+    /// the slice pinned by the db
+    pub fn read_with<R>(
+        &self,
+        key: TKey,
+        decode: impl FnOnce(&[u8]) -> Result<R, StoreError>,
+    ) -> Result<R, StoreError>
+    where
+        TKey: Clone + AsRef<[u8]> + ToString,
+    {
+        let bytes = self.read_bytes(key)?;
+        self.counters.decode(&bytes, decode)
+    }
+
+    /// The encoded entry of `key`, shared with the cache or pinned by the db. A miss is
+    /// copied once, into the cache; hits copy nothing when the cache has a byte budget.
+    pub fn read_bytes(&self, key: TKey) -> Result<RecordBytes<'_>, StoreError>
+    where
+        TKey: Clone + AsRef<[u8]> + ToString,
     {
         if let Some(data) = self.cache.get(&key) {
-            let data = bincode::deserialize(&data)?;
-            Ok(data)
-        } else if let Some(slice) = self
+            self.counters.hit();
+            return Ok(RecordBytes::Cached(data));
+        }
+        self.counters.miss();
+        self.counters.db_read();
+        if let Some(slice) = self
             .db
             .raw_get_pinned_cf(self.prefix, &key)
             .map_err(|_| StoreError::CFNotExist(self.prefix.to_string()))?
         {
-            let data: TData = bincode::deserialize(&slice)?;
-            self.cache.insert(key, slice.to_vec());
-            Ok(data)
+            self.cache.insert(key, slice.to_vec().into());
+            Ok(RecordBytes::Pinned(slice))
         } else {
             Err(StoreError::KeyNotFound(key.to_string()))
         }
     }
 
+    /// Reads the entries of `keys` in order, the cache misses being fetched from the db in
+    /// a single batched lookup
+    pub fn read_many(&self, keys: &[TKey]) -> Result<Vec<TData>, StoreError>
+    where
+        TKey: Clone + AsRef<[u8]> + ToString,
+        TData: DeserializeOwned,
+    {
+        self.read_many_with(keys, |bytes| Ok(bincode::deserialize(bytes)?))
+    }
+
+    /// Like `read_many`, the entries being decoded with `decode`
+    pub fn read_many_with<R>(
+        &self,
+        keys: &[TKey],
+        decode: impl Fn(&[u8]) -> Result<R, StoreError>,
+    ) -> Result<Vec<R>, StoreError>
+    where
+        TKey: Clone + AsRef<[u8]> + ToString,
+    {
+        let mut entries = Vec::with_capacity(keys.len());
+        let mut missing = vec![];
+        for (index, key) in keys.iter().enumerate() {
+            if let Some(data) = self.cache.get(key) {
+                self.counters.hit();
+                entries.push(Some(self.counters.decode(&data, &decode)?));
+            } else {
+                self.counters.miss();
+                missing.push(index);
+                entries.push(None);
+            }
+        }
+        if !missing.is_empty() {
+            let slices = self
+                .db
+                .raw_multi_get_cf(self.prefix, missing.iter().map(|index| &keys[*index]))?;
+            for (index, slice) in missing.into_iter().zip(slices) {
+                self.counters.db_read();
+                let key = &keys[index];
+                let slice = slice.ok_or_else(|| StoreError::KeyNotFound(key.to_string()))?;
+                entries[index] = Some(self.counters.decode(&slice, &decode)?);
+                self.cache.insert(key.clone(), slice.to_vec().into());
+            }
+        }
+        Ok(entries.into_iter().flatten().collect())
+    }
+
     pub fn iterator(
         &self,
     ) -> Result<impl Iterator<Item = Result<(Box<[u8]>, TData), Box<dyn Error>>> + '_, StoreError>
@@ -99,7 +222,7 @@ where
             .map_err(|e| StoreError::CFNotExist(e.to_string()))?;
 
         Ok(db_iterator.map(|iter_result| match iter_result {
-            Ok((key, data_bytes)) => match bincode::deserialize(&data_bytes) {
+            Ok((key, data_bytes)) => match self.read_iterated(&data_bytes) {
                 Ok(data) => Ok((key, data)),
                 Err(e) => Err(e.into()),
             },
@@ -107,14 +230,29 @@ where
         }))
     }
 
-    pub fn write(&self, mut writer: impl DbWriter, key: TKey, data: TData) -> Result<(), StoreError>
+    pub fn write(&self, writer: impl DbWriter, key: TKey, data: TData) -> Result<(), StoreError>
     where
-        TKey: Clone + AsRef<[u8]>,
+        TKey: Clone + AsRef<[u8]> + 'static,
         TData: Serialize,
     {
-        let bin_data = bincode::serialize(&data)?;
-        self.cache.insert(key.clone(), bin_data.clone());
-        writer.put(self.prefix, key.as_ref(), bin_data)?;
+        self.write_bytes(writer, key, bincode::serialize(&data)?)
+    }
+
+    /// Writes the already encoded `bytes` of the entry of `key`
+    pub fn write_bytes(
+        &self,
+        mut writer: impl DbWriter,
+        key: TKey,
+        bytes: Vec<u8>,
+    ) -> Result<(), StoreError>
+    where
+        TKey: Clone + AsRef<[u8]> + 'static,
+    {
+        // The cache is only updated once the write is in the db
+        writer.put(self.prefix, key.as_ref(), bytes.clone())?;
+        self.counters.write(bytes.len());
+        let cache = self.cache.clone();
+        writer.update_cache(Box::new(move || cache.insert(key, bytes.into())));
         Ok(())
     }
 
@@ -124,42 +262,72 @@ where
         iter: &mut (impl Iterator<Item = (TKey, TData)> + Clone),
     ) -> Result<(), StoreError>
     where
-        TKey: Clone + AsRef<[u8]>,
+        TKey: Clone + AsRef<[u8]> + 'static,
         TData: Serialize,
     {
+        let mut entries = vec![];
         for (key, data) in iter {
             let bin_data = bincode::serialize(&data)?;
-            self.cache.insert(key.clone(), bin_data.clone());
-            writer.put(self.prefix, key.as_ref(), bin_data)?;
+            writer.put(self.prefix, key.as_ref(), bin_data.clone())?;
+            self.counters.write(bin_data.len());
+            entries.push((key, bin_data));
         }
+        let cache = self.cache.clone();
+        writer.update_cache(Box::new(move || {
+            entries
+                .into_iter()
+                .for_each(|(key, bin_data)| cache.insert(key, bin_data.into()))
+        }));
         Ok(())
     }
 
-    /// Write directly from an iterator and do not cache any data. NOTE: this action also clears the cache
+    /// Write directly from an iterator and do not cache any data. NOTE: this action also
+    /// evicts the written entries from the cache
     pub fn write_many_without_cache(
         &self,
-        mut writer: impl DbWriter,
+        writer: impl DbWriter,
         iter: &mut impl Iterator<Item = (TKey, TData)>,
     ) -> Result<(), StoreError>
     where
-        TKey: Clone + AsRef<[u8]>,
+        TKey: Clone + AsRef<[u8]> + 'static,
         TData: Serialize,
     {
-        for (key, data) in iter {
-            let bin_data = bincode::serialize(&data)?;
-            writer.put(self.prefix, key.as_ref(), bin_data)?;
+        let encoded = iter
+            .map(|(key, data)| Ok((key, bincode::serialize(&data)?)))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        self.write_bytes_many_without_cache(writer, &mut encoded.into_iter())
+    }
+
+    /// Like `write_many_without_cache`, the entries being already encoded
+    pub fn write_bytes_many_without_cache(
+        &self,
+        mut writer: impl DbWriter,
+        iter: &mut impl Iterator<Item = (TKey, Vec<u8>)>,
+    ) -> Result<(), StoreError>
+    where
+        TKey: Clone + AsRef<[u8]> + 'static,
+    {
+        let mut keys = vec![];
+        for (key, bytes) in iter {
+            let len = bytes.len();
+            writer.put(self.prefix, key.as_ref(), bytes)?;
+            self.counters.write(len);
+            keys.push(key);
         }
-        // The cache must be cleared in order to avoid invalidated entries
-        self.cache.remove_all();
+        // The written entries must be evicted in order to avoid invalidated entries
+        let cache = self.cache.clone();
+        writer.update_cache(Box::new(move || cache.remove_many(&mut keys.into_iter())));
         Ok(())
     }
 
     pub fn delete(&self, mut writer: impl DbWriter, key: TKey) -> Result<(), StoreError>
     where
-        TKey: Clone + AsRef<[u8]>,
+        TKey: Clone + AsRef<[u8]> + 'static,
     {
-        self.cache.remove(&key);
         writer.delete(self.prefix, key.as_ref())?;
+        self.counters.delete();
+        let cache = self.cache.clone();
+        writer.update_cache(Box::new(move || cache.remove(&key)));
         Ok(())
     }
 
@@ -169,21 +337,22 @@ where
         key_iter: &mut (impl Iterator<Item = TKey> + Clone),
     ) -> Result<(), StoreError>
     where
-        TKey: Clone + AsRef<[u8]>,
+        TKey: Clone + AsRef<[u8]> + 'static,
     {
-        let key_iter_clone = key_iter.clone();
-        self.cache.remove_many(key_iter);
-        for key in key_iter_clone {
+        let keys = key_iter.collect_vec();
+        for key in keys.iter() {
             writer.delete(self.prefix, key.as_ref())?;
+            self.counters.delete();
         }
+        let cache = self.cache.clone();
+        writer.update_cache(Box::new(move || cache.remove_many(&mut keys.into_iter())));
         Ok(())
     }
 
     pub fn delete_all(&self, mut writer: impl DbWriter) -> Result<(), StoreError>
     where
-        TKey: Clone + AsRef<[u8]>,
+        TKey: Clone + AsRef<[u8]> + 'static,
     {
-        self.cache.remove_all();
         let keys = self
             .db
             .raw_iterator_cf_opt(self.prefix, IteratorMode::Start, ReadOptions::default())
@@ -195,10 +364,19 @@ where
             .collect_vec();
         for key in keys {
             writer.delete(self.prefix, key?.as_ref())?;
+            self.counters.delete();
         }
+        let cache = self.cache.clone();
+        writer.update_cache(Box::new(move || cache.remove_all()));
         Ok(())
     }
 
+    /// Deserializes an entry read by an iterator, bypassing the cache
+    fn read_iterated(&self, bytes: &[u8]) -> bincode::Result<TData> {
+        self.counters.db_read();
+        self.counters.deserialize(bytes)
+    }
+
     /// A dynamic iterator that can iterate through a specific prefix, and from a certain start point.
     //TODO: loop and chain iterators for multi-prefix iterator.
     pub fn seek_iterator(
@@ -229,13 +407,99 @@ where
         }
 
         Ok(db_iterator.take(limit).map(move |item| match item {
-            Ok((key_bytes, value_bytes)) => {
-                match bincode::deserialize::<TData>(value_bytes.as_ref()) {
-                    Ok(value) => Ok((key_bytes, value)),
-                    Err(err) => Err(err.into()),
-                }
-            }
+            Ok((key_bytes, value_bytes)) => match self.read_iterated(value_bytes.as_ref()) {
+                Ok(value) => Ok((key_bytes, value)),
+                Err(err) => Err(err.into()),
+            },
             Err(err) => Err(err.into()),
         }))
     }
 }
+
+/// A `CachedDbAccess` to records encoded with the record format of the db
+#[derive(Clone)]
+pub struct RecordAccess<TKey, TData>
+where
+    TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]>,
+    TData: Clone + Send + Sync + Record,
+{
+    access: CachedDbAccess<TKey, TData>,
+    format: RecordFormat,
+}
+
+impl<TKey, TData> RecordAccess<TKey, TData>
+where
+    TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]> + ToString + 'static,
+    TData: Clone + Send + Sync + Record,
+{
+    pub fn new(db: Arc<DBStorage>, cache_size: u64, prefix: &'static str) -> Self {
+        Self {
+            format: db.record_format(),
+            access: CachedDbAccess::new(db, cache_size, prefix),
+        }
+    }
+
+    pub fn format(&self) -> RecordFormat {
+        self.format
+    }
+
+    pub fn has(&self, key: TKey) -> Result<bool, StoreError> {
+        self.access.has(key)
+    }
+
+    pub fn read(&self, key: TKey) -> Result<TData, StoreError> {
+        self.access
+            .read_with(key, |bytes| self.format.decode(bytes))
+    }
+
+    /// The encoded record of `key`, see `CachedDbAccess::read_bytes`
+    pub fn read_bytes(&self, key: TKey) -> Result<RecordBytes<'_>, StoreError> {
+        self.access.read_bytes(key)
+    }
+
+    /// Reads the fixed size fields of the record of `key`, in place with the fixed layout
+    pub fn read_fields(&self, key: TKey) -> Result<TData::Fields, StoreError> {
+        self.access
+            .read_with(key, |bytes| self.format.decode_fields::<TData>(bytes))
+    }
+
+    pub fn read_many(&self, keys: &[TKey]) -> Result<Vec<TData>, StoreError> {
+        self.access
+            .read_many_with(keys, |bytes| self.format.decode(bytes))
+    }
+
+    pub fn write(&self, writer: impl DbWriter, key: TKey, data: TData) -> Result<(), StoreError> {
+        self.access
+            .write_bytes(writer, key, self.format.encode(&data)?)
+    }
+
+    /// Writes the records of `iter` and evicts them from the cache, see
+    /// `CachedDbAccess::write_many_without_cache`
+    pub fn write_many_without_cache(
+        &self,
+        writer: impl DbWriter,
+        iter: &mut impl Iterator<Item = (TKey, TData)>,
+    ) -> Result<(), StoreError> {
+        let encoded = iter
+            .map(|(key, data)| self.format.encode(&data).map(|bytes| (key, bytes)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.access
+            .write_bytes_many_without_cache(writer, &mut encoded.into_iter())
+    }
+
+    pub fn delete(&self, writer: impl DbWriter, key: TKey) -> Result<(), StoreError> {
+        self.access.delete(writer, key)
+    }
+
+    pub fn delete_many(
+        &self,
+        writer: impl DbWriter,
+        key_iter: &mut (impl Iterator<Item = TKey> + Clone),
+    ) -> Result<(), StoreError> {
+        self.access.delete_many(writer, key_iter)
+    }
+
+    pub fn delete_all(&self, writer: impl DbWriter) -> Result<(), StoreError> {
+        self.access.delete_all(writer)
+    }
+}
@@ -0,0 +1,195 @@
+//!
+//! Backups of `FlexiDagStorage` based on RocksDB checkpoints.
+//!
+//! A checkpoint is a consistent snapshot of every column family, made of hard links to the
+//! immutable table files, so creating one does not need writes to stop. Restoring copies
+//! a backup next to the database, checks it with `check_storage` and only then swaps it in.
+//!
+use crate::{
+    consensus::RelationsStoreReader,
+    errors::{StoreError, StoreResult},
+    fsck::check_storage,
+    prelude::{FlexiDagStorage, FlexiDagStorageConfig},
+};
+use consensus_types::blockhash::ORIGIN;
+use rocksdb::checkpoint::Checkpoint;
+use starcoin_crypto::HashValue as Hash;
+use std::{fs, path::Path};
+
+impl FlexiDagStorage {
+    /// Creates a consistent checkpoint of the storage in `backup_path`, which must not
+    /// exist yet. Writes may go on while the checkpoint is created.
+    pub fn create_checkpoint<P: AsRef<Path>>(&self, backup_path: P) -> StoreResult<()> {
+        let checkpoint = Checkpoint::new(self.db.db())?;
+        checkpoint.create_checkpoint(backup_path)?;
+        Ok(())
+    }
+
+    /// Replaces the storage at `db_path` by the backup at `backup_path`, which is left
+    /// untouched. The backup must have been written with the current schema, and is
+    /// refused if it has no genesis or if the consistency checker finds any issue, the
+    /// storage at `db_path` being kept in that case.
+    pub fn restore_checkpoint<P: AsRef<Path>, Q: AsRef<Path>>(
+        backup_path: P,
+        db_path: Q,
+        config: FlexiDagStorageConfig,
+    ) -> StoreResult<()> {
+        let db_path = db_path.as_ref();
+        let staging_path = db_path.with_extension("restoring");
+        if staging_path.exists() {
+            fs::remove_dir_all(&staging_path).map_err(io_error)?;
+        }
+        copy_files(backup_path.as_ref(), &staging_path)?;
+
+        if let Err(error) = check_backup(&staging_path, config.clone()) {
+            fs::remove_dir_all(&staging_path).map_err(io_error)?;
+            return Err(error);
+        }
+
+        if !db_path.exists() {
+            return fs::rename(&staging_path, db_path).map_err(io_error);
+        }
+        // The replaced storage is only removed once the restored one opens
+        let replaced_path = db_path.with_extension("replaced");
+        if replaced_path.exists() {
+            fs::remove_dir_all(&replaced_path).map_err(io_error)?;
+        }
+        fs::rename(db_path, &replaced_path).map_err(io_error)?;
+        let restored = fs::rename(&staging_path, db_path)
+            .map_err(io_error)
+            .and_then(|()| FlexiDagStorage::open_read_only(db_path, config).map(drop));
+        if let Err(error) = restored {
+            if db_path.exists() {
+                fs::remove_dir_all(db_path).map_err(io_error)?;
+            }
+            fs::rename(&replaced_path, db_path).map_err(io_error)?;
+            return Err(error);
+        }
+        fs::remove_dir_all(&replaced_path).map_err(io_error)
+    }
+}
+
+/// Opens the copy of a backup at `path` read-only, so that a path which holds no db is
+/// refused, and checks that it has a genesis and no consistency issue
+fn check_backup(path: &Path, config: FlexiDagStorageConfig) -> StoreResult<()> {
+    let storage = FlexiDagStorage::open_read_only(path, config)?;
+    let origin = Hash::new(ORIGIN);
+    if !storage.relations_store.has(origin)?
+        || storage.relations_store.get_children(origin)?.is_empty()
+    {
+        return Err(StoreError::InconsistentBackup(
+            "the backup has no genesis".to_string(),
+        ));
+    }
+    let report = check_storage(&storage)?;
+    if !report.is_consistent() {
+        return Err(StoreError::InconsistentBackup(format!(
+            "{} issues, the first one being {:?}",
+            report.issues.len(),
+            report.issues[0]
+        )));
+    }
+    Ok(())
+}
+
+/// Copies the files of a checkpoint, which has no sub directories
+fn copy_files(from: &Path, to: &Path) -> StoreResult<()> {
+    fs::create_dir_all(to).map_err(io_error)?;
+    for entry in fs::read_dir(from).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if entry.file_type().map_err(io_error)?.is_dir() {
+            return Err(StoreError::DBIoError(format!(
+                "unexpected directory {} in a checkpoint",
+                entry.path().display()
+            )));
+        }
+        fs::copy(entry.path(), to.join(entry.file_name())).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+fn io_error(error: std::io::Error) -> StoreError {
+    StoreError::DBIoError(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        consensus::{RelationsStore, RelationsStoreReader, PARENTS_CF},
+        prelude::{CachedDbAccess, DirectDbWriter},
+    };
+    use consensus_types::blockhash::{BlockHashes, ORIGIN};
+    use starcoin_crypto::HashValue as Hash;
+
+    #[test]
+    fn test_checkpoint_and_restore() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let (db_path, backup_path) = (tempdir.path().join("dag"), tempdir.path().join("backup"));
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let mut storage = FlexiDagStorage::create_from_path(&db_path, config.clone()).unwrap();
+
+        let origin = Hash::new(ORIGIN);
+        let (a, b) = (Hash::from_u64(1), Hash::from_u64(2));
+        storage
+            .relations_store
+            .insert(origin, BlockHashes::new(vec![]))
+            .unwrap();
+        storage
+            .relations_store
+            .insert(a, BlockHashes::new(vec![origin]))
+            .unwrap();
+        storage.create_checkpoint(&backup_path).unwrap();
+        // Writes after the checkpoint are not part of the backup
+        storage
+            .relations_store
+            .insert(b, BlockHashes::new(vec![a]))
+            .unwrap();
+        drop(storage);
+
+        FlexiDagStorage::restore_checkpoint(&backup_path, &db_path, config.clone()).unwrap();
+        let storage = FlexiDagStorage::create_from_path(&db_path, config.clone()).unwrap();
+        assert!(storage.relations_store.has(a).unwrap());
+        assert!(!storage.relations_store.has(b).unwrap());
+
+        // An inconsistent backup is refused and the storage is kept
+        let parents_access =
+            CachedDbAccess::<Hash, BlockHashes>::new(storage.db.clone(), 0, PARENTS_CF);
+        parents_access
+            .write(
+                DirectDbWriter::new(&storage.db),
+                b,
+                BlockHashes::new(vec![a]),
+            )
+            .unwrap();
+        let broken_backup_path = tempdir.path().join("broken-backup");
+        storage.create_checkpoint(&broken_backup_path).unwrap();
+        drop(storage);
+        assert!(matches!(
+            FlexiDagStorage::restore_checkpoint(&broken_backup_path, &db_path, config.clone()),
+            Err(StoreError::InconsistentBackup(_))
+        ));
+        let storage = FlexiDagStorage::create_from_path(&db_path, config.clone()).unwrap();
+        assert!(!storage.relations_store.has(b).unwrap());
+        drop(storage);
+
+        // So are a backup without genesis and a directory holding no db
+        let empty_backup_path = tempdir.path().join("empty-backup");
+        FlexiDagStorage::create_from_path(tempdir.path().join("empty"), config.clone())
+            .unwrap()
+            .create_checkpoint(&empty_backup_path)
+            .unwrap();
+        assert!(matches!(
+            FlexiDagStorage::restore_checkpoint(&empty_backup_path, &db_path, config.clone()),
+            Err(StoreError::InconsistentBackup(_))
+        ));
+        let no_db_path = tempdir.path().join("no-db");
+        fs::create_dir(&no_db_path).unwrap();
+        assert!(
+            FlexiDagStorage::restore_checkpoint(&no_db_path, &db_path, config.clone()).is_err()
+        );
+        assert!(!db_path.with_extension("restoring").exists());
+        let storage = FlexiDagStorage::create_from_path(&db_path, config).unwrap();
+        assert!(storage.relations_store.has(a).unwrap());
+    }
+}
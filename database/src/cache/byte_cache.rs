@@ -0,0 +1,341 @@
+use super::DagCache;
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Weak,
+};
+
+/// The estimated memory used by an entry on top of its key and data
+const ENTRY_OVERHEAD: usize = 64;
+
+/// A memory budget in bytes shared by caches. Each cache keeps its entries in an LRU of
+/// its own, evicting its least recently used entries first when the budget is exceeded
+/// and only then the ones of the other caches.
+pub struct ByteBudget {
+    capacity: AtomicUsize,
+    used: AtomicUsize,
+    len: AtomicUsize,
+    /// The entries of the caches sharing the budget
+    shards: Mutex<Vec<Weak<dyn BudgetShard>>>,
+}
+
+/// The entries of a cache, accounted in a `ByteBudget`
+trait BudgetShard: Send + Sync {
+    /// Evicts the least recently used entry, returning false if there is none or if the
+    /// entries are locked by another thread and `wait` is false
+    fn evict_lru(&self, wait: bool) -> bool;
+}
+
+impl ByteBudget {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: AtomicUsize::new(capacity),
+            used: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            shards: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// The estimated bytes used by the entries of all the caches
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Changes the budget, evicting entries right away when it shrinks
+    pub fn resize(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        self.evict_shards(None, true);
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.used() > self.capacity()
+    }
+
+    fn charge(&self, size: usize) {
+        self.used.fetch_add(size, Ordering::Relaxed);
+        self.len.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn release(&self, size: usize, len: usize) {
+        self.used.fetch_sub(size, Ordering::Relaxed);
+        self.len.fetch_sub(len, Ordering::Relaxed);
+    }
+
+    fn register(&self, shard: Weak<dyn BudgetShard>) {
+        let mut shards = self.shards.lock();
+        shards.retain(|shard| shard.strong_count() > 0);
+        shards.push(shard);
+    }
+
+    /// Evicts the least recently used entry of every cache but `skip` in turn, until the
+    /// budget is met
+    fn evict_shards(&self, skip: Option<*const ()>, wait: bool) {
+        let shards: Vec<_> = self
+            .shards
+            .lock()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|shard| Some(Arc::as_ptr(shard) as *const ()) != skip)
+            .collect();
+        while self.is_exceeded() {
+            let mut evicted = false;
+            for shard in shards.iter() {
+                if !self.is_exceeded() {
+                    return;
+                }
+                evicted |= shard.evict_lru(wait);
+            }
+            if !evicted {
+                return;
+            }
+        }
+    }
+}
+
+struct ShardEntries<TKey> {
+    /// The data of the entries along with their estimated size
+    entries: LruCache<TKey, (Arc<Vec<u8>>, usize)>,
+    /// The total size and count of the entries, released from the budget when the shard
+    /// is dropped
+    used: usize,
+    len: usize,
+}
+
+impl<TKey: std::hash::Hash + Eq> ShardEntries<TKey> {
+    fn put(&mut self, budget: &ByteBudget, key: TKey, data: Arc<Vec<u8>>, size: usize) {
+        self.remove(budget, &key);
+        self.entries.put(key, (data, size));
+        self.used += size;
+        self.len += 1;
+        budget.charge(size);
+    }
+
+    fn remove(&mut self, budget: &ByteBudget, key: &TKey) {
+        if let Some((_, size)) = self.entries.pop(key) {
+            self.used -= size;
+            self.len -= 1;
+            budget.release(size, 1);
+        }
+    }
+
+    fn pop_lru(&mut self, budget: &ByteBudget) -> bool {
+        match self.entries.pop_lru() {
+            Some((_, (_, size))) => {
+                self.used -= size;
+                self.len -= 1;
+                budget.release(size, 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn clear(&mut self, budget: &ByteBudget) {
+        budget.release(self.used, self.len);
+        self.entries.clear();
+        self.used = 0;
+        self.len = 0;
+    }
+}
+
+struct Shard<TKey> {
+    budget: Arc<ByteBudget>,
+    entries: Mutex<ShardEntries<TKey>>,
+}
+
+impl<TKey: std::hash::Hash + Eq + Send + Sync> BudgetShard for Shard<TKey> {
+    fn evict_lru(&self, wait: bool) -> bool {
+        let entries = if wait {
+            Some(self.entries.lock())
+        } else {
+            self.entries.try_lock()
+        };
+        entries.map_or(false, |mut entries| entries.pop_lru(&self.budget))
+    }
+}
+
+impl<TKey> Drop for Shard<TKey> {
+    fn drop(&mut self) {
+        let entries = self.entries.get_mut();
+        self.budget.release(entries.used, entries.len);
+    }
+}
+
+/// A cache whose entries are accounted in a `ByteBudget`, which may be shared with other
+/// caches
+pub struct ByteBudgetCache<TKey> {
+    shard: Arc<Shard<TKey>>,
+    /// Estimates the memory used by the decoded data of an entry from its bytes
+    estimate_size: fn(&[u8]) -> usize,
+}
+
+impl<TKey> Clone for ByteBudgetCache<TKey> {
+    fn clone(&self) -> Self {
+        Self {
+            shard: Arc::clone(&self.shard),
+            estimate_size: self.estimate_size,
+        }
+    }
+}
+
+impl<TKey: std::hash::Hash + Eq + Send + Sync + 'static> ByteBudgetCache<TKey> {
+    pub fn new(budget: Arc<ByteBudget>) -> Self {
+        let shard = Arc::new(Shard {
+            budget: budget.clone(),
+            entries: Mutex::new(ShardEntries {
+                entries: LruCache::unbounded(),
+                used: 0,
+                len: 0,
+            }),
+        });
+        let weak: Weak<dyn BudgetShard> = Arc::downgrade(&shard);
+        budget.register(weak);
+        Self {
+            shard,
+            estimate_size: <[u8]>::len,
+        }
+    }
+
+    /// Accounts the entries with `estimate_size` rather than with their encoded length
+    pub fn with_size_estimate(mut self, estimate_size: fn(&[u8]) -> usize) -> Self {
+        self.estimate_size = estimate_size;
+        self
+    }
+}
+
+impl<TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]> + 'static> DagCache
+    for ByteBudgetCache<TKey>
+{
+    type TKey = TKey;
+    type TData = Arc<Vec<u8>>;
+
+    /// Creates a cache with a budget of its own, of `size` bytes
+    fn new_with_capacity(size: u64) -> Self {
+        Self::new(Arc::new(ByteBudget::new(size as usize)))
+    }
+
+    fn get(&self, key: &Self::TKey) -> Option<Self::TData> {
+        self.shard
+            .entries
+            .lock()
+            .entries
+            .get(key)
+            .map(|(data, _)| data.clone())
+    }
+
+    fn contains_key(&self, key: &Self::TKey) -> bool {
+        self.shard.entries.lock().entries.contains(key)
+    }
+
+    fn insert(&self, key: Self::TKey, data: Self::TData) {
+        let size = key.as_ref().len() + (self.estimate_size)(&data) + ENTRY_OVERHEAD;
+        let budget = &self.shard.budget;
+        {
+            let mut entries = self.shard.entries.lock();
+            // Entries larger than the whole budget are not cached
+            if size > budget.capacity() {
+                entries.remove(budget, &key);
+                return;
+            }
+            entries.put(budget, key, data, size);
+            while budget.is_exceeded() && entries.entries.len() > 1 {
+                entries.pop_lru(budget);
+            }
+        }
+        if budget.is_exceeded() {
+            let this = Arc::as_ptr(&self.shard) as *const ();
+            budget.evict_shards(Some(this), false);
+        }
+    }
+
+    fn remove(&self, key: &Self::TKey) {
+        self.shard.entries.lock().remove(&self.shard.budget, key);
+    }
+
+    fn remove_many(&self, key_iter: &mut impl Iterator<Item = Self::TKey>) {
+        let mut entries = self.shard.entries.lock();
+        key_iter.for_each(|key| entries.remove(&self.shard.budget, &key));
+    }
+
+    fn remove_all(&self) {
+        self.shard.entries.lock().clear(&self.shard.budget);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(cache: &ByteBudgetCache<Vec<u8>>, key: u8, data_len: usize) {
+        cache.insert(vec![key], Arc::new(vec![0; data_len]));
+    }
+
+    #[test]
+    fn test_byte_budget_cache() {
+        let size = ENTRY_OVERHEAD + 1 + 100;
+        let budget = Arc::new(ByteBudget::new(3 * size));
+        let (first, second) = (
+            ByteBudgetCache::new(budget.clone()),
+            ByteBudgetCache::new(budget.clone()),
+        );
+        insert(&first, 1, 100);
+        insert(&second, 1, 100);
+        insert(&first, 2, 100);
+        assert_eq!(budget.used(), 3 * size);
+        assert!(first.contains_key(&vec![1]) && second.contains_key(&vec![1]));
+
+        // The least recently used entry of the inserting cache is evicted first
+        assert!(first.get(&vec![1]).is_some());
+        insert(&first, 3, 100);
+        assert!(!first.contains_key(&vec![2]));
+        assert!(first.contains_key(&vec![1]) && second.contains_key(&vec![1]));
+        assert_eq!(budget.used(), 3 * size);
+
+        // The other caches make room once the inserting one has a single entry left
+        insert(&second, 2, 100 + size);
+        assert_eq!(budget.len(), 2);
+        assert!(second.contains_key(&vec![2]));
+        assert!(budget.used() <= budget.capacity());
+        insert(&second, 3, 10 * size);
+        assert!(!second.contains_key(&vec![3]));
+
+        budget.resize(2 * size);
+        assert_eq!(budget.len(), 1);
+        assert!(second.contains_key(&vec![2]));
+        budget.resize(3 * size);
+
+        // Removing all the entries of a cache leaves the other ones
+        insert(&first, 1, 0);
+        second.remove_all();
+        assert_eq!(budget.len(), 1);
+        assert!(first.contains_key(&vec![1]));
+
+        // The entries of a dropped cache are released
+        drop(first);
+        assert!(budget.is_empty());
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn test_size_estimate() {
+        let budget = Arc::new(ByteBudget::new(1 << 20));
+        let cache = ByteBudgetCache::new(budget.clone()).with_size_estimate(|data| 10 * data.len());
+        insert(&cache, 1, 100);
+        assert_eq!(budget.used(), ENTRY_OVERHEAD + 1 + 1000);
+        cache.remove(&vec![1]);
+        assert_eq!(budget.used(), 0);
+    }
+}
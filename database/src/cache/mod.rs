@@ -1,9 +1,12 @@
+mod byte_cache;
 mod stc_cache;
+pub use byte_cache::*;
 pub use stc_cache::*;
+use std::{ops::Deref, sync::Arc};
 
 pub trait DagCache {
-    type TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]>;
-    type TData: Clone + Send + Sync + AsRef<[u8]>;
+    type TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]> + 'static;
+    type TData: Clone + Send + Sync;
 
     fn new_with_capacity(size: u64) -> Self;
     fn get(&self, key: &Self::TKey) -> Option<Self::TData>;
@@ -13,3 +16,121 @@ pub trait DagCache {
     fn remove_many(&self, key_iter: &mut impl Iterator<Item = Self::TKey>);
     fn remove_all(&self);
 }
+
+/// The bytes of an entry of a `DbCache`, shared with the cache when it is bounded by a
+/// byte budget so that hits copy nothing
+#[derive(Clone)]
+pub enum CachedBytes {
+    Owned(Vec<u8>),
+    Shared(Arc<Vec<u8>>),
+}
+
+impl CachedBytes {
+    fn into_vec(self) -> Vec<u8> {
+        match self {
+            CachedBytes::Owned(bytes) => bytes,
+            CachedBytes::Shared(bytes) => {
+                Arc::try_unwrap(bytes).unwrap_or_else(|bytes| (*bytes).clone())
+            }
+        }
+    }
+
+    fn into_shared(self) -> Arc<Vec<u8>> {
+        match self {
+            CachedBytes::Owned(bytes) => Arc::new(bytes),
+            CachedBytes::Shared(bytes) => bytes,
+        }
+    }
+}
+
+impl From<Vec<u8>> for CachedBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        CachedBytes::Owned(bytes)
+    }
+}
+
+impl Deref for CachedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            CachedBytes::Owned(bytes) => bytes,
+            CachedBytes::Shared(bytes) => bytes,
+        }
+    }
+}
+
+impl AsRef<[u8]> for CachedBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// The cache of a `CachedDbAccess`, bounded by a number of entries or by the byte budget
+/// of the storage
+pub enum DbCache<TKey> {
+    Entries(Cache<TKey>),
+    Bytes(ByteBudgetCache<TKey>),
+}
+
+impl<TKey> Clone for DbCache<TKey> {
+    fn clone(&self) -> Self {
+        match self {
+            DbCache::Entries(cache) => DbCache::Entries(cache.clone()),
+            DbCache::Bytes(cache) => DbCache::Bytes(cache.clone()),
+        }
+    }
+}
+
+impl<TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]> + 'static> DagCache
+    for DbCache<TKey>
+{
+    type TKey = TKey;
+    type TData = CachedBytes;
+
+    fn new_with_capacity(size: u64) -> Self {
+        DbCache::Entries(Cache::new_with_capacity(size))
+    }
+
+    fn get(&self, key: &Self::TKey) -> Option<Self::TData> {
+        match self {
+            DbCache::Entries(cache) => cache.get(key).map(CachedBytes::Owned),
+            DbCache::Bytes(cache) => cache.get(key).map(CachedBytes::Shared),
+        }
+    }
+
+    fn contains_key(&self, key: &Self::TKey) -> bool {
+        match self {
+            DbCache::Entries(cache) => cache.contains_key(key),
+            DbCache::Bytes(cache) => cache.contains_key(key),
+        }
+    }
+
+    fn insert(&self, key: Self::TKey, data: Self::TData) {
+        match self {
+            DbCache::Entries(cache) => cache.insert(key, data.into_vec()),
+            DbCache::Bytes(cache) => cache.insert(key, data.into_shared()),
+        }
+    }
+
+    fn remove(&self, key: &Self::TKey) {
+        match self {
+            DbCache::Entries(cache) => cache.remove(key),
+            DbCache::Bytes(cache) => cache.remove(key),
+        }
+    }
+
+    fn remove_many(&self, key_iter: &mut impl Iterator<Item = Self::TKey>) {
+        match self {
+            DbCache::Entries(cache) => cache.remove_many(key_iter),
+            DbCache::Bytes(cache) => cache.remove_many(key_iter),
+        }
+    }
+
+    fn remove_all(&self) {
+        match self {
+            DbCache::Entries(cache) => cache.remove_all(),
+            DbCache::Bytes(cache) => cache.remove_all(),
+        }
+    }
+}
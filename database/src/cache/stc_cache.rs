@@ -8,7 +8,9 @@ pub struct Cache<TKey> {
     _phantom: PhantomData<TKey>,
 }
 
-impl<TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]>> DagCache for Cache<TKey> {
+impl<TKey: Clone + std::hash::Hash + Eq + Send + Sync + AsRef<[u8]> + 'static> DagCache
+    for Cache<TKey>
+{
     type TKey = TKey;
     type TData = Vec<u8>;
 
@@ -0,0 +1,180 @@
+use crate::{
+    db::DBStorage,
+    errors::{StoreError, StoreResult},
+    key::U64HashKey,
+    prelude::{BatchDbWriter, CachedDbAccess, DbBatch, DirectDbWriter},
+};
+use starcoin_crypto::HashValue as Hash;
+use std::sync::Arc;
+
+/// Write API for `BlockIndexStore`. Blocks are indexed by their blue score and by their
+/// timestamp, so that the blocks of a range can be iterated in order.
+pub trait BlockIndexStore {
+    fn insert(&mut self, hash: Hash, blue_score: u64, timestamp: u64) -> StoreResult<()>;
+}
+
+pub(crate) const BLUE_SCORE_INDEX_CF: &str = "block-index-by-blue-score";
+pub(crate) const TIMESTAMP_INDEX_CF: &str = "block-index-by-timestamp";
+
+/// A DB implementation of `BlockIndexStore` trait. The index entries have no data, the
+/// keys being made of the indexed value followed by the block hash.
+#[derive(Clone)]
+pub struct DbBlockIndexStore {
+    db: Arc<DBStorage>,
+    blue_score_access: CachedDbAccess<U64HashKey, ()>,
+    timestamp_access: CachedDbAccess<U64HashKey, ()>,
+}
+
+impl DbBlockIndexStore {
+    pub fn new(db: Arc<DBStorage>) -> Self {
+        Self {
+            db: Arc::clone(&db),
+            blue_score_access: CachedDbAccess::new(db.clone(), 0, BLUE_SCORE_INDEX_CF),
+            timestamp_access: CachedDbAccess::new(db, 0, TIMESTAMP_INDEX_CF),
+        }
+    }
+
+    /// Iterates over the blocks whose blue score is in `[low, high]`, by ascending blue
+    /// score then hash
+    pub fn blue_score_range(
+        &self,
+        low: u64,
+        high: u64,
+    ) -> StoreResult<impl Iterator<Item = StoreResult<(u64, Hash)>> + '_> {
+        range(&self.blue_score_access, low, high)
+    }
+
+    /// Iterates over the blocks whose timestamp is in `[low, high]`, by ascending
+    /// timestamp then hash
+    pub fn timestamp_range(
+        &self,
+        low: u64,
+        high: u64,
+    ) -> StoreResult<impl Iterator<Item = StoreResult<(u64, Hash)>> + '_> {
+        range(&self.timestamp_access, low, high)
+    }
+
+    /// Fills `batch` with the index entries of a block
+    pub fn insert_batch(
+        &self,
+        batch: &mut DbBatch,
+        hash: Hash,
+        blue_score: u64,
+        timestamp: u64,
+    ) -> StoreResult<()> {
+        self.blue_score_access.write(
+            BatchDbWriter::new(&self.db, batch),
+            U64HashKey::new(blue_score, hash),
+            (),
+        )?;
+        self.timestamp_access.write(
+            BatchDbWriter::new(&self.db, batch),
+            U64HashKey::new(timestamp, hash),
+            (),
+        )
+    }
+
+    /// Fills `batch` with the deletion of the index entries of the blocks, given with
+    /// their blue score and timestamp
+    pub fn delete_batch(
+        &self,
+        batch: &mut DbBatch,
+        blocks: &[(Hash, u64, u64)],
+    ) -> StoreResult<()> {
+        self.blue_score_access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut blocks
+                .iter()
+                .map(|(hash, blue_score, _)| U64HashKey::new(*blue_score, *hash)),
+        )?;
+        self.timestamp_access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut blocks
+                .iter()
+                .map(|(hash, _, timestamp)| U64HashKey::new(*timestamp, *hash)),
+        )
+    }
+
+    /// Deletes the index entries of every block, to be rebuilt from scratch
+    pub fn delete_all(&self) -> StoreResult<()> {
+        self.blue_score_access
+            .delete_all(DirectDbWriter::new(&self.db))?;
+        self.timestamp_access
+            .delete_all(DirectDbWriter::new(&self.db))
+    }
+}
+
+impl BlockIndexStore for DbBlockIndexStore {
+    fn insert(&mut self, hash: Hash, blue_score: u64, timestamp: u64) -> StoreResult<()> {
+        let mut batch = DbBatch::default();
+        self.insert_batch(&mut batch, hash, blue_score, timestamp)?;
+        self.db.raw_write_batch(batch)
+    }
+}
+
+fn range(
+    access: &CachedDbAccess<U64HashKey, ()>,
+    low: u64,
+    high: u64,
+) -> StoreResult<impl Iterator<Item = StoreResult<(u64, Hash)>> + '_> {
+    let entries =
+        access.seek_iterator(Some(U64HashKey::new(low, Hash::zero())), usize::MAX, false)?;
+    Ok(entries
+        .map(|entry| {
+            let (key, ()) = entry.map_err(|e| StoreError::DBIoError(e.to_string()))?;
+            let key = U64HashKey::try_from(key.as_ref())?;
+            Ok((key.value(), key.hash()))
+        })
+        .take_while(move |entry| !matches!(entry, Ok((value, _)) if *value > high)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{FlexiDagStorage, FlexiDagStorageConfig};
+
+    #[test]
+    fn test_block_index_ranges() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+        let mut store = storage.block_index_store.clone();
+        // (hash, blue score, timestamp)
+        let blocks: Vec<_> = (1..=6)
+            .map(|i| (Hash::from_u64(i), i / 2, 1000 - i * 10))
+            .collect();
+        for (hash, blue_score, timestamp) in blocks.iter() {
+            store.insert(*hash, *blue_score, *timestamp).unwrap();
+        }
+
+        let by_blue_score: Vec<_> = store
+            .blue_score_range(1, 2)
+            .unwrap()
+            .collect::<StoreResult<_>>()
+            .unwrap();
+        let mut expected: Vec<_> = blocks
+            .iter()
+            .filter(|(_, blue_score, _)| (1..=2).contains(blue_score))
+            .map(|(hash, blue_score, _)| (*blue_score, *hash))
+            .collect();
+        expected.sort();
+        assert_eq!(by_blue_score, expected);
+
+        let by_timestamp: Vec<_> = store
+            .timestamp_range(950, 975)
+            .unwrap()
+            .collect::<StoreResult<_>>()
+            .unwrap();
+        assert_eq!(
+            by_timestamp,
+            vec![(950, blocks[4].0), (960, blocks[3].0), (970, blocks[2].0)]
+        );
+        assert_eq!(store.timestamp_range(0, 900).unwrap().count(), 0);
+
+        let mut batch = DbBatch::default();
+        store.delete_batch(&mut batch, &blocks[..2]).unwrap();
+        storage.write_batch(batch).unwrap();
+        assert_eq!(store.blue_score_range(0, u64::MAX).unwrap().count(), 4);
+        assert_eq!(store.timestamp_range(0, u64::MAX).unwrap().count(), 4);
+    }
+}
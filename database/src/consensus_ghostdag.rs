@@ -1,11 +1,11 @@
 use crate::{
     db::DBStorage,
     errors::StoreError,
-    prelude::{CachedDbAccess, DirectDbWriter},
-    writer::BatchDbWriter,
+    prelude::{CachedDbAccess, CachedDbItem, DirectDbWriter, RecordAccess},
+    writer::{BatchDbWriter, DbBatch},
 };
 use consensus_types::{
-    blockhash::{BlockHashMap, BlockHashes, BlockLevel, BlueWorkType, HashKTypeMap},
+    blockhash::{BlockHashMap, BlockHashes, BlockLevel, BlueWorkType, HashKTypeMap, KType},
     ghostdata::{CompactGhostdagData, GhostdagData},
     ordering::SortableBlock,
 };
@@ -13,9 +13,9 @@ use itertools::{
     EitherOrBoth::{Both, Left, Right},
     Itertools,
 };
-use rocksdb::WriteBatch;
+use parking_lot::RwLock;
 use starcoin_crypto::HashValue as Hash;
-use std::{cell::RefCell, cmp, iter::once, sync::Arc};
+use std::{cell::RefCell, cmp, iter::once, mem, sync::Arc};
 
 pub trait GhostdagStoreReader {
     fn get_blue_score(&self, hash: Hash) -> Result<u64, StoreError>;
@@ -30,6 +30,17 @@ pub trait GhostdagStoreReader {
 
     fn get_compact_data(&self, hash: Hash) -> Result<CompactGhostdagData, StoreError>;
 
+    /// Returns the compact data of each of `hashes`, in the same order
+    fn get_compact_data_many(
+        &self,
+        hashes: &[Hash],
+    ) -> Result<Vec<CompactGhostdagData>, StoreError> {
+        hashes
+            .iter()
+            .map(|hash| self.get_compact_data(*hash))
+            .collect()
+    }
+
     /// Check if the store contains data for the requested hash
     fn has(&self, hash: Hash) -> Result<bool, StoreError>;
 }
@@ -146,6 +157,50 @@ impl GhostDagDataWrapper {
 
 pub(crate) const GHOST_DAG_STORE_CF: &str = "block-ghostdag-data";
 pub(crate) const COMPACT_GHOST_DAG_STORE_CF: &str = "compact-block-ghostdag-data";
+const GHOSTDAG_K_KEY: &str = "ghostdag-k";
+
+/// Estimates the memory used by decoded ghostdag data, whose blues anticone sizes map
+/// takes much more room than its encoding, from the lengths written in its encoding
+fn ghostdag_data_size(bytes: &[u8]) -> usize {
+    let Some((blues, reds, anticone_sizes)) = ghostdag_data_lengths(bytes) else {
+        return bytes.len();
+    };
+    // The buckets of the map, as many as the map of std allocates for its entries
+    let buckets = match anticone_sizes {
+        0 => 0,
+        1..=3 => 4,
+        4..=7 => 8,
+        entries => (entries * 8 / 7).next_power_of_two(),
+    };
+    // The data, the hashes and the map are each behind an `Arc` with two counters
+    3 * 2 * mem::size_of::<usize>()
+        + mem::size_of::<GhostdagData>()
+        + (blues + reds) * mem::size_of::<Hash>()
+        + mem::size_of::<BlockHashMap<KType>>()
+        // Each bucket of the map also has a control byte
+        + buckets * (mem::size_of::<(Hash, KType)>() + 1)
+}
+
+/// The lengths of the mergeset blues, of the mergeset reds and of the blues anticone sizes
+/// of bincode encoded ghostdag data, read without decoding it. They follow the fixed size
+/// blue score, blue work and selected parent, each of them prefixed by its length.
+fn ghostdag_data_lengths(bytes: &[u8]) -> Option<(usize, usize, usize)> {
+    let hash_len = bincode::serialized_size(&Hash::zero()).ok()? as usize;
+    let length_at = |offset: usize| {
+        let length = bytes.get(offset..offset.checked_add(8)?)?;
+        usize::try_from(u64::from_le_bytes(length.try_into().ok()?)).ok()
+    };
+    let blues_offset = mem::size_of::<u64>() + mem::size_of::<BlueWorkType>() + hash_len;
+    let blues = length_at(blues_offset)?;
+    let reds_offset = blues_offset
+        .checked_add(8)?
+        .checked_add(blues.checked_mul(hash_len)?)?;
+    let reds = length_at(reds_offset)?;
+    let anticone_sizes_offset = reds_offset
+        .checked_add(8)?
+        .checked_add(reds.checked_mul(hash_len)?)?;
+    Some((blues, reds, length_at(anticone_sizes_offset)?))
+}
 
 /// A DB + cache implementation of `GhostdagStore` trait, with concurrency support.
 #[derive(Clone)]
@@ -153,16 +208,35 @@ pub struct DbGhostdagStore {
     db: Arc<DBStorage>,
     level: BlockLevel,
     access: CachedDbAccess<Hash, Arc<GhostdagData>>,
-    compact_access: CachedDbAccess<Hash, CompactGhostdagData>,
+    compact_access: RecordAccess<Hash, CompactGhostdagData>,
+    /// The K the ghostdag data was computed with
+    k: CachedDbItem<KType>,
 }
 
 impl DbGhostdagStore {
     pub fn new(db: Arc<DBStorage>, level: BlockLevel, cache_size: u64) -> Self {
+        Self::new_with_compact_cache(db, level, cache_size, cache_size)
+    }
+
+    /// Creates a store whose compact data, which serves the blue score, blue work and
+    /// selected parent reads, has a cache of its own of `compact_cache_size` entries
+    pub fn new_with_compact_cache(
+        db: Arc<DBStorage>,
+        level: BlockLevel,
+        cache_size: u64,
+        compact_cache_size: u64,
+    ) -> Self {
         Self {
             db: Arc::clone(&db),
             level,
-            access: CachedDbAccess::new(db.clone(), cache_size, GHOST_DAG_STORE_CF),
-            compact_access: CachedDbAccess::new(db, cache_size, COMPACT_GHOST_DAG_STORE_CF),
+            access: CachedDbAccess::new(db.clone(), cache_size, GHOST_DAG_STORE_CF)
+                .with_size_estimate(ghostdag_data_size),
+            compact_access: RecordAccess::new(
+                db.clone(),
+                compact_cache_size,
+                COMPACT_GHOST_DAG_STORE_CF,
+            ),
+            k: CachedDbItem::new(db, GHOST_DAG_STORE_CF, GHOSTDAG_K_KEY.as_bytes().to_vec()),
         }
     }
 
@@ -172,7 +246,7 @@ impl DbGhostdagStore {
 
     pub fn insert_batch(
         &self,
-        batch: &mut WriteBatch,
+        batch: &mut DbBatch,
         hash: Hash,
         data: &Arc<GhostdagData>,
     ) -> Result<(), StoreError> {
@@ -180,9 +254,9 @@ impl DbGhostdagStore {
             return Err(StoreError::KeyAlreadyExists(hash.to_string()));
         }
         self.access
-            .write(BatchDbWriter::new(batch), hash, data.clone())?;
+            .write(BatchDbWriter::new(&self.db, batch), hash, data.clone())?;
         self.compact_access.write(
-            BatchDbWriter::new(batch),
+            BatchDbWriter::new(&self.db, batch),
             hash,
             CompactGhostdagData {
                 blue_score: data.blue_score,
@@ -192,19 +266,48 @@ impl DbGhostdagStore {
         )?;
         Ok(())
     }
+
+    pub fn delete_batch(&self, batch: &mut DbBatch, hashes: &[Hash]) -> Result<(), StoreError> {
+        self.access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut hashes.iter().copied(),
+        )?;
+        self.compact_access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut hashes.iter().copied(),
+        )
+    }
+
+    /// Returns the K the ghostdag data was computed with
+    pub fn get_k(&self) -> Result<KType, StoreError> {
+        self.k.read()
+    }
+
+    pub fn set_k(&mut self, k: KType) -> Result<(), StoreError> {
+        self.k.write(DirectDbWriter::new(&self.db), &k)
+    }
+
+    /// Deletes the ghostdag data of every block and the recorded K, to be recomputed
+    /// from scratch
+    pub fn delete_all(&mut self) -> Result<(), StoreError> {
+        self.access.delete_all(DirectDbWriter::new(&self.db))?;
+        self.compact_access
+            .delete_all(DirectDbWriter::new(&self.db))?;
+        self.k.remove(DirectDbWriter::new(&self.db))
+    }
 }
 
 impl GhostdagStoreReader for DbGhostdagStore {
     fn get_blue_score(&self, hash: Hash) -> Result<u64, StoreError> {
-        Ok(self.access.read(hash)?.blue_score)
+        Ok(self.compact_access.read(hash)?.blue_score)
     }
 
     fn get_blue_work(&self, hash: Hash) -> Result<BlueWorkType, StoreError> {
-        Ok(self.access.read(hash)?.blue_work)
+        Ok(self.compact_access.read(hash)?.blue_work)
     }
 
     fn get_selected_parent(&self, hash: Hash) -> Result<Hash, StoreError> {
-        Ok(self.access.read(hash)?.selected_parent)
+        Ok(self.compact_access.read(hash)?.selected_parent)
     }
 
     fn get_mergeset_blues(&self, hash: Hash) -> Result<BlockHashes, StoreError> {
@@ -227,6 +330,13 @@ impl GhostdagStoreReader for DbGhostdagStore {
         self.compact_access.read(hash)
     }
 
+    fn get_compact_data_many(
+        &self,
+        hashes: &[Hash],
+    ) -> Result<Vec<CompactGhostdagData>, StoreError> {
+        self.compact_access.read_many(hashes)
+    }
+
     fn has(&self, hash: Hash) -> Result<bool, StoreError> {
         self.access.has(hash)
     }
@@ -255,6 +365,103 @@ impl GhostdagStore for DbGhostdagStore {
     }
 }
 
+/// Stages the GHOSTDAG data of new blocks in memory, on top of a `DbGhostdagStore`, until
+/// it is committed at once. Clones share the staged data.
+#[derive(Clone)]
+pub struct StagingGhostdagStore {
+    store: DbGhostdagStore,
+    staging: Arc<RwLock<BlockHashMap<Arc<GhostdagData>>>>,
+}
+
+impl StagingGhostdagStore {
+    pub fn new(store: DbGhostdagStore) -> Self {
+        Self {
+            store,
+            staging: Default::default(),
+        }
+    }
+
+    /// Writes the staged data into `batch`, bypassing the caches
+    pub fn commit(&self, batch: &mut DbBatch) -> Result<(), StoreError> {
+        let staging = self.staging.read();
+        self.store.access.write_many_without_cache(
+            BatchDbWriter::new(&self.store.db, batch),
+            &mut staging.iter().map(|(hash, data)| (*hash, data.clone())),
+        )?;
+        self.store.compact_access.write_many_without_cache(
+            BatchDbWriter::new(&self.store.db, batch),
+            &mut staging.iter().map(|(hash, data)| {
+                (
+                    *hash,
+                    CompactGhostdagData {
+                        blue_score: data.blue_score,
+                        blue_work: data.blue_work,
+                        selected_parent: data.selected_parent,
+                    },
+                )
+            }),
+        )
+    }
+}
+
+impl GhostdagStoreReader for StagingGhostdagStore {
+    fn get_blue_score(&self, hash: Hash) -> Result<u64, StoreError> {
+        Ok(self.get_compact_data(hash)?.blue_score)
+    }
+
+    fn get_blue_work(&self, hash: Hash) -> Result<BlueWorkType, StoreError> {
+        Ok(self.get_compact_data(hash)?.blue_work)
+    }
+
+    fn get_selected_parent(&self, hash: Hash) -> Result<Hash, StoreError> {
+        Ok(self.get_compact_data(hash)?.selected_parent)
+    }
+
+    fn get_mergeset_blues(&self, hash: Hash) -> Result<BlockHashes, StoreError> {
+        Ok(Arc::clone(&self.get_data(hash)?.mergeset_blues))
+    }
+
+    fn get_mergeset_reds(&self, hash: Hash) -> Result<BlockHashes, StoreError> {
+        Ok(Arc::clone(&self.get_data(hash)?.mergeset_reds))
+    }
+
+    fn get_blues_anticone_sizes(&self, hash: Hash) -> Result<HashKTypeMap, StoreError> {
+        Ok(Arc::clone(&self.get_data(hash)?.blues_anticone_sizes))
+    }
+
+    fn get_data(&self, hash: Hash) -> Result<Arc<GhostdagData>, StoreError> {
+        if let Some(data) = self.staging.read().get(&hash) {
+            return Ok(data.clone());
+        }
+        self.store.get_data(hash)
+    }
+
+    fn get_compact_data(&self, hash: Hash) -> Result<CompactGhostdagData, StoreError> {
+        if let Some(data) = self.staging.read().get(&hash) {
+            return Ok(CompactGhostdagData {
+                blue_score: data.blue_score,
+                blue_work: data.blue_work,
+                selected_parent: data.selected_parent,
+            });
+        }
+        self.store.get_compact_data(hash)
+    }
+
+    fn has(&self, hash: Hash) -> Result<bool, StoreError> {
+        Ok(self.staging.read().contains_key(&hash) || self.store.has(hash)?)
+    }
+}
+
+impl GhostdagStore for StagingGhostdagStore {
+    fn insert(&self, hash: Hash, data: Arc<GhostdagData>) -> Result<(), StoreError> {
+        if self.has(hash)? {
+            return Err(StoreError::KeyAlreadyExists(hash.to_string()));
+        }
+        self.staging.write().insert(hash, data);
+        Ok(())
+    }
+}
+
 /// An in-memory implementation of `GhostdagStore` trait to be used for tests.
 /// Uses `RefCell` for interior mutability in order to workaround `insert`
 /// being non-mutable.
@@ -383,6 +590,25 @@ mod tests {
     use consensus_types::blockhash::BlockHashSet;
     use std::iter::once;
 
+    #[test]
+    fn test_ghostdag_data_lengths() {
+        let mut data = GhostdagData::new_with_selected_parent(1.into(), 5);
+        data.add_blue(2.into(), 1, &Default::default());
+        data.add_blue(3.into(), 2, &Default::default());
+        data.add_red(4.into());
+        let bytes = bincode::serialize(&data).unwrap();
+        assert_eq!(
+            ghostdag_data_lengths(&bytes),
+            Some((
+                data.mergeset_blues.len(),
+                data.mergeset_reds.len(),
+                data.blues_anticone_sizes.len()
+            ))
+        );
+        assert!(ghostdag_data_size(&bytes) > bytes.len());
+        assert_eq!(ghostdag_data_lengths(&bytes[..40]), None);
+    }
+
     #[test]
     fn test_mergeset_iterators() {
         let store = MemoryGhostdagStore::new();
@@ -2,20 +2,18 @@ use crate::{
     db::DBStorage,
     errors::{StoreError, StoreResult},
     prelude::CachedDbAccess,
-    writer::{BatchDbWriter, DirectDbWriter},
+    writer::{BatchDbWriter, DbBatch, DirectDbWriter},
 };
 use consensus_types::{
-    blockhash::BlockLevel,
+    blockhash::{BlockHashMap, BlockLevel},
     header::{CompactHeaderData, ConsensusHeader, Header, HeaderWithBlockLevel},
 };
-use rocksdb::WriteBatch;
+use parking_lot::RwLock;
 use starcoin_crypto::HashValue as Hash;
 use starcoin_types::U256;
-use std::sync::Arc;
+use std::{cell::RefCell, sync::Arc};
 
 pub trait HeaderStoreReader {
-    fn get_daa_score(&self, hash: Hash) -> Result<u64, StoreError>;
-    fn get_blue_score(&self, hash: Hash) -> Result<u64, StoreError>;
     fn get_timestamp(&self, hash: Hash) -> Result<u64, StoreError>;
     fn get_difficulty(&self, hash: Hash) -> Result<U256, StoreError>;
     fn get_header(&self, hash: Hash) -> Result<Arc<Header>, StoreError>;
@@ -72,7 +70,7 @@ impl DbHeadersStore {
 
     pub fn insert_batch(
         &self,
-        batch: &mut WriteBatch,
+        batch: &mut DbBatch,
         hash: Hash,
         header: Arc<Header>,
         block_level: BlockLevel,
@@ -81,7 +79,7 @@ impl DbHeadersStore {
             return Err(StoreError::KeyAlreadyExists(hash.to_string()));
         }
         self.headers_access.write(
-            BatchDbWriter::new(batch),
+            BatchDbWriter::new(&self.db, batch),
             hash,
             HeaderWithBlockLevel {
                 header: header.clone(),
@@ -89,7 +87,7 @@ impl DbHeadersStore {
             },
         )?;
         self.compact_headers_access.write(
-            BatchDbWriter::new(batch),
+            BatchDbWriter::new(&self.db, batch),
             hash,
             CompactHeaderData {
                 timestamp: header.timestamp(),
@@ -98,17 +96,20 @@ impl DbHeadersStore {
         )?;
         Ok(())
     }
-}
-
-impl HeaderStoreReader for DbHeadersStore {
-    fn get_daa_score(&self, _hash: Hash) -> Result<u64, StoreError> {
-        unimplemented!()
-    }
 
-    fn get_blue_score(&self, _hash: Hash) -> Result<u64, StoreError> {
-        unimplemented!()
+    pub fn delete_batch(&self, batch: &mut DbBatch, hashes: &[Hash]) -> Result<(), StoreError> {
+        self.headers_access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut hashes.iter().copied(),
+        )?;
+        self.compact_headers_access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut hashes.iter().copied(),
+        )
     }
+}
 
+impl HeaderStoreReader for DbHeadersStore {
     fn get_timestamp(&self, hash: Hash) -> Result<u64, StoreError> {
         if let Some(header_with_block_level) = self.headers_access.read_from_cache(hash)? {
             return Ok(header_with_block_level.header.timestamp());
@@ -166,3 +167,172 @@ impl HeaderStore for DbHeadersStore {
         Ok(())
     }
 }
+
+/// Stages the headers of new blocks in memory, on top of a `DbHeadersStore`, until they
+/// are committed at once. Clones share the staged headers.
+#[derive(Clone)]
+pub struct StagingHeadersStore {
+    store: DbHeadersStore,
+    staging: Arc<RwLock<BlockHashMap<HeaderWithBlockLevel>>>,
+}
+
+impl StagingHeadersStore {
+    pub fn new(store: DbHeadersStore) -> Self {
+        Self {
+            store,
+            staging: Default::default(),
+        }
+    }
+
+    /// Writes the staged headers into `batch`, bypassing the caches
+    pub fn commit(&self, batch: &mut DbBatch) -> Result<(), StoreError> {
+        let staging = self.staging.read();
+        self.store.headers_access.write_many_without_cache(
+            BatchDbWriter::new(&self.store.db, batch),
+            &mut staging.iter().map(|(hash, header)| (*hash, header.clone())),
+        )?;
+        self.store.compact_headers_access.write_many_without_cache(
+            BatchDbWriter::new(&self.store.db, batch),
+            &mut staging.iter().map(|(hash, header)| {
+                (
+                    *hash,
+                    CompactHeaderData {
+                        timestamp: header.header.timestamp(),
+                        difficulty: header.header.difficulty(),
+                    },
+                )
+            }),
+        )
+    }
+}
+
+impl HeaderStoreReader for StagingHeadersStore {
+    fn get_timestamp(&self, hash: Hash) -> Result<u64, StoreError> {
+        Ok(self.get_compact_header_data(hash)?.timestamp)
+    }
+
+    fn get_difficulty(&self, hash: Hash) -> Result<U256, StoreError> {
+        Ok(self.get_compact_header_data(hash)?.difficulty)
+    }
+
+    fn get_header(&self, hash: Hash) -> Result<Arc<Header>, StoreError> {
+        Ok(self.get_header_with_block_level(hash)?.header)
+    }
+
+    fn get_header_with_block_level(&self, hash: Hash) -> Result<HeaderWithBlockLevel, StoreError> {
+        if let Some(header) = self.staging.read().get(&hash) {
+            return Ok(header.clone());
+        }
+        self.store.get_header_with_block_level(hash)
+    }
+
+    fn get_compact_header_data(&self, hash: Hash) -> Result<CompactHeaderData, StoreError> {
+        if let Some(header) = self.staging.read().get(&hash) {
+            return Ok(CompactHeaderData {
+                timestamp: header.header.timestamp(),
+                difficulty: header.header.difficulty(),
+            });
+        }
+        self.store.get_compact_header_data(hash)
+    }
+}
+
+impl HeaderStore for StagingHeadersStore {
+    fn insert(&self, hash: Hash, header: Arc<Header>, block_level: u8) -> Result<(), StoreError> {
+        if self.staging.read().contains_key(&hash) || self.store.has(hash)? {
+            return Err(StoreError::KeyAlreadyExists(hash.to_string()));
+        }
+        self.staging.write().insert(
+            hash,
+            HeaderWithBlockLevel {
+                header,
+                block_level,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// An in-memory implementation of `HeaderStore` trait to be used for tests and simulations.
+/// Uses `RefCell` for interior mutability in order to workaround `insert`
+/// being non-mutable.
+pub struct MemoryHeaderStore {
+    compact_headers_map: RefCell<BlockHashMap<CompactHeaderData>>,
+    headers_map: RefCell<BlockHashMap<HeaderWithBlockLevel>>,
+}
+
+impl MemoryHeaderStore {
+    pub fn new() -> Self {
+        Self {
+            compact_headers_map: RefCell::new(BlockHashMap::new()),
+            headers_map: RefCell::new(BlockHashMap::new()),
+        }
+    }
+
+    /// Inserts only the compact data of a block, which is all the GHOSTDAG protocol
+    /// reads. Useful when there is no full header at hand, e.g. in simulations.
+    pub fn insert_compact(&self, hash: Hash, data: CompactHeaderData) -> Result<(), StoreError> {
+        if self.compact_headers_map.borrow().contains_key(&hash) {
+            return Err(StoreError::KeyAlreadyExists(hash.to_string()));
+        }
+        self.compact_headers_map.borrow_mut().insert(hash, data);
+        Ok(())
+    }
+}
+
+impl Default for MemoryHeaderStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeaderStoreReader for MemoryHeaderStore {
+    fn get_timestamp(&self, hash: Hash) -> Result<u64, StoreError> {
+        Ok(self.get_compact_header_data(hash)?.timestamp)
+    }
+
+    fn get_difficulty(&self, hash: Hash) -> Result<U256, StoreError> {
+        Ok(self.get_compact_header_data(hash)?.difficulty)
+    }
+
+    fn get_header(&self, hash: Hash) -> Result<Arc<Header>, StoreError> {
+        Ok(self.get_header_with_block_level(hash)?.header)
+    }
+
+    fn get_header_with_block_level(&self, hash: Hash) -> Result<HeaderWithBlockLevel, StoreError> {
+        match self.headers_map.borrow().get(&hash) {
+            Some(header) => Ok(header.clone()),
+            None => Err(StoreError::KeyNotFound(hash.to_string())),
+        }
+    }
+
+    fn get_compact_header_data(&self, hash: Hash) -> Result<CompactHeaderData, StoreError> {
+        match self.compact_headers_map.borrow().get(&hash) {
+            Some(data) => Ok(*data),
+            None => Err(StoreError::KeyNotFound(hash.to_string())),
+        }
+    }
+}
+
+impl HeaderStore for MemoryHeaderStore {
+    fn insert(&self, hash: Hash, header: Arc<Header>, block_level: u8) -> Result<(), StoreError> {
+        if self.headers_map.borrow().contains_key(&hash) {
+            return Err(StoreError::KeyAlreadyExists(hash.to_string()));
+        }
+        self.compact_headers_map.borrow_mut().insert(
+            hash,
+            CompactHeaderData {
+                timestamp: header.timestamp(),
+                difficulty: header.difficulty(),
+            },
+        );
+        self.headers_map.borrow_mut().insert(
+            hash,
+            HeaderWithBlockLevel {
+                header,
+                block_level,
+            },
+        );
+        Ok(())
+    }
+}
@@ -1,17 +1,16 @@
 use crate::{
     db::DBStorage,
-    prelude::{BatchDbWriter, CachedDbAccess, CachedDbItem, DirectDbWriter, StoreError},
+    format::{reachability_hash_lists, HashList, ReachabilityFields, RecordFormat},
+    prelude::{BatchDbWriter, CachedDbItem, DbBatch, DirectDbWriter, RecordAccess, StoreError},
 };
 use starcoin_crypto::HashValue as Hash;
-use starcoin_storage::storage::RawDBStorage;
 
 use consensus_types::{
-    blockhash::{self, BlockHashMap, BlockHashes},
+    blockhash::{self, BlockHashMap, BlockHashSet, BlockHashes},
     interval::Interval,
     reachability::ReachabilityData,
 };
 use parking_lot::{RwLockUpgradableReadGuard, RwLockWriteGuard};
-use rocksdb::WriteBatch;
 use std::{collections::hash_map::Entry::Vacant, sync::Arc};
 
 /// Reader API for `ReachabilityStore`.
@@ -21,6 +20,18 @@ pub trait ReachabilityStoreReader {
     fn get_parent(&self, hash: Hash) -> Result<Hash, StoreError>;
     fn get_children(&self, hash: Hash) -> Result<BlockHashes, StoreError>;
     fn get_future_covering_set(&self, hash: Hash) -> Result<BlockHashes, StoreError>;
+
+    /// Same as `get_children`, the children being read in place from the stored record
+    /// when its format allows
+    fn get_children_list(&self, hash: Hash) -> Result<HashList<'_>, StoreError> {
+        self.get_children(hash).map(HashList::from)
+    }
+
+    /// Same as `get_future_covering_set`, the set being read in place from the stored
+    /// record when its format allows
+    fn get_future_covering_list(&self, hash: Hash) -> Result<HashList<'_>, StoreError> {
+        self.get_future_covering_set(hash).map(HashList::from)
+    }
 }
 
 /// Write API for `ReachabilityStore`. All write functions are deliberately `mut`
@@ -42,6 +53,9 @@ pub trait ReachabilityStore: ReachabilityStoreReader {
         fci: Hash,
         insertion_index: usize,
     ) -> Result<(), StoreError>;
+    fn remove_child(&mut self, hash: Hash, child: Hash) -> Result<(), StoreError>;
+    fn remove_future_covering_item(&mut self, hash: Hash, fci: Hash) -> Result<(), StoreError>;
+    fn delete(&mut self, hash: Hash) -> Result<(), StoreError>;
     fn get_height(&self, hash: Hash) -> Result<u64, StoreError>;
     fn set_reindex_root(&mut self, root: Hash) -> Result<(), StoreError>;
     fn get_reindex_root(&self) -> Result<Hash, StoreError>;
@@ -55,7 +69,7 @@ pub(crate) const REACHABILITY_DATA_CF: &str = "reachability-data";
 #[derive(Clone)]
 pub struct DbReachabilityStore {
     db: Arc<DBStorage>,
-    access: CachedDbAccess<Hash, Arc<ReachabilityData>>,
+    access: RecordAccess<Hash, Arc<ReachabilityData>>,
     reindex_root: CachedDbItem<Hash>,
 }
 
@@ -71,7 +85,7 @@ impl DbReachabilityStore {
     fn new_with_prefix_end(db: Arc<DBStorage>, cache_size: u64) -> Self {
         Self {
             db: Arc::clone(&db),
-            access: CachedDbAccess::new(Arc::clone(&db), cache_size, REACHABILITY_DATA_CF),
+            access: RecordAccess::new(Arc::clone(&db), cache_size, REACHABILITY_DATA_CF),
             reindex_root: CachedDbItem::new(
                 db,
                 REACHABILITY_DATA_CF,
@@ -83,6 +97,13 @@ impl DbReachabilityStore {
     pub fn clone_with_new_cache(&self, cache_size: u64) -> Self {
         Self::new_with_prefix_end(Arc::clone(&self.db), cache_size)
     }
+
+    /// Deletes the reachability data of every block and the reindex root, the store
+    /// must be initialized again afterwards
+    pub fn delete_all(&mut self) -> Result<(), StoreError> {
+        self.access.delete_all(DirectDbWriter::new(&self.db))?;
+        self.reindex_root.remove(DirectDbWriter::new(&self.db))
+    }
 }
 
 impl ReachabilityStore for DbReachabilityStore {
@@ -94,14 +115,12 @@ impl ReachabilityStore for DbReachabilityStore {
             capacity,
             0,
         ));
-        let mut batch = WriteBatch::default();
+        let mut batch = DbBatch::default();
         self.access
-            .write(BatchDbWriter::new(&mut batch), origin, data)?;
+            .write(BatchDbWriter::new(&self.db, &mut batch), origin, data)?;
         self.reindex_root
-            .write(BatchDbWriter::new(&mut batch), &origin)?;
-        self.db
-            .raw_write_batch(batch)
-            .map_err(|e| StoreError::DBIoError(e.to_string()))?;
+            .write(BatchDbWriter::new(&self.db, &mut batch), &origin)?;
+        self.db.raw_write_batch(batch)?;
 
         Ok(())
     }
@@ -154,8 +173,30 @@ impl ReachabilityStore for DbReachabilityStore {
         Ok(())
     }
 
+    fn remove_child(&mut self, hash: Hash, child: Hash) -> Result<(), StoreError> {
+        let mut data = self.access.read(hash)?;
+        let mut_data = Arc::make_mut(&mut data);
+        Arc::make_mut(&mut mut_data.children).retain(|c| *c != child);
+        self.access
+            .write(DirectDbWriter::new(&self.db), hash, data)?;
+        Ok(())
+    }
+
+    fn remove_future_covering_item(&mut self, hash: Hash, fci: Hash) -> Result<(), StoreError> {
+        let mut data = self.access.read(hash)?;
+        let mut_data = Arc::make_mut(&mut data);
+        Arc::make_mut(&mut mut_data.future_covering_set).retain(|c| *c != fci);
+        self.access
+            .write(DirectDbWriter::new(&self.db), hash, data)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, hash: Hash) -> Result<(), StoreError> {
+        self.access.delete(DirectDbWriter::new(&self.db), hash)
+    }
+
     fn get_height(&self, hash: Hash) -> Result<u64, StoreError> {
-        Ok(self.access.read(hash)?.height)
+        Ok(self.access.read_fields(hash)?.height)
     }
 
     fn set_reindex_root(&mut self, root: Hash) -> Result<(), StoreError> {
@@ -174,11 +215,11 @@ impl ReachabilityStoreReader for DbReachabilityStore {
     }
 
     fn get_interval(&self, hash: Hash) -> Result<Interval, StoreError> {
-        Ok(self.access.read(hash)?.interval)
+        Ok(self.access.read_fields(hash)?.interval)
     }
 
     fn get_parent(&self, hash: Hash) -> Result<Hash, StoreError> {
-        Ok(self.access.read(hash)?.parent)
+        Ok(self.access.read_fields(hash)?.parent)
     }
 
     fn get_children(&self, hash: Hash) -> Result<BlockHashes, StoreError> {
@@ -188,11 +229,51 @@ impl ReachabilityStoreReader for DbReachabilityStore {
     fn get_future_covering_set(&self, hash: Hash) -> Result<BlockHashes, StoreError> {
         Ok(Arc::clone(&self.access.read(hash)?.future_covering_set))
     }
+
+    fn get_children_list(&self, hash: Hash) -> Result<HashList<'_>, StoreError> {
+        self.read_hash_list(hash, ReachabilityList::Children)
+    }
+
+    fn get_future_covering_list(&self, hash: Hash) -> Result<HashList<'_>, StoreError> {
+        self.read_hash_list(hash, ReachabilityList::FutureCoveringSet)
+    }
+}
+
+/// The hash lists of the reachability data
+#[derive(Clone, Copy)]
+enum ReachabilityList {
+    Children,
+    FutureCoveringSet,
+}
+
+impl DbReachabilityStore {
+    /// Reads `list` in place with the fixed layout, decoding the record otherwise
+    fn read_hash_list(
+        &self,
+        hash: Hash,
+        list: ReachabilityList,
+    ) -> Result<HashList<'_>, StoreError> {
+        if self.access.format() == RecordFormat::Bincode {
+            let data = self.access.read(hash)?;
+            return Ok(HashList::Decoded(match list {
+                ReachabilityList::Children => Arc::clone(&data.children),
+                ReachabilityList::FutureCoveringSet => Arc::clone(&data.future_covering_set),
+            }));
+        }
+        let bytes = self.access.read_bytes(hash)?;
+        let (children, future_covering_set) = reachability_hash_lists(&bytes)?;
+        let range = match list {
+            ReachabilityList::Children => children,
+            ReachabilityList::FutureCoveringSet => future_covering_set,
+        };
+        Ok(HashList::Encoded { bytes, range })
+    }
 }
 
 pub struct StagingReachabilityStore<'a> {
     store_read: RwLockUpgradableReadGuard<'a, DbReachabilityStore>,
     staging_writes: BlockHashMap<ReachabilityData>,
+    staging_deletions: BlockHashSet,
     staging_reindex_root: Option<Hash>,
 }
 
@@ -201,28 +282,50 @@ impl<'a> StagingReachabilityStore<'a> {
         Self {
             store_read,
             staging_writes: BlockHashMap::new(),
+            staging_deletions: BlockHashSet::new(),
             staging_reindex_root: None,
         }
     }
 
     pub fn commit(
         self,
-        batch: &mut WriteBatch,
+        batch: &mut DbBatch,
     ) -> Result<RwLockWriteGuard<'a, DbReachabilityStore>, StoreError> {
         let mut store_write = RwLockUpgradableReadGuard::upgrade(self.store_read);
         for (k, v) in self.staging_writes {
             let data = Arc::new(v);
             store_write
                 .access
-                .write(BatchDbWriter::new(batch), k, data)?
+                .write(BatchDbWriter::new(&store_write.db, batch), k, data)?
         }
+        store_write.access.delete_many(
+            BatchDbWriter::new(&store_write.db, batch),
+            &mut self.staging_deletions.iter().copied(),
+        )?;
         if let Some(root) = self.staging_reindex_root {
+            let db = Arc::clone(&store_write.db);
             store_write
                 .reindex_root
-                .write(BatchDbWriter::new(batch), &root)?;
+                .write(BatchDbWriter::new(&db, batch), &root)?;
         }
         Ok(store_write)
     }
+
+    /// Reads the committed data of `hash`, unless it was deleted by the staged changes
+    fn read_committed(&self, hash: Hash) -> Result<Arc<ReachabilityData>, StoreError> {
+        if self.staging_deletions.contains(&hash) {
+            return Err(StoreError::KeyNotFound(hash.to_string()));
+        }
+        self.store_read.access.read(hash)
+    }
+
+    /// Reads the fixed size fields of the committed data of `hash`, see `read_committed`
+    fn read_committed_fields(&self, hash: Hash) -> Result<ReachabilityFields, StoreError> {
+        if self.staging_deletions.contains(&hash) {
+            return Err(StoreError::KeyNotFound(hash.to_string()));
+        }
+        self.store_read.access.read_fields(hash)
+    }
 }
 
 impl ReachabilityStore for StagingReachabilityStore<'_> {
@@ -239,9 +342,10 @@ impl ReachabilityStore for StagingReachabilityStore<'_> {
         interval: Interval,
         height: u64,
     ) -> Result<(), StoreError> {
-        if self.store_read.has(hash)? {
+        if self.has(hash)? {
             return Err(StoreError::KeyAlreadyExists(hash.to_string()));
         }
+        self.staging_deletions.remove(&hash);
         if let Vacant(e) = self.staging_writes.entry(hash) {
             e.insert(ReachabilityData::new(parent, interval, height));
             Ok(())
@@ -256,7 +360,7 @@ impl ReachabilityStore for StagingReachabilityStore<'_> {
             return Ok(());
         }
 
-        let mut data = (*self.store_read.access.read(hash)?).clone();
+        let mut data = (*self.read_committed(hash)?).clone();
         data.interval = interval;
         self.staging_writes.insert(hash, data);
 
@@ -269,7 +373,7 @@ impl ReachabilityStore for StagingReachabilityStore<'_> {
             return Ok(data.height);
         }
 
-        let mut data = (*self.store_read.access.read(hash)?).clone();
+        let mut data = (*self.read_committed(hash)?).clone();
         let height = data.height;
         Arc::make_mut(&mut data.children).push(child);
         self.staging_writes.insert(hash, data);
@@ -288,18 +392,53 @@ impl ReachabilityStore for StagingReachabilityStore<'_> {
             return Ok(());
         }
 
-        let mut data = (*self.store_read.access.read(hash)?).clone();
+        let mut data = (*self.read_committed(hash)?).clone();
         Arc::make_mut(&mut data.future_covering_set).insert(insertion_index, fci);
         self.staging_writes.insert(hash, data);
 
         Ok(())
     }
 
+    fn remove_child(&mut self, hash: Hash, child: Hash) -> Result<(), StoreError> {
+        if let Some(data) = self.staging_writes.get_mut(&hash) {
+            Arc::make_mut(&mut data.children).retain(|c| *c != child);
+            return Ok(());
+        }
+
+        let mut data = (*self.read_committed(hash)?).clone();
+        Arc::make_mut(&mut data.children).retain(|c| *c != child);
+        self.staging_writes.insert(hash, data);
+
+        Ok(())
+    }
+
+    fn remove_future_covering_item(&mut self, hash: Hash, fci: Hash) -> Result<(), StoreError> {
+        if let Some(data) = self.staging_writes.get_mut(&hash) {
+            Arc::make_mut(&mut data.future_covering_set).retain(|c| *c != fci);
+            return Ok(());
+        }
+
+        let mut data = (*self.read_committed(hash)?).clone();
+        Arc::make_mut(&mut data.future_covering_set).retain(|c| *c != fci);
+        self.staging_writes.insert(hash, data);
+
+        Ok(())
+    }
+
+    fn delete(&mut self, hash: Hash) -> Result<(), StoreError> {
+        if !self.has(hash)? {
+            return Err(StoreError::KeyNotFound(hash.to_string()));
+        }
+        self.staging_writes.remove(&hash);
+        self.staging_deletions.insert(hash);
+        Ok(())
+    }
+
     fn get_height(&self, hash: Hash) -> Result<u64, StoreError> {
         if let Some(data) = self.staging_writes.get(&hash) {
             Ok(data.height)
         } else {
-            Ok(self.store_read.access.read(hash)?.height)
+            Ok(self.read_committed_fields(hash)?.height)
         }
     }
 
@@ -319,14 +458,15 @@ impl ReachabilityStore for StagingReachabilityStore<'_> {
 
 impl ReachabilityStoreReader for StagingReachabilityStore<'_> {
     fn has(&self, hash: Hash) -> Result<bool, StoreError> {
-        Ok(self.staging_writes.contains_key(&hash) || self.store_read.access.has(hash)?)
+        Ok(self.staging_writes.contains_key(&hash)
+            || (!self.staging_deletions.contains(&hash) && self.store_read.access.has(hash)?))
     }
 
     fn get_interval(&self, hash: Hash) -> Result<Interval, StoreError> {
         if let Some(data) = self.staging_writes.get(&hash) {
             Ok(data.interval)
         } else {
-            Ok(self.store_read.access.read(hash)?.interval)
+            Ok(self.read_committed_fields(hash)?.interval)
         }
     }
 
@@ -334,7 +474,7 @@ impl ReachabilityStoreReader for StagingReachabilityStore<'_> {
         if let Some(data) = self.staging_writes.get(&hash) {
             Ok(data.parent)
         } else {
-            Ok(self.store_read.access.read(hash)?.parent)
+            Ok(self.read_committed_fields(hash)?.parent)
         }
     }
 
@@ -342,9 +482,7 @@ impl ReachabilityStoreReader for StagingReachabilityStore<'_> {
         if let Some(data) = self.staging_writes.get(&hash) {
             Ok(BlockHashes::clone(&data.children))
         } else {
-            Ok(BlockHashes::clone(
-                &self.store_read.access.read(hash)?.children,
-            ))
+            Ok(BlockHashes::clone(&self.read_committed(hash)?.children))
         }
     }
 
@@ -353,10 +491,32 @@ impl ReachabilityStoreReader for StagingReachabilityStore<'_> {
             Ok(BlockHashes::clone(&data.future_covering_set))
         } else {
             Ok(BlockHashes::clone(
-                &self.store_read.access.read(hash)?.future_covering_set,
+                &self.read_committed(hash)?.future_covering_set,
             ))
         }
     }
+
+    fn get_children_list(&self, hash: Hash) -> Result<HashList<'_>, StoreError> {
+        if let Some(data) = self.staging_writes.get(&hash) {
+            return Ok(HashList::from(BlockHashes::clone(&data.children)));
+        }
+        if self.staging_deletions.contains(&hash) {
+            return Err(StoreError::KeyNotFound(hash.to_string()));
+        }
+        self.store_read.get_children_list(hash)
+    }
+
+    fn get_future_covering_list(&self, hash: Hash) -> Result<HashList<'_>, StoreError> {
+        if let Some(data) = self.staging_writes.get(&hash) {
+            return Ok(HashList::from(BlockHashes::clone(
+                &data.future_covering_set,
+            )));
+        }
+        if self.staging_deletions.contains(&hash) {
+            return Err(StoreError::KeyNotFound(hash.to_string()));
+        }
+        self.store_read.get_future_covering_list(hash)
+    }
 }
 
 pub struct MemoryReachabilityStore {
@@ -438,6 +598,25 @@ impl ReachabilityStore for MemoryReachabilityStore {
         Ok(())
     }
 
+    fn remove_child(&mut self, hash: Hash, child: Hash) -> Result<(), StoreError> {
+        let data = self.get_data_mut(hash)?;
+        Arc::make_mut(&mut data.children).retain(|c| *c != child);
+        Ok(())
+    }
+
+    fn remove_future_covering_item(&mut self, hash: Hash, fci: Hash) -> Result<(), StoreError> {
+        let data = self.get_data_mut(hash)?;
+        Arc::make_mut(&mut data.future_covering_set).retain(|c| *c != fci);
+        Ok(())
+    }
+
+    fn delete(&mut self, hash: Hash) -> Result<(), StoreError> {
+        match self.map.remove(&hash) {
+            Some(_) => Ok(()),
+            None => Err(StoreError::KeyNotFound(hash.to_string())),
+        }
+    }
+
     fn get_height(&self, hash: Hash) -> Result<u64, StoreError> {
         Ok(self.get_data(hash)?.height)
     }
@@ -480,6 +659,7 @@ impl ReachabilityStoreReader for MemoryReachabilityStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::prelude::{FlexiDagStorage, FlexiDagStorageConfig};
 
     #[test]
     fn test_store_basics() {
@@ -494,4 +674,38 @@ mod tests {
         store.get_interval(7.into()).unwrap();
         println!("{children:?}");
     }
+
+    #[test]
+    fn test_hash_lists() {
+        for format in [RecordFormat::Bincode, RecordFormat::FixedV1] {
+            let db_tempdir = tempfile::tempdir().unwrap();
+            let config =
+                FlexiDagStorageConfig::create_with_params(1, 0, 1024).update_record_format(format);
+            let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+            let mut store = storage.reachability_store.clone();
+            let hash = 7.into();
+            store
+                .insert(hash, 15.into(), Interval::maximal(), 5)
+                .unwrap();
+            store.append_child(hash, 31.into()).unwrap();
+            store.append_child(hash, 63.into()).unwrap();
+            store
+                .insert_future_covering_item(hash, 127.into(), 0)
+                .unwrap();
+
+            // The lists are the same whether they are read from the cache or from the db
+            let uncached = DbReachabilityStore::new(storage.db.clone(), 0);
+            for store in [&store, &uncached] {
+                let children = store.get_children_list(hash).unwrap();
+                assert_eq!(children.to_vec(), *store.get_children(hash).unwrap());
+                assert_eq!(children.len(), 2);
+                assert_eq!(children.hash(1), 63.into());
+                assert_eq!(
+                    store.get_future_covering_list(hash).unwrap().to_vec(),
+                    vec![127.into()]
+                );
+            }
+            assert!(store.get_children_list(8.into()).is_err());
+        }
+    }
 }
@@ -1,9 +1,9 @@
 use crate::{
     db::DBStorage,
-    prelude::{BatchDbWriter, CachedDbAccess, DirectDbWriter, StoreError},
+    prelude::{BatchDbWriter, CachedDbAccess, DbBatch, DirectDbWriter, StoreError},
 };
-use consensus_types::blockhash::{BlockHashMap, BlockHashes, BlockLevel};
-use rocksdb::WriteBatch;
+use consensus_types::blockhash::{BlockHashMap, BlockHashSet, BlockHashes, BlockLevel};
+use parking_lot::RwLock;
 use starcoin_crypto::HashValue as Hash;
 use std::{collections::hash_map::Entry::Vacant, sync::Arc};
 
@@ -50,7 +50,7 @@ impl DbRelationsStore {
 
     pub fn insert_batch(
         &mut self,
-        batch: &mut WriteBatch,
+        batch: &mut DbBatch,
         hash: Hash,
         parents: BlockHashes,
     ) -> Result<(), StoreError> {
@@ -60,11 +60,11 @@ impl DbRelationsStore {
 
         // Insert a new entry for `hash`
         self.parents_access
-            .write(BatchDbWriter::new(batch), hash, parents.clone())?;
+            .write(BatchDbWriter::new(&self.db, batch), hash, parents.clone())?;
 
         // The new hash has no children yet
         self.children_access.write(
-            BatchDbWriter::new(batch),
+            BatchDbWriter::new(&self.db, batch),
             hash,
             BlockHashes::new(Vec::new()),
         )?;
@@ -74,7 +74,7 @@ impl DbRelationsStore {
             let mut children = (*self.get_children(parent)?).clone();
             children.push(hash);
             self.children_access.write(
-                BatchDbWriter::new(batch),
+                BatchDbWriter::new(&self.db, batch),
                 parent,
                 BlockHashes::new(children),
             )?;
@@ -82,6 +82,46 @@ impl DbRelationsStore {
 
         Ok(())
     }
+
+    /// Deletes the entries of `hashes`, which must contain the children of each of its
+    /// blocks, and removes them from the children of the remaining parents
+    pub fn delete_batch(
+        &mut self,
+        batch: &mut DbBatch,
+        hashes: &BlockHashSet,
+    ) -> Result<(), StoreError> {
+        let mut remaining_parents = BlockHashSet::new();
+        for hash in hashes.iter() {
+            for parent in self.get_parents(*hash)?.iter() {
+                if !hashes.contains(parent) {
+                    remaining_parents.insert(*parent);
+                }
+            }
+        }
+
+        for parent in remaining_parents {
+            let children = self
+                .get_children(parent)?
+                .iter()
+                .copied()
+                .filter(|child| !hashes.contains(child))
+                .collect();
+            self.children_access.write(
+                BatchDbWriter::new(&self.db, batch),
+                parent,
+                BlockHashes::new(children),
+            )?;
+        }
+
+        self.parents_access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut hashes.iter().copied(),
+        )?;
+        self.children_access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut hashes.iter().copied(),
+        )
+    }
 }
 
 impl RelationsStoreReader for DbRelationsStore {
@@ -137,6 +177,92 @@ impl RelationsStore for DbRelationsStore {
     }
 }
 
+/// Stages the relations of new blocks in memory, on top of a `DbRelationsStore`, until
+/// they are committed at once. Clones share the staged relations.
+#[derive(Clone)]
+pub struct StagingRelationsStore {
+    store: DbRelationsStore,
+    staging: Arc<RwLock<StagedRelations>>,
+}
+
+#[derive(Default)]
+struct StagedRelations {
+    parents: BlockHashMap<BlockHashes>,
+    /// The children of the new blocks and of the parents they were appended to
+    children: BlockHashMap<BlockHashes>,
+}
+
+impl StagingRelationsStore {
+    pub fn new(store: DbRelationsStore) -> Self {
+        Self {
+            store,
+            staging: Default::default(),
+        }
+    }
+
+    /// Writes the staged relations into `batch`, bypassing the caches
+    pub fn commit(&self, batch: &mut DbBatch) -> Result<(), StoreError> {
+        let staging = self.staging.read();
+        self.store.parents_access.write_many_without_cache(
+            BatchDbWriter::new(&self.store.db, batch),
+            &mut staging
+                .parents
+                .iter()
+                .map(|(hash, parents)| (*hash, parents.clone())),
+        )?;
+        self.store.children_access.write_many_without_cache(
+            BatchDbWriter::new(&self.store.db, batch),
+            &mut staging
+                .children
+                .iter()
+                .map(|(hash, children)| (*hash, children.clone())),
+        )
+    }
+}
+
+impl RelationsStoreReader for StagingRelationsStore {
+    fn get_parents(&self, hash: Hash) -> Result<BlockHashes, StoreError> {
+        if let Some(parents) = self.staging.read().parents.get(&hash) {
+            return Ok(BlockHashes::clone(parents));
+        }
+        self.store.get_parents(hash)
+    }
+
+    fn get_children(&self, hash: Hash) -> Result<BlockHashes, StoreError> {
+        if let Some(children) = self.staging.read().children.get(&hash) {
+            return Ok(BlockHashes::clone(children));
+        }
+        self.store.get_children(hash)
+    }
+
+    fn has(&self, hash: Hash) -> Result<bool, StoreError> {
+        Ok(self.staging.read().parents.contains_key(&hash) || self.store.has(hash)?)
+    }
+}
+
+impl RelationsStore for StagingRelationsStore {
+    fn insert(&mut self, hash: Hash, parents: BlockHashes) -> Result<(), StoreError> {
+        if self.has(hash)? {
+            return Err(StoreError::KeyAlreadyExists(hash.to_string()));
+        }
+
+        // Update `children` for each parent
+        let mut children_updates = Vec::with_capacity(parents.len());
+        for parent in parents.iter().cloned() {
+            let mut children = (*self.get_children(parent)?).clone();
+            children.push(hash);
+            children_updates.push((parent, BlockHashes::new(children)));
+        }
+
+        let mut staging = self.staging.write();
+        staging.parents.insert(hash, parents);
+        staging.children.extend(children_updates);
+        // The new hash has no children yet
+        staging.children.insert(hash, BlockHashes::new(Vec::new()));
+        Ok(())
+    }
+}
+
 pub struct MemoryRelationsStore {
     parents_map: BlockHashMap<BlockHashes>,
     children_map: BlockHashMap<BlockHashes>,
@@ -0,0 +1,159 @@
+use crate::{
+    db::DBStorage,
+    errors::StoreResult,
+    key::U64Key,
+    prelude::{BatchDbWriter, CachedDbAccess, CachedDbItem, DbBatch, DbWriter, DirectDbWriter},
+};
+use consensus_types::blockhash::ChainPath;
+use starcoin_crypto::HashValue as Hash;
+use std::sync::Arc;
+
+/// Reader API for `SelectedChainStore`.
+pub trait SelectedChainStoreReader {
+    /// The position of `hash` on the selected chain, genesis being at index 0
+    fn get_by_hash(&self, hash: Hash) -> StoreResult<u64>;
+    fn get_by_index(&self, index: u64) -> StoreResult<Hash>;
+    /// The highest index of the selected chain, together with its block
+    fn get_tip(&self) -> StoreResult<(u64, Hash)>;
+}
+
+/// Write API for `SelectedChainStore`. The selected chain of the virtual block
+/// can be reorganized, hence all write functions are deliberately `mut`.
+pub trait SelectedChainStore: SelectedChainStoreReader {
+    fn init(&mut self, genesis: Hash) -> StoreResult<()>;
+    /// Removes the blocks of `changes.removed`, which must be the highest ones of the
+    /// chain, and appends those of `changes.added`, given from low to high.
+    fn apply_changes(&mut self, changes: &ChainPath) -> StoreResult<()>;
+}
+
+pub(crate) const CHAIN_HASH_BY_INDEX_CF: &str = "selected-chain-hash-by-index";
+pub(crate) const CHAIN_INDEX_BY_HASH_CF: &str = "selected-chain-index-by-hash";
+pub(crate) const CHAIN_HIGHEST_INDEX_CF: &str = "selected-chain-highest-index";
+const HIGHEST_INDEX_KEY: &str = "highest-index";
+
+/// A DB + cache implementation of `SelectedChainStore` trait
+#[derive(Clone)]
+pub struct DbSelectedChainStore {
+    db: Arc<DBStorage>,
+    access_hash_by_index: CachedDbAccess<U64Key, Hash>,
+    access_index_by_hash: CachedDbAccess<Hash, u64>,
+    access_highest_index: CachedDbItem<u64>,
+}
+
+impl DbSelectedChainStore {
+    pub fn new(db: Arc<DBStorage>, cache_size: u64) -> Self {
+        Self {
+            db: Arc::clone(&db),
+            access_hash_by_index: CachedDbAccess::new(
+                db.clone(),
+                cache_size,
+                CHAIN_HASH_BY_INDEX_CF,
+            ),
+            access_index_by_hash: CachedDbAccess::new(
+                db.clone(),
+                cache_size,
+                CHAIN_INDEX_BY_HASH_CF,
+            ),
+            access_highest_index: CachedDbItem::new(
+                db,
+                CHAIN_HIGHEST_INDEX_CF,
+                HIGHEST_INDEX_KEY.as_bytes().to_vec(),
+            ),
+        }
+    }
+
+    /// Deletes the whole index, the store must be initialized again afterwards
+    pub fn delete_all(&mut self) -> StoreResult<()> {
+        self.access_hash_by_index
+            .delete_all(DirectDbWriter::new(&self.db))?;
+        self.access_index_by_hash
+            .delete_all(DirectDbWriter::new(&self.db))?;
+        self.access_highest_index
+            .remove(DirectDbWriter::new(&self.db))
+    }
+
+    pub fn apply_changes_batch(
+        &mut self,
+        batch: &mut DbBatch,
+        changes: &ChainPath,
+    ) -> StoreResult<()> {
+        let db = Arc::clone(&self.db);
+        self.apply_changes_with(BatchDbWriter::new(&db, batch), changes)
+    }
+
+    /// Replaces the whole index with `chain`, given from genesis up
+    pub fn reset_batch(&mut self, batch: &mut DbBatch, chain: &[Hash]) -> StoreResult<()> {
+        let db = Arc::clone(&self.db);
+        let mut writer = BatchDbWriter::new(&db, batch);
+        self.access_hash_by_index.delete_all(&mut writer)?;
+        self.access_index_by_hash.delete_all(&mut writer)?;
+        for (index, hash) in chain.iter().enumerate() {
+            self.access_hash_by_index
+                .write(&mut writer, (index as u64).into(), *hash)?;
+            self.access_index_by_hash
+                .write(&mut writer, *hash, index as u64)?;
+        }
+        self.access_highest_index
+            .write(&mut writer, &(chain.len() as u64 - 1))
+    }
+
+    fn apply_changes_with(
+        &mut self,
+        mut writer: impl DbWriter,
+        changes: &ChainPath,
+    ) -> StoreResult<()> {
+        let highest_index = self.access_highest_index.read()?;
+        let split_index = highest_index - changes.removed.len() as u64;
+
+        for removed in changes.removed.iter() {
+            let index = self.access_index_by_hash.read(*removed)?;
+            self.access_index_by_hash.delete(&mut writer, *removed)?;
+            self.access_hash_by_index
+                .delete(&mut writer, index.into())?;
+        }
+
+        for (offset, added) in changes.added.iter().enumerate() {
+            let index = split_index + offset as u64 + 1;
+            self.access_hash_by_index
+                .write(&mut writer, index.into(), *added)?;
+            self.access_index_by_hash
+                .write(&mut writer, *added, index)?;
+        }
+
+        self.access_highest_index
+            .write(&mut writer, &(split_index + changes.added.len() as u64))
+    }
+}
+
+impl SelectedChainStoreReader for DbSelectedChainStore {
+    fn get_by_hash(&self, hash: Hash) -> StoreResult<u64> {
+        self.access_index_by_hash.read(hash)
+    }
+
+    fn get_by_index(&self, index: u64) -> StoreResult<Hash> {
+        self.access_hash_by_index.read(index.into())
+    }
+
+    fn get_tip(&self) -> StoreResult<(u64, Hash)> {
+        let index = self.access_highest_index.read()?;
+        Ok((index, self.get_by_index(index)?))
+    }
+}
+
+impl SelectedChainStore for DbSelectedChainStore {
+    fn init(&mut self, genesis: Hash) -> StoreResult<()> {
+        self.access_hash_by_index
+            .write(DirectDbWriter::new(&self.db), 0.into(), genesis)?;
+        self.access_index_by_hash
+            .write(DirectDbWriter::new(&self.db), genesis, 0)?;
+        self.access_highest_index
+            .write(DirectDbWriter::new(&self.db), &0)
+    }
+
+    fn apply_changes(&mut self, changes: &ChainPath) -> StoreResult<()> {
+        // One batch for the whole path, the index is never seen half moved
+        let mut batch = DbBatch::default();
+        self.apply_changes_batch(&mut batch, changes)?;
+        self.db.raw_write_batch(batch)
+    }
+}
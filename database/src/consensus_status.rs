@@ -0,0 +1,72 @@
+use crate::{
+    db::DBStorage,
+    errors::StoreResult,
+    prelude::{BatchDbWriter, CachedDbAccess, DbBatch, DirectDbWriter},
+};
+use consensus_types::status::BlockStatus;
+use starcoin_crypto::HashValue as Hash;
+use std::sync::Arc;
+
+/// Reader API for `StatusesStore`.
+pub trait StatusesStoreReader {
+    fn get(&self, hash: Hash) -> StoreResult<BlockStatus>;
+    fn has(&self, hash: Hash) -> StoreResult<bool>;
+}
+
+/// Write API for `StatusesStore`. The status of a block evolves as it gets validated,
+/// so statuses are overwritten by `set`.
+pub trait StatusesStore: StatusesStoreReader {
+    fn set(&mut self, hash: Hash, status: BlockStatus) -> StoreResult<()>;
+}
+
+pub(crate) const STATUS_STORE_CF: &str = "block-status";
+
+/// A DB + cache implementation of `StatusesStore` trait, with concurrent readers support.
+#[derive(Clone)]
+pub struct DbStatusesStore {
+    db: Arc<DBStorage>,
+    access: CachedDbAccess<Hash, BlockStatus>,
+}
+
+impl DbStatusesStore {
+    pub fn new(db: Arc<DBStorage>, cache_size: u64) -> Self {
+        Self {
+            db: Arc::clone(&db),
+            access: CachedDbAccess::new(db, cache_size, STATUS_STORE_CF),
+        }
+    }
+
+    pub fn set_batch(
+        &mut self,
+        batch: &mut DbBatch,
+        hash: Hash,
+        status: BlockStatus,
+    ) -> StoreResult<()> {
+        self.access
+            .write(BatchDbWriter::new(&self.db, batch), hash, status)
+    }
+
+    pub fn delete_batch(&self, batch: &mut DbBatch, hashes: &[Hash]) -> StoreResult<()> {
+        self.access.delete_many(
+            BatchDbWriter::new(&self.db, batch),
+            &mut hashes.iter().copied(),
+        )
+    }
+}
+
+impl StatusesStoreReader for DbStatusesStore {
+    fn get(&self, hash: Hash) -> StoreResult<BlockStatus> {
+        self.access.read(hash)
+    }
+
+    fn has(&self, hash: Hash) -> StoreResult<bool> {
+        self.access.has(hash)
+    }
+}
+
+impl StatusesStore for DbStatusesStore {
+    fn set(&mut self, hash: Hash, status: BlockStatus) -> StoreResult<()> {
+        self.access
+            .write(DirectDbWriter::new(&self.db), hash, status)
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{
+    db::DBStorage,
+    errors::StoreResult,
+    prelude::{BatchDbWriter, CachedDbItem, DbBatch, DirectDbWriter},
+};
+use consensus_types::blockhash::BlockHashSet;
+use starcoin_crypto::HashValue as Hash;
+use std::sync::Arc;
+
+/// Reader API for `TipsStore`.
+pub trait TipsStoreReader {
+    fn get(&self) -> StoreResult<Arc<BlockHashSet>>;
+}
+
+/// Write API for `TipsStore`. The tips set is rewritten by every new block,
+/// hence all write functions are deliberately `mut`.
+pub trait TipsStore: TipsStoreReader {
+    fn init(&mut self, initial_tip: Hash) -> StoreResult<()>;
+    /// Adds `new_tip` to the tips set and removes its parents, which cannot be tips anymore
+    fn add_tip(
+        &mut self,
+        new_tip: Hash,
+        new_tip_parents: &[Hash],
+    ) -> StoreResult<Arc<BlockHashSet>>;
+}
+
+pub(crate) const DAG_TIPS_CF: &str = "dag-tips";
+const TIPS_KEY: &str = "tips";
+
+/// A DB + cache implementation of `TipsStore` trait
+#[derive(Clone)]
+pub struct DbTipsStore {
+    db: Arc<DBStorage>,
+    tips: CachedDbItem<Arc<BlockHashSet>>,
+}
+
+impl DbTipsStore {
+    pub fn new(db: Arc<DBStorage>) -> Self {
+        Self {
+            db: Arc::clone(&db),
+            tips: CachedDbItem::new(db, DAG_TIPS_CF, TIPS_KEY.as_bytes().to_vec()),
+        }
+    }
+
+    /// Overwrites the whole tips set
+    pub fn set_batch(&mut self, batch: &mut DbBatch, tips: BlockHashSet) -> StoreResult<()> {
+        self.tips
+            .write(BatchDbWriter::new(&self.db, batch), &Arc::new(tips))
+    }
+}
+
+impl TipsStoreReader for DbTipsStore {
+    fn get(&self) -> StoreResult<Arc<BlockHashSet>> {
+        self.tips.read()
+    }
+}
+
+impl TipsStore for DbTipsStore {
+    fn init(&mut self, initial_tip: Hash) -> StoreResult<()> {
+        self.tips.write(
+            DirectDbWriter::new(&self.db),
+            &Arc::new(BlockHashSet::from_iter([initial_tip])),
+        )
+    }
+
+    fn add_tip(
+        &mut self,
+        new_tip: Hash,
+        new_tip_parents: &[Hash],
+    ) -> StoreResult<Arc<BlockHashSet>> {
+        self.tips.update(DirectDbWriter::new(&self.db), |mut tips| {
+            let tips_mut = Arc::make_mut(&mut tips);
+            for parent in new_tip_parents {
+                tips_mut.remove(parent);
+            }
+            tips_mut.insert(new_tip);
+            tips
+        })
+    }
+}
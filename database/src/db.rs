@@ -1,12 +1,212 @@
+use crate::cache::ByteBudget;
 use crate::consensus::{
-    DbGhostdagStore, DbHeadersStore, DbReachabilityStore, DbRelationsStore, CHILDREN_CF,
-    COMPACT_GHOST_DAG_STORE_CF, COMPACT_HEADER_DATA_STORE_CF, GHOST_DAG_STORE_CF, HEADERS_STORE_CF,
-    PARENTS_CF, REACHABILITY_DATA_CF,
+    DbBlockIndexStore, DbGhostdagStore, DbHeadersStore, DbReachabilityStore, DbRelationsStore,
+    DbSelectedChainStore, DbStatusesStore, DbTipsStore, BLUE_SCORE_INDEX_CF,
+    CHAIN_HASH_BY_INDEX_CF, CHAIN_HIGHEST_INDEX_CF, CHAIN_INDEX_BY_HASH_CF, CHILDREN_CF,
+    COMPACT_GHOST_DAG_STORE_CF, COMPACT_HEADER_DATA_STORE_CF, DAG_TIPS_CF, GHOST_DAG_STORE_CF,
+    HEADERS_STORE_CF, PARENTS_CF, REACHABILITY_DATA_CF, STATUS_STORE_CF, TIMESTAMP_INDEX_CF,
 };
-use crate::errors::StoreError;
-use starcoin_config::RocksdbConfig;
-pub(crate) use starcoin_storage::db_storage::DBStorage;
-use std::{path::Path, sync::Arc};
+use crate::errors::{StoreError, StoreResult};
+use crate::format::RecordFormat;
+use crate::metrics::{StoreCounters, StoreMetrics, StoreMetricsRegistry};
+use crate::schema::{check_schema, resolve_record_format, upgrade_schema, SCHEMA_VERSION_CF};
+use crate::writer::DbBatch;
+use rocksdb::{
+    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, DBIterator,
+    DBPinnableSlice, IteratorMode, Options, ReadOptions, SliceTransform, WriteOptions, DB,
+};
+use starcoin_crypto::HashValue as Hash;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// The RocksDB instance of the dag, refusing writes when it was opened read-only
+pub struct DBStorage {
+    db: DB,
+    read_only: bool,
+    /// The memory budget shared by the caches of the stores, if any
+    cache_budget: Option<Arc<ByteBudget>>,
+    metrics: StoreMetricsRegistry,
+    record_format: RecordFormat,
+}
+
+impl DBStorage {
+    pub(crate) fn record_format(&self) -> RecordFormat {
+        self.record_format
+    }
+
+    pub(crate) fn cache_budget(&self) -> Option<&Arc<ByteBudget>> {
+        self.cache_budget.as_ref()
+    }
+
+    pub(crate) fn store_counters(&self, prefix: &'static str) -> Arc<StoreCounters> {
+        self.metrics.counters(prefix)
+    }
+
+    pub fn db(&self) -> &DB {
+        &self.db
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub(crate) fn get_cf_handle(&self, cf_name: &str) -> StoreResult<&ColumnFamily> {
+        self.db
+            .cf_handle(cf_name)
+            .ok_or_else(|| StoreError::CFNotExist(cf_name.to_string()))
+    }
+
+    pub(crate) fn put(
+        &self,
+        cf_name: &str,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> StoreResult<()> {
+        Ok(self.db.put_cf(self.get_cf_handle(cf_name)?, key, value)?)
+    }
+
+    pub(crate) fn remove(&self, cf_name: &str, key: impl AsRef<[u8]>) -> StoreResult<()> {
+        Ok(self.db.delete_cf(self.get_cf_handle(cf_name)?, key)?)
+    }
+
+    pub(crate) fn raw_get_pinned_cf(
+        &self,
+        cf_name: &str,
+        key: impl AsRef<[u8]>,
+    ) -> StoreResult<Option<DBPinnableSlice<'_>>> {
+        Ok(self.db.get_pinned_cf(self.get_cf_handle(cf_name)?, key)?)
+    }
+
+    /// Looks up `keys` in a single batch, returning the values in the same order
+    pub(crate) fn raw_multi_get_cf<K: AsRef<[u8]>>(
+        &self,
+        cf_name: &str,
+        keys: impl IntoIterator<Item = K>,
+    ) -> StoreResult<Vec<Option<DBPinnableSlice<'_>>>> {
+        let cf = self.get_cf_handle(cf_name)?;
+        self.db
+            .batched_multi_get_cf(cf, keys, false)
+            .into_iter()
+            .map(|value| Ok(value?))
+            .collect()
+    }
+
+    /// Iterates over `cf_name` in key order, whatever the prefix extractor of the column
+    /// family
+    pub(crate) fn raw_iterator_cf_opt(
+        &self,
+        cf_name: &str,
+        mode: IteratorMode,
+        mut read_opts: ReadOptions,
+    ) -> StoreResult<DBIterator<'_>> {
+        read_opts.set_total_order_seek(true);
+        Ok(self
+            .db
+            .iterator_cf_opt(self.get_cf_handle(cf_name)?, read_opts, mode))
+    }
+
+    /// Writes `batch`, then applies the cache updates of its writes
+    pub(crate) fn raw_write_batch(&self, batch: DbBatch) -> StoreResult<()> {
+        self.raw_write_batch_opt(batch, &WriteOptions::default())
+    }
+
+    /// Writes `batch` and syncs the write-ahead log before returning
+    pub(crate) fn raw_write_batch_sync(&self, batch: DbBatch) -> StoreResult<()> {
+        let mut write_opts = WriteOptions::default();
+        write_opts.set_sync(true);
+        self.raw_write_batch_opt(batch, &write_opts)
+    }
+
+    fn raw_write_batch_opt(&self, batch: DbBatch, write_opts: &WriteOptions) -> StoreResult<()> {
+        self.check_writable()?;
+        let (batch, cache_updates) = batch.into_parts();
+        self.db.write_opt(batch, write_opts)?;
+        cache_updates.into_iter().for_each(|update| update());
+        Ok(())
+    }
+
+    pub(crate) fn check_writable(&self) -> StoreResult<()> {
+        if self.read_only {
+            return Err(StoreError::ReadOnlyDb);
+        }
+        Ok(())
+    }
+}
+
+/// The RocksDB options of a column family
+#[derive(Clone, Debug)]
+pub struct ColumnFamilyConfig {
+    /// Whether the blocks of the column family are kept in the block cache shared by the
+    /// column families of the storage, in place of a cache of its own
+    pub shared_block_cache: bool,
+    /// The size in bytes of the block cache of the column family when it does not use the
+    /// shared one, RocksDB choosing it if `None`
+    pub block_cache_size: Option<usize>,
+    /// The bits per key of the bloom filters, no filter being built if `None`
+    pub bloom_filter_bits_per_key: Option<f64>,
+    pub compression: DBCompressionType,
+    /// Extracts the block hash prefix of the keys, so that the bloom filters also
+    /// serve the lookups of the entries of a block
+    pub hash_prefix_extractor: bool,
+}
+
+impl Default for ColumnFamilyConfig {
+    fn default() -> Self {
+        Self {
+            shared_block_cache: false,
+            block_cache_size: None,
+            bloom_filter_bits_per_key: None,
+            compression: DBCompressionType::Lz4,
+            hash_prefix_extractor: false,
+        }
+    }
+}
+
+impl ColumnFamilyConfig {
+    /// A config suited for random point lookups by block hash
+    pub fn point_lookups() -> Self {
+        Self {
+            shared_block_cache: true,
+            block_cache_size: None,
+            bloom_filter_bits_per_key: Some(10.0),
+            compression: DBCompressionType::Lz4,
+            hash_prefix_extractor: true,
+        }
+    }
+
+    fn options(&self, block_cache: &Cache) -> Options {
+        let mut options = Options::default();
+        options.set_compression_type(self.compression);
+        let mut table_options = BlockBasedOptions::default();
+        if self.shared_block_cache {
+            table_options.set_block_cache(block_cache);
+        } else if let Some(size) = self.block_cache_size {
+            table_options.set_block_cache(&Cache::new_lru_cache(size));
+        }
+        if let Some(bits_per_key) = self.bloom_filter_bits_per_key {
+            table_options.set_bloom_filter(bits_per_key, false);
+            table_options.set_whole_key_filtering(true);
+        }
+        options.set_block_based_table_factory(&table_options);
+        if self.hash_prefix_extractor {
+            options.set_prefix_extractor(SliceTransform::create_fixed_prefix(Hash::LENGTH));
+        }
+        options
+    }
+}
+
+/// The size of the block cache shared by the column families read by random point lookups
+const SHARED_BLOCK_CACHE_SIZE: usize = 128 << 20;
+
+/// How the RocksDB instance of a storage is opened
+#[derive(Clone, Copy)]
+enum OpenMode<'a> {
+    ReadWrite,
+    ReadOnly,
+    /// A secondary instance, keeping its own files under the given path
+    Secondary(&'a Path),
+}
 
 #[derive(Clone)]
 pub struct FlexiDagStorage {
@@ -14,28 +214,42 @@ pub struct FlexiDagStorage {
     pub header_store: DbHeadersStore,
     pub reachability_store: DbReachabilityStore,
     pub relations_store: DbRelationsStore,
+    pub tips_store: DbTipsStore,
+    pub selected_chain_store: DbSelectedChainStore,
+    pub statuses_store: DbStatusesStore,
+    pub block_index_store: DbBlockIndexStore,
+    pub(crate) db: Arc<DBStorage>,
+    /// The config of a secondary instance, with which its stores are rebuilt on catch up
+    secondary_config: Option<FlexiDagStorageConfig>,
 }
 
 #[derive(Clone, Default)]
 pub struct GhostDagStoreConfig {
     pub block_level: u8,
     pub cache_size: u64,
+    /// The entries of the compact data cache, which serves the blue score, blue work and
+    /// selected parent reads
+    pub compact_cache_size: u64,
+    pub cf_conf: ColumnFamilyConfig,
 }
 
 #[derive(Clone, Default)]
 pub struct HeaderStoreConfig {
     pub cache_size: u64,
+    pub cf_conf: ColumnFamilyConfig,
 }
 
 #[derive(Clone, Default)]
 pub struct ReachabilityStoreConfig {
     pub cache_size: u64,
+    pub cf_conf: ColumnFamilyConfig,
 }
 
 #[derive(Clone, Default)]
 pub struct RelationsStoreConfig {
     pub block_level: u8,
     pub cache_size: u64,
+    pub cf_conf: ColumnFamilyConfig,
 }
 
 #[derive(Clone, Default)]
@@ -45,6 +259,17 @@ pub struct FlexiDagStorageConfig {
     pub hs_conf: HeaderStoreConfig,
     pub rbs_conf: ReachabilityStoreConfig,
     pub rs_conf: RelationsStoreConfig,
+    /// The options of the column families not owned by one of the stores above
+    pub default_cf_conf: ColumnFamilyConfig,
+    /// The size in bytes of the block cache shared by the column families configured
+    /// with `shared_block_cache`
+    pub shared_block_cache_size: usize,
+    /// A memory budget in bytes shared by the caches of all the stores, replacing their
+    /// `cache_size` entry counts
+    pub cache_budget: Option<usize>,
+    /// The record format of the databases created with this config, existing databases
+    /// keeping the format they were created with
+    pub record_format: RecordFormat,
 }
 
 impl FlexiDagStorageConfig {
@@ -58,13 +283,26 @@ impl FlexiDagStorageConfig {
             gds_conf: GhostDagStoreConfig {
                 block_level,
                 cache_size,
+                compact_cache_size: cache_size,
+                cf_conf: ColumnFamilyConfig::point_lookups(),
+            },
+            hs_conf: HeaderStoreConfig {
+                cache_size,
+                cf_conf: ColumnFamilyConfig::default(),
+            },
+            rbs_conf: ReachabilityStoreConfig {
+                cache_size,
+                cf_conf: ColumnFamilyConfig::point_lookups(),
             },
-            hs_conf: HeaderStoreConfig { cache_size },
-            rbs_conf: ReachabilityStoreConfig { cache_size },
             rs_conf: RelationsStoreConfig {
                 block_level,
                 cache_size,
+                cf_conf: ColumnFamilyConfig::default(),
             },
+            default_cf_conf: ColumnFamilyConfig::default(),
+            shared_block_cache_size: SHARED_BLOCK_CACHE_SIZE,
+            cache_budget: None,
+            record_format: RecordFormat::default(),
         }
     }
 
@@ -92,6 +330,60 @@ impl FlexiDagStorageConfig {
         self.rs_conf = rs_conf;
         self
     }
+
+    pub fn update_shared_block_cache_size(mut self, shared_block_cache_size: usize) -> Self {
+        self.shared_block_cache_size = shared_block_cache_size;
+        self
+    }
+
+    pub fn update_cache_budget(mut self, cache_budget: Option<usize>) -> Self {
+        self.cache_budget = cache_budget;
+        self
+    }
+
+    pub fn update_default_cf_conf(mut self, default_cf_conf: ColumnFamilyConfig) -> Self {
+        self.default_cf_conf = default_cf_conf;
+        self
+    }
+
+    pub fn update_record_format(mut self, record_format: RecordFormat) -> Self {
+        self.record_format = record_format;
+        self
+    }
+
+    /// The options of every column family of the storage
+    fn column_families(&self) -> Vec<ColumnFamilyDescriptor> {
+        let block_cache = Cache::new_lru_cache(self.shared_block_cache_size);
+        [
+            // consensus headers
+            (HEADERS_STORE_CF, &self.hs_conf.cf_conf),
+            (COMPACT_HEADER_DATA_STORE_CF, &self.hs_conf.cf_conf),
+            // consensus relations
+            (PARENTS_CF, &self.rs_conf.cf_conf),
+            (CHILDREN_CF, &self.rs_conf.cf_conf),
+            // consensus reachability
+            (REACHABILITY_DATA_CF, &self.rbs_conf.cf_conf),
+            // consensus ghostdag
+            (GHOST_DAG_STORE_CF, &self.gds_conf.cf_conf),
+            (COMPACT_GHOST_DAG_STORE_CF, &self.gds_conf.cf_conf),
+            // dag tips
+            (DAG_TIPS_CF, &self.default_cf_conf),
+            // virtual selected chain
+            (CHAIN_HASH_BY_INDEX_CF, &self.default_cf_conf),
+            (CHAIN_INDEX_BY_HASH_CF, &self.default_cf_conf),
+            (CHAIN_HIGHEST_INDEX_CF, &self.default_cf_conf),
+            // block statuses
+            (STATUS_STORE_CF, &self.default_cf_conf),
+            // block indexes
+            (BLUE_SCORE_INDEX_CF, &self.default_cf_conf),
+            (TIMESTAMP_INDEX_CF, &self.default_cf_conf),
+            // schema version
+            (SCHEMA_VERSION_CF, &self.default_cf_conf),
+        ]
+        .into_iter()
+        .map(|(name, cf_conf)| ColumnFamilyDescriptor::new(name, cf_conf.options(&block_cache)))
+        .collect()
+    }
 }
 
 impl FlexiDagStorage {
@@ -100,48 +392,567 @@ impl FlexiDagStorage {
         db_path: P,
         config: FlexiDagStorageConfig,
     ) -> Result<Self, StoreError> {
-        let rocksdb_config = RocksdbConfig {
-            parallelism: config.parallelism,
-            ..Default::default()
-        };
+        let db = Self::open_db(db_path, &config, OpenMode::ReadWrite)?;
+        upgrade_schema(&db)?;
+        Ok(Self::with_stores(db, config))
+    }
 
-        let db = Arc::new(
-            DBStorage::open_with_cfs(
-                db_path,
-                vec![
-                    // consensus headers
-                    HEADERS_STORE_CF,
-                    COMPACT_HEADER_DATA_STORE_CF,
-                    // consensus relations
-                    PARENTS_CF,
-                    CHILDREN_CF,
-                    // consensus reachability
-                    REACHABILITY_DATA_CF,
-                    // consensus ghostdag
-                    GHOST_DAG_STORE_CF,
-                    COMPACT_GHOST_DAG_STORE_CF,
-                ],
-                false,
-                rocksdb_config,
-                None,
-            )
-            .map_err(|e| StoreError::DBIoError(e.to_string()))?,
-        );
+    /// Loads an existing storage without allowing writes, which then fail with
+    /// `StoreError::ReadOnlyDb`. The storage is not migrated, so its schema must be the
+    /// current one. Several read-only instances may be opened along the writing one.
+    pub fn open_read_only<P: AsRef<Path>>(
+        db_path: P,
+        config: FlexiDagStorageConfig,
+    ) -> Result<Self, StoreError> {
+        let db = Self::open_db(db_path, &config, OpenMode::ReadOnly)?;
+        check_schema(&db)?;
+        Ok(Self::with_stores(db, config))
+    }
+
+    /// Opens a RocksDB secondary instance following the storage at `primary_path`, which
+    /// keeps the view of the time it was opened until `catch_up_with_primary` is called.
+    /// The secondary keeps its own info logs under `secondary_path`.
+    pub fn open_as_secondary<P: AsRef<Path>, Q: AsRef<Path>>(
+        primary_path: P,
+        secondary_path: Q,
+        config: FlexiDagStorageConfig,
+    ) -> Result<Self, StoreError> {
+        let db = Self::open_db(
+            primary_path,
+            &config,
+            OpenMode::Secondary(secondary_path.as_ref()),
+        )?;
+        check_schema(&db)?;
+        let mut storage = Self::with_stores(db, config.clone());
+        storage.secondary_config = Some(config);
+        Ok(storage)
+    }
+
+    /// Makes the writes committed by the primary since the instance was opened, or
+    /// since the last catch up, visible. The caches are dropped in the process.
+    pub fn catch_up_with_primary(&mut self) -> Result<(), StoreError> {
+        let config = self.secondary_config.clone().ok_or_else(|| {
+            StoreError::DBIoError("the storage is not a secondary instance".to_string())
+        })?;
+        self.db.db().try_catch_up_with_primary()?;
+        *self = Self::with_stores(self.db.clone(), config.clone());
+        self.secondary_config = Some(config);
+        Ok(())
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.db.is_read_only()
+    }
+
+    /// The format of the reachability data and of the compact ghostdag data of the storage
+    pub fn record_format(&self) -> RecordFormat {
+        self.db.record_format()
+    }
+
+    /// The byte budget of the caches, if the storage was configured with one
+    pub fn cache_budget(&self) -> Option<&ByteBudget> {
+        self.db.cache_budget().map(|budget| budget.as_ref())
+    }
 
-        Ok(Self {
-            ghost_dag_store: DbGhostdagStore::new(
+    /// The counters of the cache and db accesses of every column family accessed since
+    /// the storage was opened
+    pub fn metrics_snapshot(&self) -> BTreeMap<&'static str, StoreMetrics> {
+        self.db.metrics.snapshot()
+    }
+
+    /// Changes the byte budget of the caches at runtime, evicting entries right away when
+    /// it shrinks. Fails if the storage was not configured with a budget.
+    pub fn resize_cache_budget(&self, capacity: usize) -> Result<(), StoreError> {
+        let budget = self
+            .cache_budget()
+            .ok_or_else(|| StoreError::DBIoError("the storage has no cache budget".to_string()))?;
+        budget.resize(capacity);
+        Ok(())
+    }
+
+    fn open_db<P: AsRef<Path>>(
+        db_path: P,
+        config: &FlexiDagStorageConfig,
+        mode: OpenMode,
+    ) -> Result<Arc<DBStorage>, StoreError> {
+        let mut options = Options::default();
+        options.increase_parallelism(config.parallelism as i32);
+        let column_families = config.column_families();
+        let db = match mode {
+            OpenMode::ReadWrite => {
+                options.create_if_missing(true);
+                options.create_missing_column_families(true);
+                DB::open_cf_descriptors(&options, db_path, column_families)?
+            }
+            OpenMode::ReadOnly => {
+                DB::open_cf_descriptors_read_only(&options, db_path, column_families, false)?
+            }
+            OpenMode::Secondary(secondary_path) => {
+                // Secondary instances must keep all the files of the primary open
+                options.set_max_open_files(-1);
+                DB::open_cf_descriptors_as_secondary(
+                    &options,
+                    db_path,
+                    secondary_path,
+                    column_families,
+                )?
+            }
+        };
+        let mut storage = DBStorage {
+            db,
+            read_only: !matches!(mode, OpenMode::ReadWrite),
+            cache_budget: config
+                .cache_budget
+                .map(|capacity| Arc::new(ByteBudget::new(capacity))),
+            metrics: StoreMetricsRegistry::default(),
+            record_format: RecordFormat::default(),
+        };
+        storage.record_format = resolve_record_format(&storage, config.record_format)?;
+        Ok(Arc::new(storage))
+    }
+
+    fn with_stores(db: Arc<DBStorage>, config: FlexiDagStorageConfig) -> Self {
+        Self {
+            ghost_dag_store: DbGhostdagStore::new_with_compact_cache(
                 db.clone(),
                 config.gds_conf.block_level,
                 config.gds_conf.cache_size,
+                config.gds_conf.compact_cache_size,
             ),
 
             header_store: DbHeadersStore::new(db.clone(), config.hs_conf.cache_size),
             reachability_store: DbReachabilityStore::new(db.clone(), config.rbs_conf.cache_size),
             relations_store: DbRelationsStore::new(
-                db,
+                db.clone(),
                 config.rs_conf.block_level,
                 config.rs_conf.cache_size,
             ),
-        })
+            tips_store: DbTipsStore::new(db.clone()),
+            selected_chain_store: DbSelectedChainStore::new(db.clone(), config.hs_conf.cache_size),
+            statuses_store: DbStatusesStore::new(db.clone(), config.hs_conf.cache_size),
+            block_index_store: DbBlockIndexStore::new(db.clone()),
+            db,
+            secondary_config: None,
+        }
+    }
+
+    /// Atomically writes a batch filled by the `*_batch` functions of the stores
+    pub fn write_batch(&self, batch: DbBatch) -> Result<(), StoreError> {
+        self.db.raw_write_batch(batch)
+    }
+
+    /// Same as `write_batch`, returning once the batch is synced to disk
+    pub fn write_batch_sync(&self, batch: DbBatch) -> Result<(), StoreError> {
+        self.db.raw_write_batch_sync(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{
+        GhostdagStore, GhostdagStoreReader, HeaderStore, ReachabilityStore, RelationsStore,
+        RelationsStoreReader, StatusesStoreReader,
+    };
+    use crate::prelude::{check_storage, CachedDbAccess, FsckIssue};
+    use consensus_types::{
+        blockhash::{BlockHashes, ORIGIN},
+        ghostdata::GhostdagData,
+        header::{ConsensusHeader, Header},
+        interval::Interval,
+        status::BlockStatus,
+    };
+    use starcoin_types::block::BlockHeader;
+
+    #[test]
+    fn test_read_only_and_secondary() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let mut primary =
+            FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone()).unwrap();
+        let origin = Hash::new(ORIGIN);
+        let (a, b) = (Hash::from_u64(1), Hash::from_u64(2));
+        primary
+            .relations_store
+            .insert(origin, BlockHashes::new(vec![]))
+            .unwrap();
+        primary
+            .relations_store
+            .insert(a, BlockHashes::new(vec![origin]))
+            .unwrap();
+
+        // Read-only instances may be opened while the primary is open
+        let mut read_only =
+            FlexiDagStorage::open_read_only(db_tempdir.path(), config.clone()).unwrap();
+        assert!(read_only.is_read_only());
+        assert!(read_only.relations_store.has(a).unwrap());
+        let header = Header::new(BlockHeader::random(), vec![origin]);
+        assert!(matches!(
+            read_only
+                .ghost_dag_store
+                .insert(b, Arc::new(GhostdagData::new_with_selected_parent(a, 1))),
+            Err(StoreError::ReadOnlyDb)
+        ));
+        assert!(matches!(
+            read_only
+                .header_store
+                .insert(header.hash(), Arc::new(header), 0),
+            Err(StoreError::ReadOnlyDb)
+        ));
+        assert!(matches!(
+            read_only
+                .relations_store
+                .insert(b, BlockHashes::new(vec![a])),
+            Err(StoreError::ReadOnlyDb)
+        ));
+        assert!(matches!(
+            read_only
+                .reachability_store
+                .insert(b, a, Interval::maximal(), 1),
+            Err(StoreError::ReadOnlyDb)
+        ));
+        // Refused writes do not show up through the caches
+        assert!(!read_only.relations_store.has(b).unwrap());
+        assert!(read_only.catch_up_with_primary().is_err());
+
+        let secondary_tempdir = tempfile::tempdir().unwrap();
+        let mut secondary =
+            FlexiDagStorage::open_as_secondary(db_tempdir.path(), secondary_tempdir.path(), config)
+                .unwrap();
+        primary
+            .relations_store
+            .insert(b, BlockHashes::new(vec![a]))
+            .unwrap();
+        assert!(!secondary.relations_store.has(b).unwrap());
+        secondary.catch_up_with_primary().unwrap();
+        assert!(secondary.relations_store.has(b).unwrap());
+        assert!(secondary.is_read_only());
+    }
+
+    /// The sections of the latest RocksDB options file of the db at `path` describing the
+    /// options of the column family `cf_name`
+    fn cf_options(path: &Path, cf_name: &str) -> String {
+        let latest = std::fs::read_dir(path)
+            .unwrap()
+            .filter_map(|entry| {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                let number = name.strip_prefix("OPTIONS-")?.parse::<u64>().ok()?;
+                Some((number, name))
+            })
+            .max()
+            .unwrap()
+            .1;
+        let options = std::fs::read_to_string(path.join(latest)).unwrap();
+        let section_end = format!(" \"{}\"]", cf_name);
+        options
+            .split("\n[")
+            .filter(|section| section.lines().next().unwrap().ends_with(&section_end))
+            .collect()
+    }
+
+    #[test]
+    fn test_column_family_configs() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024)
+            .update_headers_conf(HeaderStoreConfig {
+                cache_size: 1024,
+                cf_conf: ColumnFamilyConfig {
+                    shared_block_cache: false,
+                    block_cache_size: Some(2 << 20),
+                    bloom_filter_bits_per_key: None,
+                    compression: DBCompressionType::None,
+                    hash_prefix_extractor: false,
+                },
+            })
+            .update_default_cf_conf(ColumnFamilyConfig::point_lookups())
+            .update_shared_block_cache_size(1 << 20);
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone()).unwrap();
+
+        // The options are applied to each column family
+        let block_cache_capacity = |cf_name| {
+            let db = storage.db.db();
+            db.property_int_value_cf(
+                storage.db.get_cf_handle(cf_name).unwrap(),
+                "rocksdb.block-cache-capacity",
+            )
+            .unwrap()
+            .unwrap()
+        };
+        assert_eq!(block_cache_capacity(HEADERS_STORE_CF), 2 << 20);
+        assert_eq!(block_cache_capacity(GHOST_DAG_STORE_CF), 1 << 20);
+        assert_eq!(block_cache_capacity(STATUS_STORE_CF), 1 << 20);
+        let headers_options = cf_options(db_tempdir.path(), HEADERS_STORE_CF);
+        assert!(headers_options.contains("compression=kNoCompression"));
+        assert!(headers_options.contains("prefix_extractor=nullptr"));
+        assert!(!headers_options.contains("bloomfilter"));
+        for cf_name in [GHOST_DAG_STORE_CF, STATUS_STORE_CF] {
+            let options = cf_options(db_tempdir.path(), cf_name);
+            assert!(options.contains("compression=kLZ4Compression"));
+            assert!(options.contains("prefix_extractor=rocksdb.FixedPrefix.32"));
+            assert!(options.contains("filter_policy=bloomfilter:10"));
+        }
+        let hashes: Vec<_> = (1..=16).map(Hash::from_u64).collect();
+        for hash in hashes.iter() {
+            storage
+                .ghost_dag_store
+                .insert(
+                    *hash,
+                    Arc::new(GhostdagData::new_with_selected_parent(hashes[0], 1)),
+                )
+                .unwrap();
+        }
+        drop(storage);
+
+        // The prefix extractor of the ghostdag column family neither hides entries from
+        // point lookups nor from iterations
+        let storage = FlexiDagStorage::open_read_only(db_tempdir.path(), config).unwrap();
+        for hash in hashes.iter() {
+            assert_eq!(
+                storage.ghost_dag_store.get_selected_parent(*hash).unwrap(),
+                hashes[0]
+            );
+        }
+        assert!(storage
+            .ghost_dag_store
+            .get_selected_parent(Hash::from_u64(17))
+            .is_err());
+        let access = CachedDbAccess::<Hash, Arc<GhostdagData>>::new(
+            storage.db.clone(),
+            0,
+            GHOST_DAG_STORE_CF,
+        );
+        assert_eq!(access.iterator().unwrap().count(), hashes.len());
+    }
+
+    #[test]
+    fn test_cache_budget() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024)
+            .update_cache_budget(Some(1 << 20));
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+        let budget = storage.cache_budget().unwrap();
+        for hash in (1..=16).map(Hash::from_u64) {
+            storage
+                .ghost_dag_store
+                .insert(
+                    hash,
+                    Arc::new(GhostdagData::new_with_selected_parent(Hash::from_u64(1), 1)),
+                )
+                .unwrap();
+        }
+        // The full and compact ghostdag data share the budget
+        assert_eq!(budget.len(), 32);
+        let used = budget.used();
+        assert!(used > 0);
+
+        storage.resize_cache_budget(used / 2).unwrap();
+        assert!(budget.used() <= used / 2);
+        // Evicted entries are read from the db again
+        for hash in (1..=16).map(Hash::from_u64) {
+            assert_eq!(
+                storage.ghost_dag_store.get_selected_parent(hash).unwrap(),
+                Hash::from_u64(1)
+            );
+        }
+        assert!(budget.used() <= used / 2);
+    }
+
+    #[test]
+    fn test_batch_cache_updates() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let mut storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+        let (a, b) = (Hash::from_u64(1), Hash::from_u64(2));
+        let mut batch = DbBatch::default();
+        storage
+            .statuses_store
+            .set_batch(&mut batch, a, BlockStatus::Valid)
+            .unwrap();
+        // Dropped batches leave no trace in the caches
+        drop(batch);
+        assert!(!storage.statuses_store.has(a).unwrap());
+
+        let mut batch = DbBatch::default();
+        storage
+            .statuses_store
+            .set_batch(&mut batch, b, BlockStatus::Valid)
+            .unwrap();
+        assert!(!storage.statuses_store.has(b).unwrap());
+        storage.write_batch(batch).unwrap();
+        assert_eq!(storage.statuses_store.get(b).unwrap(), BlockStatus::Valid);
+
+        let mut batch = DbBatch::default();
+        storage
+            .statuses_store
+            .delete_batch(&mut batch, &[b])
+            .unwrap();
+        assert!(storage.statuses_store.has(b).unwrap());
+        storage.write_batch(batch).unwrap();
+        assert!(!storage.statuses_store.has(b).unwrap());
+    }
+
+    #[test]
+    fn test_compact_data_many() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone()).unwrap();
+        let hashes: Vec<_> = (1..=4).map(Hash::from_u64).collect();
+        for (blue_score, hash) in hashes.iter().enumerate() {
+            let mut data = GhostdagData::new_with_selected_parent(hashes[0], 1);
+            data.finalize_score_and_work(blue_score as u64, (blue_score as u64 * 10).into());
+            storage
+                .ghost_dag_store
+                .insert(*hash, Arc::new(data))
+                .unwrap();
+        }
+        drop(storage);
+
+        // The cache misses are read from the db in a single batch
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+        assert_eq!(
+            storage.ghost_dag_store.get_blue_score(hashes[1]).unwrap(),
+            1
+        );
+        let reversed: Vec<_> = hashes.iter().rev().copied().collect();
+        let data = storage
+            .ghost_dag_store
+            .get_compact_data_many(&reversed)
+            .unwrap();
+        assert_eq!(
+            data.iter().map(|data| data.blue_score).collect::<Vec<_>>(),
+            vec![3, 2, 1, 0]
+        );
+        let metrics = storage.metrics_snapshot();
+        let compact = metrics[COMPACT_GHOST_DAG_STORE_CF];
+        assert_eq!((compact.hits, compact.misses, compact.db_reads), (1, 4, 4));
+        assert_eq!(compact.deserializations, 5);
+        // The score, work and selected parent reads never decode the full data
+        assert_eq!(metrics[GHOST_DAG_STORE_CF].deserializations, 0);
+
+        assert!(matches!(
+            storage
+                .ghost_dag_store
+                .get_compact_data_many(&[hashes[0], Hash::from_u64(9)]),
+            Err(StoreError::KeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_format() {
+        let fill = |storage: &mut FlexiDagStorage| {
+            let origin = Hash::new(ORIGIN);
+            let header = Header::new(BlockHeader::random(), vec![origin]);
+            storage
+                .header_store
+                .insert(header.hash(), Arc::new(header.clone()), 0)
+                .unwrap();
+            let store = &mut storage.reachability_store;
+            store.init(origin, Interval::new(1, 100)).unwrap();
+            store
+                .insert(header.hash(), origin, Interval::new(10, 20), 1)
+                .unwrap();
+            store.append_child(origin, header.hash()).unwrap();
+            let mut data = GhostdagData::new_with_selected_parent(origin, 1);
+            data.finalize_score_and_work(1, 10.into());
+            storage
+                .ghost_dag_store
+                .insert(header.hash(), Arc::new(data))
+                .unwrap();
+            header.hash()
+        };
+        let check = |storage: &FlexiDagStorage, hash: Hash| {
+            let store = &storage.reachability_store;
+            assert_eq!(store.get_interval(hash).unwrap(), Interval::new(10, 20));
+            assert_eq!(store.get_parent(hash).unwrap(), Hash::new(ORIGIN));
+            assert_eq!(store.get_height(hash).unwrap(), 1);
+            assert_eq!(*store.get_children(Hash::new(ORIGIN)).unwrap(), vec![hash]);
+            let compact = storage.ghost_dag_store.get_compact_data(hash).unwrap();
+            assert_eq!((compact.blue_score, compact.blue_work), (1, 10));
+            let report = check_storage(storage).unwrap();
+            assert_eq!(report.scanned[REACHABILITY_DATA_CF], 2);
+            assert!(!report
+                .issues
+                .iter()
+                .any(|issue| matches!(issue, FsckIssue::UndecodableEntry { .. })));
+        };
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let fixed_config = config.clone().update_record_format(RecordFormat::FixedV1);
+
+        // A new db takes the configured format, and keeps it whatever the config
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let mut storage =
+            FlexiDagStorage::create_from_path(db_tempdir.path(), fixed_config.clone()).unwrap();
+        assert_eq!(storage.record_format(), RecordFormat::FixedV1);
+        let hash = fill(&mut storage);
+        check(&storage, hash);
+        drop(storage);
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone()).unwrap();
+        assert_eq!(storage.record_format(), RecordFormat::FixedV1);
+        check(&storage, hash);
+        let read_only = FlexiDagStorage::open_read_only(db_tempdir.path(), config.clone()).unwrap();
+        check(&read_only, hash);
+        drop((storage, read_only));
+
+        // A db created before the format was recorded is bincode encoded
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let mut storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+        let hash = fill(&mut storage);
+        storage
+            .db
+            .remove(SCHEMA_VERSION_CF, "record-format")
+            .unwrap();
+        drop(storage);
+        let storage =
+            FlexiDagStorage::create_from_path(db_tempdir.path(), fixed_config.clone()).unwrap();
+        assert_eq!(storage.record_format(), RecordFormat::Bincode);
+        check(&storage, hash);
+
+        // Unknown formats are refused
+        storage
+            .db
+            .put(
+                SCHEMA_VERSION_CF,
+                "record-format",
+                bincode::serialize(&2u32).unwrap(),
+            )
+            .unwrap();
+        drop(storage);
+        assert!(matches!(
+            FlexiDagStorage::create_from_path(db_tempdir.path(), fixed_config),
+            Err(StoreError::UnsupportedRecordFormat(2))
+        ));
+    }
+
+    #[test]
+    fn test_metrics_snapshot() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone()).unwrap();
+        let (a, b) = (Hash::from_u64(1), Hash::from_u64(2));
+        storage
+            .ghost_dag_store
+            .insert(a, Arc::new(GhostdagData::new_with_selected_parent(b, 1)))
+            .unwrap();
+        let before = storage.metrics_snapshot()[GHOST_DAG_STORE_CF];
+        assert_eq!(before.writes, 1);
+        assert!(before.bytes_serialized > 0);
+
+        // Written entries are cached
+        storage.ghost_dag_store.get_data(a).unwrap();
+        assert!(storage.ghost_dag_store.get_data(b).is_err());
+        let after = storage.metrics_snapshot()[GHOST_DAG_STORE_CF];
+        assert_eq!(after.hits, before.hits + 1);
+        assert_eq!(after.misses, before.misses + 1);
+        assert_eq!(after.db_reads, before.db_reads + 1);
+        assert_eq!(after.deserialization_errors, 0);
+        drop(storage);
+
+        // Reopened storages start with cold caches and fresh counters
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+        storage.ghost_dag_store.get_data(a).unwrap();
+        storage.ghost_dag_store.get_data(a).unwrap();
+        let metrics = storage.metrics_snapshot()[GHOST_DAG_STORE_CF];
+        assert_eq!((metrics.hits, metrics.misses, metrics.db_reads), (1, 1, 1));
+        assert_eq!(metrics.hit_ratio(), Some(0.5));
     }
 }
@@ -22,6 +22,21 @@ pub enum StoreError {
 
     #[error("ghostdag {0} duplicate blocks")]
     DAGDupBlocksError(String),
+
+    #[error("the db schema version {0} is not supported, this build uses version {1}")]
+    UnsupportedSchemaVersion(u32, u32),
+
+    #[error("the db record format {0} is not supported")]
+    UnsupportedRecordFormat(u32),
+
+    #[error("malformed fixed layout record: {0}")]
+    MalformedRecord(String),
+
+    #[error("the backup is inconsistent: {0}")]
+    InconsistentBackup(String),
+
+    #[error("the db is opened read-only")]
+    ReadOnlyDb,
 }
 
 pub type StoreResult<T> = std::result::Result<T, StoreError>;
@@ -0,0 +1,461 @@
+//!
+//! The encodings of the records read on the hot paths of the dag.
+//!
+//! Records are bincode encoded, which means decoding their hash lists into freshly
+//! allocated vectors on every read. The fixed layout stores the fixed size fields of a
+//! record at fixed offsets, in little endian, followed by its hash lists, so that these
+//! fields are read in place from the cached bytes or the pinned slices of the db.
+//!
+//! The format of a database is chosen when it is created and recorded along its schema
+//! version. Databases created before the format was recorded are bincode encoded.
+//!
+use crate::{
+    access::RecordBytes,
+    errors::{StoreError, StoreResult},
+};
+use consensus_types::{
+    blockhash::{BlockHashes, BlueWorkType},
+    ghostdata::CompactGhostdagData,
+    interval::Interval,
+    reachability::ReachabilityData,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use starcoin_crypto::HashValue as Hash;
+use std::{cmp::Ordering, ops::Range, sync::Arc};
+
+/// The encoding of the reachability data and of the compact ghostdag data of a database
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// The bincode encoding of the records
+    #[default]
+    Bincode,
+    /// The first version of the fixed layout
+    FixedV1,
+}
+
+impl RecordFormat {
+    /// The version recorded in the db
+    pub fn version(self) -> u32 {
+        match self {
+            RecordFormat::Bincode => 0,
+            RecordFormat::FixedV1 => 1,
+        }
+    }
+
+    pub fn from_version(version: u32) -> StoreResult<Self> {
+        match version {
+            0 => Ok(RecordFormat::Bincode),
+            1 => Ok(RecordFormat::FixedV1),
+            _ => Err(StoreError::UnsupportedRecordFormat(version)),
+        }
+    }
+
+    pub(crate) fn encode<T: Record>(self, data: &T) -> StoreResult<Vec<u8>> {
+        match self {
+            RecordFormat::Bincode => Ok(bincode::serialize(data)?),
+            RecordFormat::FixedV1 => {
+                let mut bytes = Vec::with_capacity(T::FIXED_LEN);
+                data.encode_fixed(&mut bytes);
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub(crate) fn decode<T: Record>(self, bytes: &[u8]) -> StoreResult<T> {
+        match self {
+            RecordFormat::Bincode => Ok(bincode::deserialize(bytes)?),
+            RecordFormat::FixedV1 => T::decode_fixed(bytes),
+        }
+    }
+
+    /// Decodes the fixed size fields only, in place with the fixed layout
+    pub(crate) fn decode_fields<T: Record>(self, bytes: &[u8]) -> StoreResult<T::Fields> {
+        match self {
+            RecordFormat::Bincode => Ok(bincode::deserialize::<T>(bytes)?.fields()),
+            RecordFormat::FixedV1 => T::decode_fixed_fields(bytes),
+        }
+    }
+}
+
+/// A record having a fixed layout encoding besides its bincode one
+pub trait Record: Serialize + DeserializeOwned {
+    /// The fixed size fields of the record
+    type Fields;
+
+    /// The length of the fixed size part of the encoding
+    const FIXED_LEN: usize;
+
+    fn fields(&self) -> Self::Fields;
+    fn encode_fixed(&self, bytes: &mut Vec<u8>);
+    fn decode_fixed(bytes: &[u8]) -> StoreResult<Self>;
+    fn decode_fixed_fields(bytes: &[u8]) -> StoreResult<Self::Fields>;
+}
+
+impl<T: Record> Record for Arc<T> {
+    type Fields = T::Fields;
+
+    const FIXED_LEN: usize = T::FIXED_LEN;
+
+    fn fields(&self) -> Self::Fields {
+        T::fields(self)
+    }
+
+    fn encode_fixed(&self, bytes: &mut Vec<u8>) {
+        T::encode_fixed(self, bytes)
+    }
+
+    fn decode_fixed(bytes: &[u8]) -> StoreResult<Self> {
+        T::decode_fixed(bytes).map(Arc::new)
+    }
+
+    fn decode_fixed_fields(bytes: &[u8]) -> StoreResult<Self::Fields> {
+        T::decode_fixed_fields(bytes)
+    }
+}
+
+/// The fixed size fields of `ReachabilityData`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReachabilityFields {
+    pub parent: Hash,
+    pub interval: Interval,
+    pub height: u64,
+}
+
+/// parent | interval start | interval end | height | children count | future covering
+/// set count | children | future covering set
+impl Record for ReachabilityData {
+    type Fields = ReachabilityFields;
+
+    const FIXED_LEN: usize = Hash::LENGTH + 3 * 8 + 2 * 4;
+
+    fn fields(&self) -> Self::Fields {
+        ReachabilityFields {
+            parent: self.parent,
+            interval: self.interval,
+            height: self.height,
+        }
+    }
+
+    fn encode_fixed(&self, bytes: &mut Vec<u8>) {
+        bytes.reserve(
+            Self::FIXED_LEN + (self.children.len() + self.future_covering_set.len()) * Hash::LENGTH,
+        );
+        bytes.extend_from_slice(self.parent.as_ref());
+        bytes.extend_from_slice(&self.interval.start.to_le_bytes());
+        bytes.extend_from_slice(&self.interval.end.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&(self.children.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.future_covering_set.len() as u32).to_le_bytes());
+        for hash in self.children.iter().chain(self.future_covering_set.iter()) {
+            bytes.extend_from_slice(hash.as_ref());
+        }
+    }
+
+    fn decode_fixed(bytes: &[u8]) -> StoreResult<Self> {
+        let fields = Self::decode_fixed_fields(bytes)?;
+        let (children, future_covering_set) = reachability_hash_lists(bytes)?;
+        Ok(ReachabilityData {
+            children: Arc::new(decode_hashes(&bytes[children])),
+            parent: fields.parent,
+            interval: fields.interval,
+            height: fields.height,
+            future_covering_set: Arc::new(decode_hashes(&bytes[future_covering_set])),
+        })
+    }
+
+    fn decode_fixed_fields(bytes: &[u8]) -> StoreResult<Self::Fields> {
+        let mut reader = FixedReader::new(bytes);
+        Ok(ReachabilityFields {
+            parent: reader.hash()?,
+            interval: Interval {
+                start: reader.u64()?,
+                end: reader.u64()?,
+            },
+            height: reader.u64()?,
+        })
+    }
+}
+
+/// The byte ranges of the children and of the future covering set of a fixed layout
+/// `ReachabilityData` record
+pub(crate) fn reachability_hash_lists(bytes: &[u8]) -> StoreResult<(Range<usize>, Range<usize>)> {
+    let mut reader = FixedReader::new(bytes);
+    reader.skip(Hash::LENGTH + 3 * 8)?;
+    let children_len = (reader.u32()? as usize).saturating_mul(Hash::LENGTH);
+    let future_covering_set_len = (reader.u32()? as usize).saturating_mul(Hash::LENGTH);
+    reader.skip(children_len)?;
+    reader.skip(future_covering_set_len)?;
+    reader.finish()?;
+    let children_end = ReachabilityData::FIXED_LEN + children_len;
+    Ok((
+        ReachabilityData::FIXED_LEN..children_end,
+        children_end..bytes.len(),
+    ))
+}
+
+fn decode_hashes(bytes: &[u8]) -> Vec<Hash> {
+    bytes.chunks_exact(Hash::LENGTH).map(hash_at).collect()
+}
+
+fn hash_at(bytes: &[u8]) -> Hash {
+    Hash::new(bytes.try_into().expect("the chunks are hashes"))
+}
+
+/// A list of hashes of a record, read in place from its encoded bytes when the record has
+/// the fixed layout rather than decoded into a vector
+pub enum HashList<'a> {
+    Encoded {
+        bytes: RecordBytes<'a>,
+        /// The range of the hashes in the bytes
+        range: Range<usize>,
+    },
+    Decoded(BlockHashes),
+}
+
+impl HashList<'_> {
+    pub fn len(&self) -> usize {
+        match self {
+            HashList::Encoded { range, .. } => range.len() / Hash::LENGTH,
+            HashList::Decoded(hashes) => hashes.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<Hash> {
+        match self {
+            HashList::Encoded { bytes, range } => {
+                let start = range.start + index.checked_mul(Hash::LENGTH)?;
+                (start < range.end).then(|| hash_at(&bytes[start..start + Hash::LENGTH]))
+            }
+            HashList::Decoded(hashes) => hashes.get(index).copied(),
+        }
+    }
+
+    /// The hash at `index`, panicking when it is out of bounds as slice indexing does
+    pub fn hash(&self, index: usize) -> Hash {
+        self.get(index).unwrap_or_else(|| {
+            panic!(
+                "index {} is out of bounds of a list of {} hashes",
+                index,
+                self.len()
+            )
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Hash> + '_ {
+        (0..self.len()).map(|index| self.hash(index))
+    }
+
+    pub fn to_vec(&self) -> Vec<Hash> {
+        match self {
+            HashList::Encoded { bytes, range } => decode_hashes(&bytes[range.clone()]),
+            HashList::Decoded(hashes) => hashes.to_vec(),
+        }
+    }
+
+    /// Binary searches the hashes sorted by `f`, as `slice::binary_search_by_key` does
+    pub fn binary_search_by_key<B: Ord>(
+        &self,
+        key: &B,
+        mut f: impl FnMut(Hash) -> B,
+    ) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match f(self.hash(middle)).cmp(key) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return Ok(middle),
+            }
+        }
+        Err(low)
+    }
+}
+
+impl From<BlockHashes> for HashList<'_> {
+    fn from(hashes: BlockHashes) -> Self {
+        HashList::Decoded(hashes)
+    }
+}
+
+/// blue score | blue work | selected parent
+impl Record for CompactGhostdagData {
+    type Fields = Self;
+
+    const FIXED_LEN: usize = 8 + 16 + Hash::LENGTH;
+
+    fn fields(&self) -> Self::Fields {
+        *self
+    }
+
+    fn encode_fixed(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.blue_score.to_le_bytes());
+        bytes.extend_from_slice(&self.blue_work.to_le_bytes());
+        bytes.extend_from_slice(self.selected_parent.as_ref());
+    }
+
+    fn decode_fixed(bytes: &[u8]) -> StoreResult<Self> {
+        let mut reader = FixedReader::new(bytes);
+        let data = CompactGhostdagData {
+            blue_score: reader.u64()?,
+            blue_work: BlueWorkType::from_le_bytes(reader.array()?),
+            selected_parent: reader.hash()?,
+        };
+        reader.finish()?;
+        Ok(data)
+    }
+
+    fn decode_fixed_fields(bytes: &[u8]) -> StoreResult<Self::Fields> {
+        Self::decode_fixed(bytes)
+    }
+}
+
+/// Reads the fields of a fixed layout record one after the other
+struct FixedReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> FixedReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> StoreResult<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(StoreError::MalformedRecord(format!(
+                "{} bytes missing",
+                len - self.bytes.len()
+            )));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn skip(&mut self, len: usize) -> StoreResult<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn array<const N: usize>(&mut self) -> StoreResult<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("N bytes were taken"))
+    }
+
+    fn u32(&mut self) -> StoreResult<u32> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> StoreResult<u64> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn hash(&mut self) -> StoreResult<Hash> {
+        self.array().map(Hash::new)
+    }
+
+    /// Checks that the whole record was read
+    fn finish(self) -> StoreResult<()> {
+        if !self.bytes.is_empty() {
+            return Err(StoreError::MalformedRecord(format!(
+                "{} trailing bytes",
+                self.bytes.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CachedBytes;
+
+    fn reachability_data() -> ReachabilityData {
+        ReachabilityData {
+            children: Arc::new(vec![1.into(), 2.into()]),
+            parent: 3.into(),
+            interval: Interval::new(10, 20),
+            height: 7,
+            future_covering_set: Arc::new(vec![4.into()]),
+        }
+    }
+
+    #[test]
+    fn test_fixed_reachability_data() {
+        let data = reachability_data();
+        let bytes = RecordFormat::FixedV1.encode(&data).unwrap();
+        assert_eq!(bytes.len(), ReachabilityData::FIXED_LEN + 3 * Hash::LENGTH);
+
+        let decoded: ReachabilityData = RecordFormat::FixedV1.decode(&bytes).unwrap();
+        assert_eq!(decoded.children, data.children);
+        assert_eq!(decoded.future_covering_set, data.future_covering_set);
+        assert_eq!(decoded.fields(), data.fields());
+        assert_eq!(
+            RecordFormat::FixedV1
+                .decode_fields::<ReachabilityData>(&bytes)
+                .unwrap(),
+            data.fields()
+        );
+
+        // The hash lists are read in place
+        let (children, future_covering_set) = reachability_hash_lists(&bytes).unwrap();
+        let list = |range| HashList::Encoded {
+            bytes: RecordBytes::Cached(CachedBytes::from(bytes.clone())),
+            range,
+        };
+        let (children, future_covering_set) = (list(children), list(future_covering_set));
+        assert_eq!(children.to_vec(), *data.children);
+        assert_eq!(children.iter().collect::<Vec<_>>(), *data.children);
+        assert_eq!(future_covering_set.to_vec(), *data.future_covering_set);
+        assert_eq!(children.get(2), None);
+        assert_eq!(
+            children.binary_search_by_key(&data.children[1], |hash| hash),
+            Ok(1)
+        );
+        assert_eq!(
+            children.binary_search_by_key(&0.into(), |hash| hash),
+            Err(0)
+        );
+
+        // Truncated and overlong records are refused
+        let overlong = [bytes.as_slice(), &[0]].concat();
+        for bytes in [&bytes[..bytes.len() - 1], overlong.as_slice()] {
+            assert!(matches!(
+                RecordFormat::FixedV1.decode::<ReachabilityData>(bytes),
+                Err(StoreError::MalformedRecord(_))
+            ));
+            assert!(reachability_hash_lists(bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn test_fixed_compact_ghostdag_data() {
+        let data = CompactGhostdagData {
+            blue_score: 5,
+            blue_work: BlueWorkType::MAX - 1,
+            selected_parent: 9.into(),
+        };
+        let bytes = RecordFormat::FixedV1.encode(&data).unwrap();
+        assert_eq!(bytes.len(), CompactGhostdagData::FIXED_LEN);
+        let decoded: CompactGhostdagData = RecordFormat::FixedV1.decode(&bytes).unwrap();
+        assert_eq!(decoded.blue_score, data.blue_score);
+        assert_eq!(decoded.blue_work, data.blue_work);
+        assert_eq!(decoded.selected_parent, data.selected_parent);
+    }
+
+    #[test]
+    fn test_record_format_versions() {
+        for format in [RecordFormat::Bincode, RecordFormat::FixedV1] {
+            assert_eq!(
+                RecordFormat::from_version(format.version()).unwrap(),
+                format
+            );
+        }
+        assert!(matches!(
+            RecordFormat::from_version(2),
+            Err(StoreError::UnsupportedRecordFormat(2))
+        ));
+    }
+}
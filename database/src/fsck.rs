@@ -0,0 +1,370 @@
+//!
+//! A consistency checker for `FlexiDagStorage`, meant to find out databases left in
+//! an inconsistent state, by a crash for instance.
+//!
+//! The column families are scanned one by one, and each entry is checked against the
+//! entries of the other stores it relates to. The findings are gathered in a
+//! serializable report.
+//!
+use crate::{
+    consensus::{
+        GhostdagStoreReader, ReachabilityStoreReader, RelationsStoreReader, CHILDREN_CF,
+        GHOST_DAG_STORE_CF, HEADERS_STORE_CF, PARENTS_CF, REACHABILITY_DATA_CF,
+    },
+    db::DBStorage,
+    errors::{StoreError, StoreResult},
+    format::Record,
+    prelude::FlexiDagStorage,
+};
+use consensus_types::{
+    blockhash::BlockHashExtensions, ghostdata::GhostdagData, header::HeaderWithBlockLevel,
+    interval::Interval, reachability::ReachabilityData,
+};
+use rocksdb::{IteratorMode, ReadOptions};
+use serde::{de::DeserializeOwned, Serialize};
+use starcoin_crypto::HashValue as Hash;
+use std::collections::BTreeMap;
+
+/// A broken invariant found by `check_storage`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FsckIssue {
+    /// `child` lists `parent` as a parent, but `parent` does not list `child` as a child
+    MissingChildEntry {
+        parent: Hash,
+        child: Hash,
+    },
+    /// `parent` lists `child` as a child, but `child` does not list `parent` as a parent
+    MissingParentEntry {
+        child: Hash,
+        parent: Hash,
+    },
+    MissingRelations {
+        block: Hash,
+    },
+    MissingGhostdagData {
+        block: Hash,
+    },
+    MissingReachabilityData {
+        block: Hash,
+    },
+    /// The interval of `block` is not strictly contained in the one of its tree parent
+    IntervalOutOfParent {
+        block: Hash,
+        parent: Hash,
+        interval: Interval,
+        parent_interval: Interval,
+    },
+    /// The future covering set of `block` is not sorted by interval
+    UnsortedFutureCoveringSet {
+        block: Hash,
+    },
+    SelectedParentNotAParent {
+        block: Hash,
+        selected_parent: Hash,
+    },
+    UndecodableEntry {
+        column_family: String,
+        key: String,
+        error: String,
+    },
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct FsckReport {
+    /// The number of entries scanned, per column family
+    pub scanned: BTreeMap<String, u64>,
+    pub issues: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Scans the consensus column families of `storage` and reports the broken invariants.
+/// Errors are only returned when the db itself cannot be read.
+pub fn check_storage(storage: &FlexiDagStorage) -> StoreResult<FsckReport> {
+    let mut checker = Checker {
+        storage,
+        report: FsckReport::default(),
+    };
+    checker.check_parents()?;
+    checker.check_children()?;
+    checker.check_headers()?;
+    checker.check_reachability()?;
+    checker.check_ghostdag()?;
+    Ok(checker.report)
+}
+
+struct Checker<'a> {
+    storage: &'a FlexiDagStorage,
+    report: FsckReport,
+}
+
+impl Checker<'_> {
+    /// Visits the entries of `cf` keyed by a block hash, reporting those which cannot be
+    /// decoded. Other keys, like the reachability reindex root, are skipped.
+    fn scan<T: DeserializeOwned>(
+        &mut self,
+        cf: &'static str,
+        visit: impl FnMut(&mut Self, Hash, T) -> StoreResult<()>,
+    ) -> StoreResult<()> {
+        self.scan_with(cf, |bytes| bincode::deserialize(bytes), visit)
+    }
+
+    /// Like `scan`, the entries being decoded with the record format of the db
+    fn scan_records<T: Record>(
+        &mut self,
+        cf: &'static str,
+        visit: impl FnMut(&mut Self, Hash, T) -> StoreResult<()>,
+    ) -> StoreResult<()> {
+        let format = self.storage.record_format();
+        self.scan_with(cf, |bytes| format.decode(bytes), visit)
+    }
+
+    fn scan_with<T, E: ToString>(
+        &mut self,
+        cf: &'static str,
+        decode: impl Fn(&[u8]) -> Result<T, E>,
+        mut visit: impl FnMut(&mut Self, Hash, T) -> StoreResult<()>,
+    ) -> StoreResult<()> {
+        let db: &DBStorage = &self.storage.db;
+        let iterator = db
+            .raw_iterator_cf_opt(cf, IteratorMode::Start, ReadOptions::default())
+            .map_err(|e| StoreError::CFNotExist(e.to_string()))?;
+        let mut scanned = 0;
+        for item in iterator {
+            let (key, value) = item?;
+            if key.len() != Hash::LENGTH {
+                continue;
+            }
+            scanned += 1;
+            let hash = Hash::from_slice(&key).expect("the key length was checked");
+            match decode(&value) {
+                Ok(data) => visit(self, hash, data)?,
+                Err(error) => self.report.issues.push(FsckIssue::UndecodableEntry {
+                    column_family: cf.to_string(),
+                    key: faster_hex::hex_string(&key),
+                    error: error.to_string(),
+                }),
+            }
+        }
+        self.report.scanned.insert(cf.to_string(), scanned);
+        Ok(())
+    }
+
+    /// Turns a missing entry into `issue`, other errors are returned
+    fn found<T>(&mut self, result: StoreResult<T>, issue: FsckIssue) -> StoreResult<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(StoreError::KeyNotFound(_)) => {
+                self.report.issues.push(issue);
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn check_parents(&mut self) -> StoreResult<()> {
+        self.scan(PARENTS_CF, |checker, child, parents: Vec<Hash>| {
+            for parent in parents {
+                let children = checker.storage.relations_store.get_children(parent);
+                let issue = FsckIssue::MissingChildEntry { parent, child };
+                if let Some(children) = checker.found(children, issue.clone())? {
+                    if !children.contains(&child) {
+                        checker.report.issues.push(issue);
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn check_children(&mut self) -> StoreResult<()> {
+        self.scan(CHILDREN_CF, |checker, parent, children: Vec<Hash>| {
+            for child in children {
+                let parents = checker.storage.relations_store.get_parents(child);
+                let issue = FsckIssue::MissingParentEntry { child, parent };
+                if let Some(parents) = checker.found(parents, issue.clone())? {
+                    if !parents.contains(&parent) {
+                        checker.report.issues.push(issue);
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn check_headers(&mut self) -> StoreResult<()> {
+        self.scan(
+            HEADERS_STORE_CF,
+            |checker, block, _: HeaderWithBlockLevel| {
+                if !checker.storage.ghost_dag_store.has(block)? {
+                    checker
+                        .report
+                        .issues
+                        .push(FsckIssue::MissingGhostdagData { block });
+                }
+                if !checker.storage.reachability_store.has(block)? {
+                    checker
+                        .report
+                        .issues
+                        .push(FsckIssue::MissingReachabilityData { block });
+                }
+                Ok(())
+            },
+        )
+    }
+
+    fn check_reachability(&mut self) -> StoreResult<()> {
+        self.scan_records(
+            REACHABILITY_DATA_CF,
+            |checker, block, data: ReachabilityData| {
+                let storage = checker.storage;
+                let store = &storage.reachability_store;
+                // The origin is the root of the reachability tree
+                if !data.parent.is_none() {
+                    let parent_interval = store.get_interval(data.parent);
+                    let issue = FsckIssue::MissingReachabilityData { block: data.parent };
+                    if let Some(parent_interval) = checker.found(parent_interval, issue)? {
+                        if !parent_interval.strictly_contains(data.interval) {
+                            checker.report.issues.push(FsckIssue::IntervalOutOfParent {
+                                block,
+                                parent: data.parent,
+                                interval: data.interval,
+                                parent_interval,
+                            });
+                        }
+                    }
+                }
+
+                let mut intervals = Vec::with_capacity(data.future_covering_set.len());
+                for item in data.future_covering_set.iter() {
+                    let interval = store.get_interval(*item);
+                    let issue = FsckIssue::MissingReachabilityData { block: *item };
+                    if let Some(interval) = checker.found(interval, issue)? {
+                        intervals.push(interval);
+                    }
+                }
+                if !intervals.windows(2).all(|w| w[0].end < w[1].start) {
+                    checker
+                        .report
+                        .issues
+                        .push(FsckIssue::UnsortedFutureCoveringSet { block });
+                }
+                Ok(())
+            },
+        )
+    }
+
+    fn check_ghostdag(&mut self) -> StoreResult<()> {
+        self.scan(GHOST_DAG_STORE_CF, |checker, block, data: GhostdagData| {
+            let parents = checker.storage.relations_store.get_parents(block);
+            let issue = FsckIssue::MissingRelations { block };
+            if let Some(parents) = checker.found(parents, issue)? {
+                if !parents.contains(&data.selected_parent) {
+                    checker
+                        .report
+                        .issues
+                        .push(FsckIssue::SelectedParentNotAParent {
+                            block,
+                            selected_parent: data.selected_parent,
+                        });
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        consensus::{GhostdagStore, ReachabilityStore, RelationsStore},
+        prelude::{CachedDbAccess, DirectDbWriter, FlexiDagStorageConfig},
+    };
+    use consensus_types::blockhash::{BlockHashes, ORIGIN};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_check_storage() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let mut storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+        let report = check_storage(&storage).unwrap();
+        assert!(report.is_consistent());
+
+        let origin = Hash::new(ORIGIN);
+        let (a, b, c) = (Hash::from_u64(1), Hash::from_u64(2), Hash::from_u64(3));
+        storage
+            .relations_store
+            .insert(origin, BlockHashes::new(vec![]))
+            .unwrap();
+        storage
+            .relations_store
+            .insert(a, BlockHashes::new(vec![origin]))
+            .unwrap();
+        storage
+            .relations_store
+            .insert(b, BlockHashes::new(vec![a]))
+            .unwrap();
+        // Break the mirror of c -> a
+        let parents_access =
+            CachedDbAccess::<Hash, BlockHashes>::new(storage.db.clone(), 0, PARENTS_CF);
+        parents_access
+            .write(
+                DirectDbWriter::new(&storage.db),
+                c,
+                BlockHashes::new(vec![a]),
+            )
+            .unwrap();
+
+        storage
+            .ghost_dag_store
+            .insert(
+                b,
+                Arc::new(GhostdagData {
+                    selected_parent: origin,
+                    ..Default::default()
+                }),
+            )
+            .unwrap();
+
+        let store = &mut storage.reachability_store;
+        store.init(origin, Interval::new(1, 100)).unwrap();
+        store.insert(a, origin, Interval::new(10, 20), 1).unwrap();
+        store.append_child(origin, a).unwrap();
+        store.insert(b, a, Interval::new(15, 30), 2).unwrap();
+        store.append_child(a, b).unwrap();
+        store.insert(c, origin, Interval::new(40, 50), 1).unwrap();
+        store.insert_future_covering_item(c, b, 0).unwrap();
+        store.insert_future_covering_item(c, a, 1).unwrap();
+
+        let report = check_storage(&storage).unwrap();
+        assert_eq!(report.scanned[PARENTS_CF], 4);
+        assert_eq!(report.scanned[REACHABILITY_DATA_CF], 4);
+        assert_eq!(
+            report.issues,
+            vec![
+                FsckIssue::MissingChildEntry {
+                    parent: a,
+                    child: c
+                },
+                FsckIssue::IntervalOutOfParent {
+                    block: b,
+                    parent: a,
+                    interval: Interval::new(15, 30),
+                    parent_interval: Interval::new(10, 20),
+                },
+                FsckIssue::UnsortedFutureCoveringSet { block: c },
+                FsckIssue::SelectedParentNotAParent {
+                    block: b,
+                    selected_parent: origin,
+                },
+            ]
+        );
+    }
+}
@@ -1,9 +1,8 @@
-use crate::{db::DBStorage, errors::StoreError};
+use crate::{db::DBStorage, errors::StoreError, metrics::StoreCounters};
 
 use super::prelude::DbWriter;
 use parking_lot::RwLock;
 use serde::{de::DeserializeOwned, Serialize};
-use starcoin_storage::storage::RawDBStorage;
 use std::sync::Arc;
 
 /// A cached DB item with concurrency support
@@ -13,11 +12,13 @@ pub struct CachedDbItem<T> {
     key: Vec<u8>,
     prefix: &'static str,
     cached_item: Arc<RwLock<Option<T>>>,
+    counters: Arc<StoreCounters>,
 }
 
 impl<T> CachedDbItem<T> {
     pub fn new(db: Arc<DBStorage>, prefix: &'static str, key: Vec<u8>) -> Self {
         Self {
+            counters: db.store_counters(prefix),
             db,
             key,
             prefix,
@@ -30,14 +31,17 @@ impl<T> CachedDbItem<T> {
         T: Clone + DeserializeOwned,
     {
         if let Some(item) = self.cached_item.read().clone() {
+            self.counters.hit();
             return Ok(item);
         }
+        self.counters.miss();
+        self.counters.db_read();
         if let Some(slice) = self
             .db
             .raw_get_pinned_cf(self.prefix, &self.key)
             .map_err(|_| StoreError::CFNotExist(self.prefix.to_string()))?
         {
-            let item: T = bincode::deserialize(&slice)?;
+            let item: T = self.counters.deserialize(&slice)?;
             *self.cached_item.write() = Some(item.clone());
             Ok(item)
         } else {
@@ -50,47 +54,69 @@ impl<T> CachedDbItem<T> {
 
     pub fn write(&mut self, mut writer: impl DbWriter, item: &T) -> Result<(), StoreError>
     where
-        T: Clone + Serialize,
+        T: Clone + Serialize + Send + Sync + 'static,
     {
-        *self.cached_item.write() = Some(item.clone());
         let bin_data = bincode::serialize(item)?;
+        let len = bin_data.len();
         writer.put(self.prefix, &self.key, bin_data)?;
+        self.counters.write(len);
+        let cached_item = self.cached_item.clone();
+        let item = item.clone();
+        writer.update_cache(Box::new(move || *cached_item.write() = Some(item)));
         Ok(())
     }
 
     pub fn remove(&mut self, mut writer: impl DbWriter) -> Result<(), StoreError>
-where {
-        *self.cached_item.write() = None;
+    where
+        T: Send + Sync + 'static,
+    {
         writer.delete(self.prefix, &self.key)?;
+        self.counters.delete();
+        let cached_item = self.cached_item.clone();
+        writer.update_cache(Box::new(move || *cached_item.write() = None));
         Ok(())
     }
 
+    /// Applies `op` to the item, which the next read then caches again
     pub fn update<F>(&mut self, mut writer: impl DbWriter, op: F) -> Result<T, StoreError>
     where
-        T: Clone + Serialize + DeserializeOwned,
+        T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
         F: Fn(T) -> T,
     {
         let mut guard = self.cached_item.write();
-        let mut item = if let Some(item) = guard.take() {
-            item
-        } else if let Some(slice) = self
-            .db
-            .raw_get_pinned_cf(self.prefix, &self.key)
-            .map_err(|_| StoreError::CFNotExist(self.prefix.to_string()))?
-        {
-            let item: T = bincode::deserialize(&slice)?;
-            item
-        } else {
-            return Err(StoreError::KeyNotFound(
-                String::from_utf8(self.key.clone())
-                    .unwrap_or(("unrecoverable key string").to_string()),
-            ));
+        let mut item = match guard.take() {
+            Some(item) => {
+                self.counters.hit();
+                item
+            }
+            None => {
+                self.counters.miss();
+                self.counters.db_read();
+                match self
+                    .db
+                    .raw_get_pinned_cf(self.prefix, &self.key)
+                    .map_err(|_| StoreError::CFNotExist(self.prefix.to_string()))?
+                {
+                    Some(slice) => self.counters.deserialize(&slice)?,
+                    None => {
+                        return Err(StoreError::KeyNotFound(
+                            String::from_utf8(self.key.clone())
+                                .unwrap_or(("unrecoverable key string").to_string()),
+                        ))
+                    }
+                }
+            }
         };
 
         item = op(item); // Apply the update op
-        *guard = Some(item.clone());
         let bin_data = bincode::serialize(&item)?;
+        let len = bin_data.len();
         writer.put(self.prefix, &self.key, bin_data)?;
+        self.counters.write(len);
+        drop(guard);
+        // A read made before the write is in the db may have cached the previous item
+        let cached_item = self.cached_item.clone();
+        writer.update_cache(Box::new(move || *cached_item.write() = None));
         Ok(item)
     }
 }
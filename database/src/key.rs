@@ -0,0 +1,77 @@
+use crate::errors::StoreError;
+use starcoin_crypto::HashValue as Hash;
+use std::fmt;
+
+/// A `u64` db key, encoded in big endian so that the keys of a column family
+/// iterate in numerical order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct U64Key([u8; 8]);
+
+impl From<u64> for U64Key {
+    fn from(value: u64) -> Self {
+        Self(value.to_be_bytes())
+    }
+}
+
+impl From<U64Key> for u64 {
+    fn from(key: U64Key) -> Self {
+        u64::from_be_bytes(key.0)
+    }
+}
+
+impl AsRef<[u8]> for U64Key {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for U64Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", u64::from(*self))
+    }
+}
+
+/// A db key made of a `u64` in big endian followed by a block hash, so that the keys of a
+/// column family iterate in numerical order, blocks sharing a value being ordered by hash
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct U64HashKey([u8; 8 + Hash::LENGTH]);
+
+impl U64HashKey {
+    pub fn new(value: u64, hash: Hash) -> Self {
+        let mut key = [0; 8 + Hash::LENGTH];
+        key[..8].copy_from_slice(&value.to_be_bytes());
+        key[8..].copy_from_slice(hash.as_ref());
+        Self(key)
+    }
+
+    pub fn value(&self) -> u64 {
+        u64::from_be_bytes(self.0[..8].try_into().expect("the key has 8 value bytes"))
+    }
+
+    pub fn hash(&self) -> Hash {
+        Hash::from_slice(&self.0[8..]).expect("the key has a hash")
+    }
+}
+
+impl TryFrom<&[u8]> for U64HashKey {
+    type Error = StoreError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let key = bytes
+            .try_into()
+            .map_err(|_| StoreError::DBIoError(format!("invalid index key {:?}", bytes)))?;
+        Ok(Self(key))
+    }
+}
+
+impl AsRef<[u8]> for U64HashKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for U64HashKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.value(), self.hash())
+    }
+}
@@ -1,30 +1,49 @@
 mod access;
+mod backup;
 mod cache;
+mod consensus_block_index;
 mod consensus_ghostdag;
 mod consensus_header;
 mod consensus_reachability;
 mod consensus_relations;
+mod consensus_selected_chain;
+mod consensus_status;
+mod consensus_tips;
 mod db;
 mod errors;
+mod format;
+mod fsck;
 mod item;
+mod key;
+mod metrics;
+mod schema;
 mod writer;
 
 pub mod prelude {
-    use crate::{db, errors};
+    use crate::{db, errors, format, fsck, metrics, schema};
 
     pub use super::{
-        access::CachedDbAccess,
-        cache::Cache,
+        access::{CachedDbAccess, RecordAccess, RecordBytes},
+        cache::{ByteBudget, ByteBudgetCache, Cache, CachedBytes, DagCache},
         item::CachedDbItem,
-        writer::{BatchDbWriter, DbWriter, DirectDbWriter},
+        key::{U64HashKey, U64Key},
+        writer::{BatchDbWriter, CacheUpdate, DbBatch, DbWriter, DirectDbWriter},
+    };
+    pub use db::{
+        ColumnFamilyConfig, FlexiDagStorage, FlexiDagStorageConfig, GhostDagStoreConfig,
+        HeaderStoreConfig, ReachabilityStoreConfig, RelationsStoreConfig,
     };
-    pub use db::{FlexiDagStorage, FlexiDagStorageConfig};
     pub use errors::{StoreError, StoreResult, StoreResultEmptyTuple, StoreResultExtensions};
+    pub use format::{HashList, ReachabilityFields, Record, RecordFormat};
+    pub use fsck::{check_storage, FsckIssue, FsckReport};
+    pub use metrics::StoreMetrics;
+    pub use schema::{Migration, CURRENT_SCHEMA_VERSION, MIGRATIONS};
 }
 
 pub mod consensus {
     pub use super::{
-        consensus_ghostdag::*, consensus_header::*, consensus_reachability::*,
-        consensus_relations::*,
+        consensus_block_index::*, consensus_ghostdag::*, consensus_header::*,
+        consensus_reachability::*, consensus_relations::*, consensus_selected_chain::*,
+        consensus_status::*, consensus_tips::*,
     };
 }
@@ -0,0 +1,134 @@
+//!
+//! Counters of the cache and db accesses of the stores, kept per column family.
+//!
+//! Every `CachedDbAccess` and `CachedDbItem` of a column family shares the same counters,
+//! which are registered in the `DBStorage` of the storage and read as a snapshot.
+//!
+use parking_lot::RwLock;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// The counters of a column family as read at some point
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StoreMetrics {
+    pub hits: u64,
+    pub misses: u64,
+    pub db_reads: u64,
+    /// The entries handed to a writer, which may be part of a batch not committed yet
+    pub writes: u64,
+    pub deletes: u64,
+    pub bytes_serialized: u64,
+    /// The entries decoded, whether they were read from the cache or from the db
+    pub deserializations: u64,
+    pub bytes_deserialized: u64,
+    pub deserialization_errors: u64,
+}
+
+impl StoreMetrics {
+    /// The ratio of the cache lookups which were hits, `None` if there was no lookup
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct StoreCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    db_reads: AtomicU64,
+    writes: AtomicU64,
+    deletes: AtomicU64,
+    bytes_serialized: AtomicU64,
+    deserializations: AtomicU64,
+    bytes_deserialized: AtomicU64,
+    deserialization_errors: AtomicU64,
+}
+
+impl StoreCounters {
+    pub(crate) fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn db_read(&self) {
+        self.db_reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn write(&self, bytes: usize) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        self.bytes_serialized
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn delete(&self) {
+        self.deletes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Deserializes `bytes`, counting the decoded volume and the failures
+    pub(crate) fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> bincode::Result<T> {
+        self.decode(bytes, |bytes| bincode::deserialize(bytes))
+    }
+
+    /// Decodes `bytes` with `decode`, counted as a deserialization
+    pub(crate) fn decode<T, E>(
+        &self,
+        bytes: &[u8],
+        decode: impl FnOnce(&[u8]) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.deserializations.fetch_add(1, Ordering::Relaxed);
+        self.bytes_deserialized
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        let result = decode(bytes);
+        if result.is_err() {
+            self.deserialization_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn snapshot(&self) -> StoreMetrics {
+        StoreMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            db_reads: self.db_reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            deletes: self.deletes.load(Ordering::Relaxed),
+            bytes_serialized: self.bytes_serialized.load(Ordering::Relaxed),
+            deserializations: self.deserializations.load(Ordering::Relaxed),
+            bytes_deserialized: self.bytes_deserialized.load(Ordering::Relaxed),
+            deserialization_errors: self.deserialization_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The counters of every column family accessed so far
+#[derive(Default)]
+pub(crate) struct StoreMetricsRegistry {
+    counters: RwLock<BTreeMap<&'static str, Arc<StoreCounters>>>,
+}
+
+impl StoreMetricsRegistry {
+    pub(crate) fn counters(&self, prefix: &'static str) -> Arc<StoreCounters> {
+        if let Some(counters) = self.counters.read().get(prefix) {
+            return counters.clone();
+        }
+        self.counters.write().entry(prefix).or_default().clone()
+    }
+
+    pub(crate) fn snapshot(&self) -> BTreeMap<&'static str, StoreMetrics> {
+        self.counters
+            .read()
+            .iter()
+            .map(|(prefix, counters)| (*prefix, counters.snapshot()))
+            .collect()
+    }
+}
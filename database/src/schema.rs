@@ -0,0 +1,424 @@
+//!
+//! Versioning of the encoding of the dag database.
+//!
+//! The schema version is stored along the data. Opening a database written with an older
+//! schema runs the registered migrations one version after the other, each of them
+//! being committed atomically together with the version it upgrades to. Databases
+//! written with a newer schema are refused.
+//!
+use crate::{
+    consensus::{
+        DbBlockIndexStore, DbSelectedChainStore, DbTipsStore, TipsStoreReader, CHILDREN_CF,
+        COMPACT_GHOST_DAG_STORE_CF, HEADERS_STORE_CF, STATUS_STORE_CF,
+    },
+    db::DBStorage,
+    errors::{StoreError, StoreResult},
+    format::RecordFormat,
+    prelude::{BatchDbWriter, CachedDbAccess, CachedDbItem, DbBatch, DirectDbWriter, RecordAccess},
+};
+use consensus_types::{
+    blockhash::{BlockHashSet, ORIGIN},
+    ghostdata::CompactGhostdagData,
+    header::{ConsensusHeader, HeaderWithBlockLevel},
+    ordering::SortableBlock,
+    status::BlockStatus,
+};
+use rocksdb::{IteratorMode, ReadOptions};
+use starcoin_crypto::HashValue as Hash;
+use std::{cmp, sync::Arc};
+
+pub(crate) const SCHEMA_VERSION_CF: &str = "dag-schema-version";
+const SCHEMA_VERSION_KEY: &str = "schema-version";
+const RECORD_FORMAT_KEY: &str = "record-format";
+
+/// The schema version written by this build
+pub const CURRENT_SCHEMA_VERSION: u32 = 4;
+
+/// A step upgrading the database from the schema version `from` to `from + 1`
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    /// Fills the batch with the rewritten entries
+    pub migrate: fn(&Arc<DBStorage>, &mut DbBatch) -> StoreResult<()>,
+}
+
+/// The migrations, sorted by version. Version 0 stands for the databases created before
+/// the schema was versioned.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "record the status of the blocks committed before statuses were stored",
+        migrate: backfill_block_statuses,
+    },
+    Migration {
+        from: 1,
+        description: "index the blocks by blue score and by timestamp",
+        migrate: backfill_block_indexes,
+    },
+    Migration {
+        from: 2,
+        description: "rebuild the dag tips from the blocks without children",
+        migrate: rebuild_dag_tips,
+    },
+    Migration {
+        from: 3,
+        description: "rebuild the selected chain index from the ghostdag data of the tips",
+        migrate: rebuild_selected_chain,
+    },
+];
+
+fn backfill_block_statuses(db: &Arc<DBStorage>, batch: &mut DbBatch) -> StoreResult<()> {
+    let headers_access =
+        CachedDbAccess::<Hash, HeaderWithBlockLevel>::new(Arc::clone(db), 0, HEADERS_STORE_CF);
+    let statuses_access =
+        CachedDbAccess::<Hash, BlockStatus>::new(Arc::clone(db), 0, STATUS_STORE_CF);
+    for item in headers_access.iterator()? {
+        let (key, header) = item.map_err(|e| StoreError::DBIoError(e.to_string()))?;
+        let hash = Hash::from_slice(&key).map_err(|e| StoreError::DBIoError(e.to_string()))?;
+        if statuses_access.has(hash)? {
+            continue;
+        }
+        // The genesis is the only block having the origin as its parent
+        let status = if header.header.parents_hash() == [Hash::new(ORIGIN)] {
+            BlockStatus::Valid
+        } else {
+            BlockStatus::HeaderOnly
+        };
+        statuses_access.write(BatchDbWriter::new(db, batch), hash, status)?;
+    }
+    Ok(())
+}
+
+fn backfill_block_indexes(db: &Arc<DBStorage>, batch: &mut DbBatch) -> StoreResult<()> {
+    let headers_access =
+        CachedDbAccess::<Hash, HeaderWithBlockLevel>::new(Arc::clone(db), 0, HEADERS_STORE_CF);
+    let ghostdag_access = RecordAccess::<Hash, CompactGhostdagData>::new(
+        Arc::clone(db),
+        0,
+        COMPACT_GHOST_DAG_STORE_CF,
+    );
+    let block_index_store = DbBlockIndexStore::new(Arc::clone(db));
+    for item in headers_access.iterator()? {
+        let (key, header) = item.map_err(|e| StoreError::DBIoError(e.to_string()))?;
+        let hash = Hash::from_slice(&key).map_err(|e| StoreError::DBIoError(e.to_string()))?;
+        // Blocks without ghostdag data are indexed once the data is rebuilt
+        let blue_score = match ghostdag_access.read(hash) {
+            Ok(data) => data.blue_score,
+            Err(StoreError::KeyNotFound(_)) => continue,
+            Err(error) => return Err(error),
+        };
+        block_index_store.insert_batch(batch, hash, blue_score, header.header.timestamp())?;
+    }
+    Ok(())
+}
+
+fn rebuild_dag_tips(db: &Arc<DBStorage>, batch: &mut DbBatch) -> StoreResult<()> {
+    let headers_access =
+        CachedDbAccess::<Hash, HeaderWithBlockLevel>::new(Arc::clone(db), 0, HEADERS_STORE_CF);
+    let children_access =
+        CachedDbAccess::<Hash, Arc<Vec<Hash>>>::new(Arc::clone(db), 0, CHILDREN_CF);
+    let mut tips = BlockHashSet::new();
+    for item in headers_access.iterator()? {
+        let (key, _) = item.map_err(|e| StoreError::DBIoError(e.to_string()))?;
+        let hash = Hash::from_slice(&key).map_err(|e| StoreError::DBIoError(e.to_string()))?;
+        let has_children = match children_access.read(hash) {
+            Ok(children) => !children.is_empty(),
+            Err(StoreError::KeyNotFound(_)) => false,
+            Err(error) => return Err(error),
+        };
+        if !has_children {
+            tips.insert(hash);
+        }
+    }
+    DbTipsStore::new(Arc::clone(db)).set_batch(batch, tips)
+}
+
+fn rebuild_selected_chain(db: &Arc<DBStorage>, batch: &mut DbBatch) -> StoreResult<()> {
+    let ghostdag_access = RecordAccess::<Hash, CompactGhostdagData>::new(
+        Arc::clone(db),
+        0,
+        COMPACT_GHOST_DAG_STORE_CF,
+    );
+    // The selected chain ends with the heaviest tip, as chosen by the ghostdag manager
+    let mut selected_tip = None;
+    for tip in DbTipsStore::new(Arc::clone(db)).get()?.iter() {
+        // Without ghostdag data, the index is rebuilt along with the data
+        let blue_work = match ghostdag_access.read(*tip) {
+            Ok(data) => data.blue_work,
+            Err(StoreError::KeyNotFound(_)) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        selected_tip = cmp::max(selected_tip, Some(SortableBlock::new(*tip, blue_work)));
+    }
+    let mut current = match selected_tip {
+        Some(tip) => tip.hash,
+        None => return Ok(()),
+    };
+    let mut chain = vec![];
+    while current != Hash::new(ORIGIN) {
+        chain.push(current);
+        current = ghostdag_access.read(current)?.selected_parent;
+    }
+    chain.reverse();
+    DbSelectedChainStore::new(Arc::clone(db), 0).reset_batch(batch, &chain)
+}
+
+/// Reads the schema version of `db`, running the migrations up to the current version.
+/// An empty database is stamped with the current version right away.
+pub(crate) fn upgrade_schema(db: &Arc<DBStorage>) -> StoreResult<u32> {
+    let mut version_item = CachedDbItem::<u32>::new(
+        Arc::clone(db),
+        SCHEMA_VERSION_CF,
+        SCHEMA_VERSION_KEY.as_bytes().to_vec(),
+    );
+    let mut version = match version_item.read() {
+        Ok(version) => version,
+        Err(StoreError::KeyNotFound(_)) if is_empty(db)? => {
+            version_item.write(DirectDbWriter::new(db), &CURRENT_SCHEMA_VERSION)?;
+            return Ok(CURRENT_SCHEMA_VERSION);
+        }
+        Err(StoreError::KeyNotFound(_)) => 0,
+        Err(error) => return Err(error),
+    };
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(StoreError::UnsupportedSchemaVersion(
+            version,
+            CURRENT_SCHEMA_VERSION,
+        ));
+    }
+
+    while version < CURRENT_SCHEMA_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == version)
+            .unwrap_or_else(|| panic!("no migration from the schema version {version}"));
+        let mut batch = DbBatch::default();
+        (migration.migrate)(db, &mut batch)?;
+        version += 1;
+        version_item.write(BatchDbWriter::new(db, &mut batch), &version)?;
+        db.raw_write_batch(batch)?;
+    }
+    Ok(version)
+}
+
+/// Checks that the schema of `db`, which is not migrated, is the current one
+pub(crate) fn check_schema(db: &Arc<DBStorage>) -> StoreResult<()> {
+    let version_item = CachedDbItem::<u32>::new(
+        Arc::clone(db),
+        SCHEMA_VERSION_CF,
+        SCHEMA_VERSION_KEY.as_bytes().to_vec(),
+    );
+    let version = match version_item.read() {
+        Ok(version) => version,
+        Err(StoreError::KeyNotFound(_)) if is_empty(db)? => return Ok(()),
+        Err(StoreError::KeyNotFound(_)) => 0,
+        Err(error) => return Err(error),
+    };
+    if version != CURRENT_SCHEMA_VERSION {
+        return Err(StoreError::UnsupportedSchemaVersion(
+            version,
+            CURRENT_SCHEMA_VERSION,
+        ));
+    }
+    Ok(())
+}
+
+/// Reads the record format of `db`. An empty database is stamped with `configured`,
+/// unless it is read-only, and a database without a recorded format is bincode encoded.
+pub(crate) fn resolve_record_format(
+    db: &DBStorage,
+    configured: RecordFormat,
+) -> StoreResult<RecordFormat> {
+    if let Some(bytes) = db.raw_get_pinned_cf(SCHEMA_VERSION_CF, RECORD_FORMAT_KEY)? {
+        return RecordFormat::from_version(bincode::deserialize(&bytes)?);
+    }
+    if !is_empty(db)? {
+        return Ok(RecordFormat::Bincode);
+    }
+    if !db.is_read_only() {
+        db.put(
+            SCHEMA_VERSION_CF,
+            RECORD_FORMAT_KEY,
+            bincode::serialize(&configured.version())?,
+        )?;
+    }
+    Ok(configured)
+}
+
+fn is_empty(db: &DBStorage) -> StoreResult<bool> {
+    let mut headers = db
+        .raw_iterator_cf_opt(
+            HEADERS_STORE_CF,
+            IteratorMode::Start,
+            ReadOptions::default(),
+        )
+        .map_err(|e| StoreError::CFNotExist(e.to_string()))?;
+    Ok(headers.next().is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        consensus::{
+            GhostdagStore, HeaderStore, RelationsStore, SelectedChainStore,
+            SelectedChainStoreReader, StatusesStore, StatusesStoreReader, TipsStore,
+        },
+        prelude::{FlexiDagStorage, FlexiDagStorageConfig},
+    };
+    use consensus_types::{
+        blockhash::{BlockHashes, ChainPath},
+        ghostdata::GhostdagData,
+        header::Header,
+    };
+    use starcoin_types::block::BlockHeader;
+
+    fn version_item(storage: &FlexiDagStorage) -> CachedDbItem<u32> {
+        CachedDbItem::new(
+            storage.db.clone(),
+            SCHEMA_VERSION_CF,
+            SCHEMA_VERSION_KEY.as_bytes().to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_upgrade_schema() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone()).unwrap();
+        assert_eq!(
+            version_item(&storage).read().unwrap(),
+            CURRENT_SCHEMA_VERSION
+        );
+
+        // A database created before the schema was versioned
+        let genesis = Header::new(BlockHeader::random(), vec![Hash::new(ORIGIN)]);
+        let block = Header::new(BlockHeader::random(), vec![genesis.hash()]);
+        for header in [&genesis, &block] {
+            storage
+                .header_store
+                .insert(header.hash(), Arc::new(header.clone()), 0)
+                .unwrap();
+        }
+        version_item(&storage)
+            .remove(DirectDbWriter::new(&storage.db))
+            .unwrap();
+        drop(storage);
+
+        let storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone()).unwrap();
+        assert_eq!(
+            version_item(&storage).read().unwrap(),
+            CURRENT_SCHEMA_VERSION
+        );
+        assert_eq!(
+            storage.statuses_store.get(genesis.hash()).unwrap(),
+            BlockStatus::Valid
+        );
+        assert_eq!(
+            storage.statuses_store.get(block.hash()).unwrap(),
+            BlockStatus::HeaderOnly
+        );
+
+        // Newer databases are refused
+        version_item(&storage)
+            .write(
+                DirectDbWriter::new(&storage.db),
+                &(CURRENT_SCHEMA_VERSION + 1),
+            )
+            .unwrap();
+        drop(storage);
+        assert!(matches!(
+            FlexiDagStorage::create_from_path(db_tempdir.path(), config),
+            Err(StoreError::UnsupportedSchemaVersion(..))
+        ));
+    }
+
+    #[test]
+    fn test_commit_after_upgrade() {
+        let db_tempdir = tempfile::tempdir().unwrap();
+        let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+        let mut storage =
+            FlexiDagStorage::create_from_path(db_tempdir.path(), config.clone()).unwrap();
+
+        // A version 0 database, without statuses, block indexes nor selected chain index,
+        // and whose tips are out of date
+        let origin = Hash::new(ORIGIN);
+        let genesis = Header::new(BlockHeader::random(), vec![origin]);
+        let a = Header::new(BlockHeader::random(), vec![genesis.hash()]);
+        let b = Header::new(BlockHeader::random(), vec![genesis.hash()]);
+        storage
+            .relations_store
+            .insert(origin, BlockHashes::new(vec![]))
+            .unwrap();
+        for (blue_work, header) in [&genesis, &a, &b].into_iter().enumerate() {
+            storage
+                .header_store
+                .insert(header.hash(), Arc::new(header.clone()), 0)
+                .unwrap();
+            storage
+                .relations_store
+                .insert(
+                    header.hash(),
+                    BlockHashes::new(header.parents_hash().to_vec()),
+                )
+                .unwrap();
+            let mut data = GhostdagData::new_with_selected_parent(header.parents_hash()[0], 1);
+            data.finalize_score_and_work(blue_work as u64, (blue_work as u64).into());
+            storage
+                .ghost_dag_store
+                .insert(header.hash(), Arc::new(data))
+                .unwrap();
+        }
+        storage.tips_store.init(genesis.hash()).unwrap();
+        version_item(&storage)
+            .remove(DirectDbWriter::new(&storage.db))
+            .unwrap();
+        drop(storage);
+
+        let mut storage = FlexiDagStorage::create_from_path(db_tempdir.path(), config).unwrap();
+        assert_eq!(
+            *storage.tips_store.get().unwrap(),
+            BlockHashSet::from_iter([a.hash(), b.hash()])
+        );
+        // b has more blue work than a
+        assert_eq!(
+            storage.selected_chain_store.get_tip().unwrap(),
+            (1, b.hash())
+        );
+        assert_eq!(
+            storage.selected_chain_store.get_by_index(0).unwrap(),
+            genesis.hash()
+        );
+
+        // A block merging both tips is committed the way the dag commits headers
+        let c = Header::new(BlockHeader::random(), vec![b.hash(), a.hash()]);
+        storage
+            .header_store
+            .insert(c.hash(), Arc::new(c.clone()), 0)
+            .unwrap();
+        storage
+            .relations_store
+            .insert(c.hash(), BlockHashes::new(c.parents_hash().to_vec()))
+            .unwrap();
+        storage
+            .statuses_store
+            .set(c.hash(), BlockStatus::HeaderOnly)
+            .unwrap();
+        let tips = storage
+            .tips_store
+            .add_tip(c.hash(), c.parents_hash())
+            .unwrap();
+        assert_eq!(*tips, BlockHashSet::from_iter([c.hash()]));
+        storage
+            .selected_chain_store
+            .apply_changes(&ChainPath {
+                added: vec![c.hash()],
+                removed: vec![],
+            })
+            .unwrap();
+        assert_eq!(
+            storage.selected_chain_store.get_tip().unwrap(),
+            (2, c.hash())
+        );
+    }
+}
@@ -1,12 +1,16 @@
 use rocksdb::WriteBatch;
-use starcoin_storage::storage::InnerStore;
 
 use crate::{db::DBStorage, errors::StoreError};
 
+/// An update of a store cache, to be applied once the write it mirrors is in the db
+pub type CacheUpdate = Box<dyn FnOnce() + Send>;
+
 /// Abstraction over direct/batched DB writing
 pub trait DbWriter {
     fn put(&mut self, cf_name: &str, key: &[u8], value: Vec<u8>) -> Result<(), StoreError>;
     fn delete(&mut self, cf_name: &str, key: &[u8]) -> Result<(), StoreError>;
+    /// Applies `update` once the writes made so far are in the db
+    fn update_cache(&mut self, update: CacheUpdate);
 }
 
 pub struct DirectDbWriter<'a> {
@@ -21,38 +25,77 @@ impl<'a> DirectDbWriter<'a> {
 
 impl DbWriter for DirectDbWriter<'_> {
     fn put(&mut self, cf_name: &str, key: &[u8], value: Vec<u8>) -> Result<(), StoreError> {
-        self.db
-            .put(cf_name, key.to_owned(), value)
-            .map_err(|e| StoreError::DBIoError(e.to_string()))
+        self.db.check_writable()?;
+        self.db.put(cf_name, key, value)
     }
 
     fn delete(&mut self, cf_name: &str, key: &[u8]) -> Result<(), StoreError> {
-        self.db
-            .remove(cf_name, key.to_owned())
-            .map_err(|e| StoreError::DBIoError(e.to_string()))
+        self.db.check_writable()?;
+        self.db.remove(cf_name, key)
+    }
+
+    fn update_cache(&mut self, update: CacheUpdate) {
+        update()
+    }
+}
+
+/// A `WriteBatch` along with the cache updates of its writes, which are only applied
+/// once the batch was written, see `FlexiDagStorage::write_batch`
+#[derive(Default)]
+pub struct DbBatch {
+    batch: WriteBatch,
+    cache_updates: Vec<CacheUpdate>,
+}
+
+impl DbBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of writes in the batch
+    pub fn len(&self) -> usize {
+        self.batch.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batch.is_empty()
+    }
+
+    pub(crate) fn into_parts(self) -> (WriteBatch, Vec<CacheUpdate>) {
+        (self.batch, self.cache_updates)
     }
 }
 
+/// Writes into a `DbBatch`, the column families being resolved against `db`
 pub struct BatchDbWriter<'a> {
-    batch: &'a mut WriteBatch,
+    db: &'a DBStorage,
+    batch: &'a mut DbBatch,
 }
 
 impl<'a> BatchDbWriter<'a> {
-    pub fn new(batch: &'a mut WriteBatch) -> Self {
-        Self { batch }
+    pub fn new(db: &'a DBStorage, batch: &'a mut DbBatch) -> Self {
+        Self { db, batch }
     }
 }
 
 impl DbWriter for BatchDbWriter<'_> {
-    fn put(&mut self, _cf_name: &str, key: &[u8], value: Vec<u8>) -> Result<(), StoreError> {
-        self.batch.put(key, value);
+    fn put(&mut self, cf_name: &str, key: &[u8], value: Vec<u8>) -> Result<(), StoreError> {
+        self.db.check_writable()?;
+        let cf = self.db.get_cf_handle(cf_name)?;
+        self.batch.batch.put_cf(cf, key, value);
         Ok(())
     }
 
-    fn delete(&mut self, _cf_name: &str, key: &[u8]) -> Result<(), StoreError> {
-        self.batch.delete(key);
+    fn delete(&mut self, cf_name: &str, key: &[u8]) -> Result<(), StoreError> {
+        self.db.check_writable()?;
+        let cf = self.db.get_cf_handle(cf_name)?;
+        self.batch.batch.delete_cf(cf, key);
         Ok(())
     }
+
+    fn update_cache(&mut self, update: CacheUpdate) {
+        self.batch.cache_updates.push(update);
+    }
 }
 
 impl<T: DbWriter> DbWriter for &mut T {
@@ -65,4 +108,9 @@ impl<T: DbWriter> DbWriter for &mut T {
     fn delete(&mut self, cf_name: &str, key: &[u8]) -> Result<(), StoreError> {
         (*self).delete(cf_name, key)
     }
+
+    #[inline]
+    fn update_cache(&mut self, update: CacheUpdate) {
+        (*self).update_cache(update)
+    }
 }
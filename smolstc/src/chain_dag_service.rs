@@ -1,11 +1,15 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 
 use crate::{
     network_dag_rpc::{SyncDagBlockInfo, TargetAccumulatorLeaf, TargetAccumulatorLeafDetail},
     sync_block_dag::{RelationshipPair, SyncBlockDag},
 };
 use anyhow::Result;
+use consensus::{blockdag::BlockTemplateParents, genesis::DagGenesisConfig};
+use consensus_types::{header::Header, status::BlockStatus};
+use database::prelude::StoreMetrics;
 use starcoin_accumulator::{accumulator_info::AccumulatorInfo, Accumulator};
+use starcoin_crypto::HashValue;
 use starcoin_service_registry::{
     ActorService, ServiceContext, ServiceFactory, ServiceHandler, ServiceRequest,
 };
@@ -20,9 +24,11 @@ impl ChainDagService {}
 impl ServiceFactory<Self> for ChainDagService {
     fn create(ctx: &mut ServiceContext<ChainDagService>) -> Result<ChainDagService> {
         // for testing only
+        let genesis_config = ctx.get_shared::<DagGenesisConfig>()?;
         return Ok(ChainDagService {
             dag: SyncBlockDag::build_sync_block_dag(
                 ctx.get_shared::<Arc<Storage>>().unwrap().clone(),
+                &genesis_config,
             ),
         });
     }
@@ -251,3 +257,116 @@ impl ServiceHandler<Self, GetDagBlockInfo> for ChainDagService {
         Some(info)
     }
 }
+
+#[derive(Debug)]
+pub struct GetBlockTemplateParents;
+
+impl ServiceRequest for GetBlockTemplateParents {
+    type Response = Result<BlockTemplateParents>;
+}
+
+impl ServiceHandler<Self, GetBlockTemplateParents> for ChainDagService {
+    fn handle(
+        &mut self,
+        msg: GetBlockTemplateParents,
+        ctx: &mut starcoin_service_registry::ServiceContext<Self>,
+    ) -> <GetBlockTemplateParents as ServiceRequest>::Response {
+        self.dag.dag.block_template_parents()
+    }
+}
+
+#[derive(Debug)]
+pub struct ConnectDagBlock {
+    pub header: Header,
+}
+
+impl ServiceRequest for ConnectDagBlock {
+    type Response = Result<()>;
+}
+
+impl ServiceHandler<Self, ConnectDagBlock> for ChainDagService {
+    fn handle(
+        &mut self,
+        msg: ConnectDagBlock,
+        ctx: &mut starcoin_service_registry::ServiceContext<Self>,
+    ) -> <ConnectDagBlock as ServiceRequest>::Response {
+        self.dag.dag.connect_block(&msg.header)
+    }
+}
+
+/// The parents of `header` which are not in the local dag yet
+#[derive(Debug)]
+pub struct GetMissingDagParents {
+    pub header: Header,
+}
+
+impl ServiceRequest for GetMissingDagParents {
+    type Response = Result<Vec<HashValue>>;
+}
+
+impl ServiceHandler<Self, GetMissingDagParents> for ChainDagService {
+    fn handle(
+        &mut self,
+        msg: GetMissingDagParents,
+        ctx: &mut starcoin_service_registry::ServiceContext<Self>,
+    ) -> <GetMissingDagParents as ServiceRequest>::Response {
+        self.dag.dag.missing_parents(&msg.header)
+    }
+}
+
+#[derive(Debug)]
+pub struct GetDagBlockStatus {
+    pub hash: HashValue,
+}
+
+impl ServiceRequest for GetDagBlockStatus {
+    type Response = Result<Option<BlockStatus>>;
+}
+
+impl ServiceHandler<Self, GetDagBlockStatus> for ChainDagService {
+    fn handle(
+        &mut self,
+        msg: GetDagBlockStatus,
+        ctx: &mut starcoin_service_registry::ServiceContext<Self>,
+    ) -> <GetDagBlockStatus as ServiceRequest>::Response {
+        self.dag.dag.get_block_status(msg.hash)
+    }
+}
+
+/// Backs up the dag db of the running node into `path`
+#[derive(Debug)]
+pub struct CreateDagCheckpoint {
+    pub path: PathBuf,
+}
+
+impl ServiceRequest for CreateDagCheckpoint {
+    type Response = Result<()>;
+}
+
+impl ServiceHandler<Self, CreateDagCheckpoint> for ChainDagService {
+    fn handle(
+        &mut self,
+        msg: CreateDagCheckpoint,
+        ctx: &mut starcoin_service_registry::ServiceContext<Self>,
+    ) -> <CreateDagCheckpoint as ServiceRequest>::Response {
+        self.dag.dag.create_checkpoint(&msg.path)
+    }
+}
+
+/// The cache and db access counters of the dag stores of the running node
+#[derive(Debug)]
+pub struct GetDagStoreMetrics;
+
+impl ServiceRequest for GetDagStoreMetrics {
+    type Response = BTreeMap<&'static str, StoreMetrics>;
+}
+
+impl ServiceHandler<Self, GetDagStoreMetrics> for ChainDagService {
+    fn handle(
+        &mut self,
+        _msg: GetDagStoreMetrics,
+        _ctx: &mut starcoin_service_registry::ServiceContext<Self>,
+    ) -> <GetDagStoreMetrics as ServiceRequest>::Response {
+        self.dag.dag.store_metrics()
+    }
+}
@@ -1,4 +1,5 @@
 mod chain_dag_service;
+mod mining_dag_service;
 mod network_dag_data;
 mod network_dag_handle;
 mod network_dag_rpc;
@@ -21,10 +22,19 @@ use std::sync::Arc;
 
 use anyhow::Ok;
 use chain_dag_service::ChainDagService;
+use consensus::blockdag::BlockDAG;
+use consensus::dot::{render_dot, DotRegion};
+use consensus::export::{export_dag, import_dag, ExportOptions};
+use consensus::genesis::DagGenesisConfig;
+use consensus::rebuild::{rebuild_dag_data, RebuildConfig};
+use consensus_types::header::ConsensusHeader;
+use database::prelude::{check_storage, FlexiDagStorage, FlexiDagStorageConfig};
+use mining_dag_service::{MiningConfig, MiningDagService};
 use network_dag_rpc_service::NetworkDagRpcService;
 // use flexi_dag::{FlexiBlock, FlexiDagConsensus};
 use network_dag_service::{NetworkDagService, NetworkDagServiceFactory, NetworkMultiaddr};
 use starcoin_config::RocksdbConfig;
+use starcoin_crypto::HashValue;
 use starcoin_service_registry::{RegistryAsyncService, RegistryService, ServiceRef};
 use starcoin_storage::{
     cache_storage::CacheStorage, db_storage::DBStorage, storage::StorageInstance, Storage,
@@ -78,12 +88,176 @@ async fn run_server(registry: &ServiceRef<RegistryService>) -> anyhow::Result<()
     return Ok(());
 }
 
+async fn run_miner(registry: &ServiceRef<RegistryService>, args: &[String]) -> anyhow::Result<()> {
+    let config = MiningConfig::from_args(args)?;
+    registry.put_shared(config).await?;
+    registry.register::<MiningDagService>().await?;
+    return Ok(());
+}
+
+/// Takes the dag genesis from `--genesis <network|file>`, the dev network is used by default.
+fn load_genesis(args: &mut Vec<String>) -> anyhow::Result<DagGenesisConfig> {
+    match args.iter().position(|arg| arg == "--genesis") {
+        Some(index) => {
+            let source = args.get(index + 1).cloned().ok_or_else(|| {
+                anyhow::anyhow!("--genesis requires a network name or a genesis file")
+            })?;
+            args.drain(index..=index + 1);
+            DagGenesisConfig::load(&source)
+        }
+        None => Ok(DagGenesisConfig::dev()),
+    }
+}
+
+/// Checks the consistency of the dag storage at `path` and prints the report as json.
+fn run_fsck(path: &str) -> anyhow::Result<bool> {
+    let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+    let storage = FlexiDagStorage::open_read_only(path, config)?;
+    let report = check_storage(&storage)?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(report.is_consistent())
+}
+
+/// Recomputes the reachability and ghostdag data of the dag storage at `path` with
+/// `rebuild <db path> <k> [batch size]`.
+fn run_rebuild(args: &[String]) -> anyhow::Result<()> {
+    let path = args
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("rebuild requires the path of a dag db"))?;
+    let k = args
+        .get(1)
+        .ok_or_else(|| anyhow::anyhow!("rebuild requires the ghostdag k"))?
+        .parse()?;
+    let batch_size = match args.get(2) {
+        Some(batch_size) => batch_size.parse()?,
+        None => 1000,
+    };
+    let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+    let storage = FlexiDagStorage::create_from_path(path, config)?;
+    rebuild_dag_data(&storage, RebuildConfig { k, batch_size }, |progress| {
+        println!("rebuilt {}/{} blocks", progress.processed, progress.total);
+    })
+}
+
+/// Opens the dag storage at `path`, with the genesis given by `--genesis` if any. A
+/// read-only storage must have been initialized with that genesis already.
+fn open_dag(path: &str, args: &mut Vec<String>, read_only: bool) -> anyhow::Result<BlockDAG> {
+    let genesis_config = load_genesis(args)?;
+    let config = FlexiDagStorageConfig::create_with_params(1, 0, 1024);
+    let storage = if read_only {
+        FlexiDagStorage::open_read_only(path, config)?
+    } else {
+        FlexiDagStorage::create_from_path(path, config)?
+    };
+    BlockDAG::new(genesis_config.to_header(), genesis_config.k, storage)
+}
+
+/// Exports the dag with `export <db path> <file> [--from <hash>] [--with-ghostdag-data]`
+fn run_export(mut args: Vec<String>) -> anyhow::Result<()> {
+    let mut options = ExportOptions::default();
+    if let Some(index) = args.iter().position(|arg| arg == "--with-ghostdag-data") {
+        args.remove(index);
+        options.with_ghostdag_data = true;
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--from") {
+        let from = args
+            .get(index + 1)
+            .ok_or_else(|| anyhow::anyhow!("--from requires a block hash"))?;
+        options.from = Some(HashValue::from_hex_literal(from)?);
+        args.drain(index..=index + 1);
+    }
+    let mut rest = args.split_off(2);
+    let dag = open_dag(&args[0], &mut rest, true)?;
+    let file = std::io::BufWriter::new(std::fs::File::create(&args[1])?);
+    let exported = export_dag(&dag, &options, file)?;
+    println!("exported {} blocks to {}", exported, args[1]);
+    Ok(())
+}
+
+/// Imports the blocks of a file created by `export` with `import <db path> <file>`
+fn run_import(mut args: Vec<String>) -> anyhow::Result<()> {
+    let mut rest = args.split_off(2);
+    let mut dag = open_dag(&args[0], &mut rest, false)?;
+    let file = std::io::BufReader::new(std::fs::File::open(&args[1])?);
+    let summary = import_dag(&mut dag, file)?;
+    println!(
+        "imported {} blocks, {} were known already",
+        summary.imported, summary.skipped
+    );
+    Ok(())
+}
+
+/// Prints a dag region in the DOT language with
+/// `dot <db path> (--range <low> <high> | --around <hash> <radius>) [--context <hash>]`,
+/// the context defaulting to the highest block of the region.
+fn run_dot(mut args: Vec<String>) -> anyhow::Result<()> {
+    let mut context = None;
+    if let Some(index) = args.iter().position(|arg| arg == "--context") {
+        let hash = args
+            .get(index + 1)
+            .ok_or_else(|| anyhow::anyhow!("--context requires a block hash"))?;
+        context = Some(HashValue::from_hex_literal(hash)?);
+        args.drain(index..=index + 1);
+    }
+    if args.len() < 4 {
+        anyhow::bail!("dot requires a db path and a region");
+    }
+    let mut rest = args.split_off(4);
+    let (region, default_context) = match args[1].as_str() {
+        "--range" => {
+            let high = HashValue::from_hex_literal(&args[3])?;
+            let low = HashValue::from_hex_literal(&args[2])?;
+            (DotRegion::Range { low, high }, high)
+        }
+        "--around" => {
+            let block = HashValue::from_hex_literal(&args[2])?;
+            let radius = args[3].parse()?;
+            (DotRegion::Around { block, radius }, block)
+        }
+        other => anyhow::bail!("unknown dag region: {}", other),
+    };
+    let dag = open_dag(&args[0], &mut rest, true)?;
+    print!(
+        "{}",
+        render_dot(&dag, region, context.unwrap_or(default_context))?
+    );
+    Ok(())
+}
+
 fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.len() > 2 && args[1] == "dot" {
+        run_dot(args[2..].to_vec()).expect("failed to render the dag");
+        return;
+    }
+    if args.len() > 3 && args[1] == "export" {
+        run_export(args[2..].to_vec()).expect("failed to export the dag");
+        return;
+    }
+    if args.len() > 3 && args[1] == "import" {
+        run_import(args[2..].to_vec()).expect("failed to import the dag");
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("fsck") {
+        let path = args.get(2).expect("fsck requires the path of a dag db");
+        let consistent = run_fsck(path).expect("failed to check the dag db");
+        std::process::exit(if consistent { 0 } else { 1 });
+    }
+    if args.get(1).map(String::as_str) == Some("rebuild") {
+        run_rebuild(&args[2..]).expect("failed to rebuild the dag db");
+        return;
+    }
+
     async_std::task::block_on(async {
         let system = actix::prelude::System::new();
 
         let registry = RegistryService::launch();
 
+        let mut op = std::env::args().collect::<Vec<_>>();
+        let genesis_config = load_genesis(&mut op).expect("failed to load the dag genesis");
+        println!("dag genesis: {}", genesis_config.to_header().hash());
+        registry.put_shared(genesis_config).await.unwrap();
+
         /// initialize the storage
         registry
             .put_shared(Arc::new(
@@ -117,13 +291,15 @@ fn main() {
         registry.register::<SyncDagService>().await.unwrap();
 
         /// to see if sync task or server task?
-        let op = std::env::args().collect::<Vec<_>>();
         if op.len() > 1 {
             let cmd = op.get(1).unwrap();
             if "sync" == cmd {
                 run_sync(&registry, op[2..].to_vec()).await.unwrap();
             } else if "server" == cmd {
                 run_server(&registry).await.unwrap();
+            } else if "mine" == cmd {
+                run_server(&registry).await.unwrap();
+                run_miner(&registry, &op[2..]).await.unwrap();
             }
         }
         system.run().unwrap();
@@ -0,0 +1,273 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    chain_dag_service::{ChainDagService, ConnectDagBlock, GetBlockTemplateParents},
+    network_dag_service::{BroadcastDagBlock, NetworkDagService},
+};
+use anyhow::Result;
+use consensus::{blockdag::check_pow, genesis::DagGenesisConfig};
+use consensus_types::header::{parents_commitment, ConsensusHeader, Header};
+use starcoin_crypto::HashValue;
+use starcoin_service_registry::{
+    ActorService, EventHandler, ServiceContext, ServiceFactory, ServiceRef,
+};
+use starcoin_types::{
+    account_address::AccountAddress,
+    block::{BlockHeader, BlockHeaderExtra},
+    genesis_config::ChainId,
+    U256,
+};
+
+/// Parameters of the local cpu miner
+#[derive(Clone, Debug)]
+pub struct MiningConfig {
+    /// The expected number of blocks per second
+    pub rate: f64,
+    /// The number of blocks mined concurrently on top of the same tips,
+    /// which emulates the width of the dag
+    pub parallelism: usize,
+    /// The number of threads solving the pow of a single block
+    pub workers: usize,
+    /// The difficulty written into the mined headers
+    pub difficulty: u64,
+}
+
+impl Default for MiningConfig {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            parallelism: 1,
+            workers: 1,
+            difficulty: 1,
+        }
+    }
+}
+
+impl MiningConfig {
+    /// Parses `--rate <blocks per second>`, `--parallelism <n>`, `--workers <n>` and
+    /// `--difficulty <n>`, unspecified parameters keep their default values.
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let mut config = Self::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let value = iter
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("{} requires a value", arg))?;
+            match arg.as_str() {
+                "--rate" => config.rate = value.parse()?,
+                "--parallelism" => config.parallelism = value.parse()?,
+                "--workers" => config.workers = value.parse()?,
+                "--difficulty" => config.difficulty = value.parse()?,
+                _ => anyhow::bail!("unknown mining option: {}", arg),
+            }
+        }
+        if config.rate <= 0.0 {
+            anyhow::bail!("the mining rate must be positive");
+        }
+        config.parallelism = config.parallelism.max(1);
+        config.workers = config.workers.max(1);
+        config.difficulty = config.difficulty.max(1);
+        Ok(config)
+    }
+
+    /// The time between two mining rounds, each round producing `parallelism` blocks
+    pub fn round_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.parallelism as f64 / self.rate)
+    }
+}
+
+/// The fields of a header which stay the same while searching for a nonce
+struct HeaderTemplate {
+    parents: Vec<HashValue>,
+    /// The commitment to the parents, written in place of the parent hash. The header id
+    /// does not cover the dag parents, so `verify_header` requires it to keep blocks of
+    /// distinct parents from sharing an id
+    parents_commitment: HashValue,
+    timestamp: u64,
+    number: u64,
+    difficulty: u64,
+    chain_id: ChainId,
+}
+
+impl HeaderTemplate {
+    fn header_with_nonce(&self, nonce: u32) -> Header {
+        Header::new(
+            BlockHeader::new(
+                self.parents_commitment,
+                self.timestamp,
+                self.number,
+                AccountAddress::ZERO,
+                HashValue::zero(),
+                HashValue::zero(),
+                HashValue::zero(),
+                0,
+                U256::from(self.difficulty),
+                HashValue::zero(),
+                self.chain_id,
+                nonce,
+                BlockHeaderExtra::default(),
+            ),
+            self.parents.clone(),
+        )
+    }
+
+    /// Searches the nonces from `first_nonce` on with `workers` threads
+    fn solve(&self, first_nonce: u32, workers: usize) -> Option<Header> {
+        let found = AtomicBool::new(false);
+        let solution = Mutex::new(None);
+        std::thread::scope(|scope| {
+            for worker in 0..workers {
+                let found = &found;
+                let solution = &solution;
+                scope.spawn(move || {
+                    let mut nonce = first_nonce.wrapping_add(worker as u32);
+                    let mut tried = worker as u64;
+                    while tried <= u32::MAX as u64 && !found.load(Ordering::Relaxed) {
+                        let header = self.header_with_nonce(nonce);
+                        if check_pow(&header) {
+                            found.store(true, Ordering::Relaxed);
+                            *solution.lock().unwrap() = Some(header);
+                            return;
+                        }
+                        nonce = nonce.wrapping_add(workers as u32);
+                        tried += workers as u64;
+                    }
+                });
+            }
+        });
+        solution.into_inner().unwrap()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct MineRound;
+
+/// Mines dag blocks on the cpu at the configured rate, connects them to the local dag
+/// and broadcasts them to the connected peers. For devnets only.
+pub struct MiningDagService {
+    config: MiningConfig,
+    chain_id: ChainId,
+    /// Set while the blocks of a round are solved, rounds are skipped meanwhile
+    solving: Arc<AtomicBool>,
+}
+
+impl ServiceFactory<Self> for MiningDagService {
+    fn create(ctx: &mut ServiceContext<MiningDagService>) -> Result<MiningDagService> {
+        let config = ctx.get_shared::<MiningConfig>()?;
+        let genesis_config = ctx.get_shared::<DagGenesisConfig>()?;
+        Ok(MiningDagService {
+            config,
+            chain_id: ChainId::new(genesis_config.chain_id),
+            solving: Arc::new(AtomicBool::new(false)),
+        })
+    }
+}
+
+impl ActorService for MiningDagService {
+    fn started(&mut self, ctx: &mut ServiceContext<Self>) -> Result<()> {
+        println!("start mining with {:?}", self.config);
+        ctx.run_interval(self.config.round_interval(), |ctx| {
+            ctx.notify(MineRound);
+        });
+        Ok(())
+    }
+
+    fn service_name() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl EventHandler<Self, MineRound> for MiningDagService {
+    fn handle_event(&mut self, _msg: MineRound, ctx: &mut ServiceContext<Self>) {
+        if let Err(error) = self.mine_round(ctx) {
+            println!("failed to mine a dag block: {}", error);
+        }
+    }
+}
+
+impl MiningDagService {
+    /// Builds the header template of the round and solves its blocks on a separate thread,
+    /// so that the service keeps handling its messages meanwhile
+    fn mine_round(&self, ctx: &mut ServiceContext<Self>) -> Result<()> {
+        if self.solving.swap(true, Ordering::AcqRel) {
+            println!("the previous mining round is still being solved, skipped a round");
+            return Ok(());
+        }
+        let round = self.prepare_round(ctx);
+        let (header_template, chain_service, network_service) = match round {
+            Ok(round) => round,
+            Err(error) => {
+                self.solving.store(false, Ordering::Release);
+                return Err(error);
+            }
+        };
+        let config = self.config.clone();
+        let solving = self.solving.clone();
+        std::thread::spawn(move || {
+            if let Err(error) =
+                solve_round(&header_template, &config, &chain_service, &network_service)
+            {
+                println!("failed to mine a dag block: {}", error);
+            }
+            solving.store(false, Ordering::Release);
+        });
+        Ok(())
+    }
+
+    fn prepare_round(
+        &self,
+        ctx: &mut ServiceContext<Self>,
+    ) -> Result<(
+        HeaderTemplate,
+        ServiceRef<ChainDagService>,
+        ServiceRef<NetworkDagService>,
+    )> {
+        let chain_service = ctx.service_ref::<ChainDagService>()?.clone();
+        let network_service = ctx.service_ref::<NetworkDagService>()?.clone();
+
+        let template = async_std::task::block_on(chain_service.send(GetBlockTemplateParents))??;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let header_template = HeaderTemplate {
+            parents_commitment: parents_commitment(&template.parents),
+            parents: template.parents,
+            timestamp,
+            number: template.ghostdag_data.blue_score,
+            difficulty: self.config.difficulty,
+            chain_id: self.chain_id,
+        };
+        Ok((header_template, chain_service, network_service))
+    }
+}
+
+/// Solves the `parallelism` blocks of a round, connecting and broadcasting each of them
+fn solve_round(
+    header_template: &HeaderTemplate,
+    config: &MiningConfig,
+    chain_service: &ServiceRef<ChainDagService>,
+    network_service: &ServiceRef<NetworkDagService>,
+) -> Result<()> {
+    // Blocks of the same round share their parents, so split the nonce space
+    // between them in order to get distinct blocks
+    let nonce_step = u32::MAX / config.parallelism as u32;
+    for index in 0..config.parallelism {
+        let header = header_template
+            .solve(nonce_step * index as u32, config.workers)
+            .ok_or_else(|| anyhow::anyhow!("no nonce meets the difficulty"))?;
+        let hash = header.hash();
+        async_std::task::block_on(chain_service.send(ConnectDagBlock {
+            header: header.clone(),
+        }))??;
+        async_std::task::block_on(network_service.send(BroadcastDagBlock { header }))??;
+        println!(
+            "mined dag block {}, expected blue score {}",
+            hash, header_template.number
+        );
+    }
+    Ok(())
+}
@@ -1,4 +1,7 @@
 use crate::{
+    chain_dag_service::{
+        ChainDagService, ConnectDagBlock, GetDagBlockStatus, GetMissingDagParents,
+    },
     network_dag_data::{ChainInfo, Status},
     network_dag_handle::DagDataHandle,
     network_dag_rpc::gen_client,
@@ -9,6 +12,8 @@ use crate::{
 };
 use anyhow::Result;
 use bcs_ext::BCSCodec;
+use consensus::blockdag::check_pow;
+use consensus_types::header::{ConsensusHeader, Header};
 use futures::{channel::mpsc::channel, FutureExt, StreamExt};
 use futures_core::future::BoxFuture;
 use network_p2p::{config, config::RequestResponseConfig, Event, NetworkService, NetworkWorker};
@@ -65,6 +70,15 @@ impl ServiceRequest for GetBestChainInfo {
     type Response = ChainInfo;
 }
 
+/// Sends a newly mined dag block to all the connected peers
+#[derive(Debug)]
+pub struct BroadcastDagBlock {
+    pub header: Header,
+}
+impl ServiceRequest for BroadcastDagBlock {
+    type Response = Result<()>;
+}
+
 pub struct NetworkDagService {
     worker: Option<NetworkWorker<DagDataHandle>>,
     network_inner_service: Arc<NetworkService>,
@@ -195,7 +209,14 @@ impl NetworkDagService {
 
 impl NetworkDag for NetworkDagService {
     fn broadcast_message(&self, message: Vec<u8>) -> Result<()> {
-        todo!()
+        for peer in self.peer_set.keys() {
+            self.network_inner_service.write_notification(
+                *peer,
+                Cow::from(PROTOCOL_NAME_NOTIFY),
+                message.clone(),
+            );
+        }
+        Ok(())
     }
 
     fn register_handshaking(&self, fut: BoxFuture<()>) {
@@ -271,6 +292,93 @@ impl EventHandler<Self, network_p2p::Event> for NetworkDagService {
                 self.peer_set.entry(remote).or_insert(chain_info);
                 println!("a peer is put into the peer set, {:?}", remote);
             }
+            Event::NotificationsReceived { remote, messages } => {
+                let chain_service = ctx.service_ref::<ChainDagService>().unwrap().clone();
+                for (protocol, message) in messages {
+                    if protocol != PROTOCOL_NAME_NOTIFY {
+                        continue;
+                    }
+                    let header = match Header::decode(&message) {
+                        Ok(header) => header,
+                        Err(error) => {
+                            println!(
+                                "failed to decode the dag block from {:?}: {}",
+                                remote, error
+                            );
+                            continue;
+                        }
+                    };
+                    if !check_pow(&header) {
+                        println!(
+                            "the dag block {} from {:?} does not meet its difficulty",
+                            header.hash(),
+                            remote
+                        );
+                        continue;
+                    }
+                    let hash = header.hash();
+                    // Blocks of the dag and blocks known to be invalid are not connected again
+                    match async_std::task::block_on(chain_service.send(GetDagBlockStatus { hash }))
+                    {
+                        Ok(Ok(None)) => (),
+                        Ok(Ok(Some(status))) => {
+                            println!(
+                                "the dag block {} from {:?} is known already: {:?}",
+                                hash, remote, status
+                            );
+                            continue;
+                        }
+                        Ok(Err(error)) => {
+                            println!(
+                                "failed to read the status of the dag block {}: {}",
+                                hash, error
+                            );
+                            continue;
+                        }
+                        Err(error) => {
+                            println!("failed to send the dag block {}: {}", hash, error);
+                            continue;
+                        }
+                    }
+                    // Notifications carry single blocks, the sync fetches the missing past
+                    // instead of keeping unconnectable blocks of any peer aside
+                    match async_std::task::block_on(chain_service.send(GetMissingDagParents {
+                        header: header.clone(),
+                    })) {
+                        Ok(Ok(missing)) if missing.is_empty() => (),
+                        Ok(Ok(missing)) => {
+                            println!(
+                                "the dag block {} from {:?} has the unknown parents {:?}, dropped",
+                                hash, remote, missing
+                            );
+                            continue;
+                        }
+                        Ok(Err(error)) => {
+                            println!(
+                                "failed to check the parents of the dag block {}: {}",
+                                hash, error
+                            );
+                            continue;
+                        }
+                        Err(error) => {
+                            println!("failed to send the dag block {}: {}", hash, error);
+                            continue;
+                        }
+                    }
+                    match async_std::task::block_on(chain_service.send(ConnectDagBlock { header }))
+                    {
+                        Ok(Ok(())) => {
+                            println!("connected the dag block {} from {:?}", hash, remote);
+                        }
+                        Ok(Err(error)) => {
+                            println!("failed to connect the dag block {}: {}", hash, error);
+                        }
+                        Err(error) => {
+                            println!("failed to send the dag block {}: {}", hash, error);
+                        }
+                    }
+                }
+            }
             _ => (),
         }
     }
@@ -287,3 +395,13 @@ impl ServiceHandler<Self, GetBestChainInfo> for NetworkDagService {
         first.clone()
     }
 }
+
+impl ServiceHandler<Self, BroadcastDagBlock> for NetworkDagService {
+    fn handle(
+        &mut self,
+        msg: BroadcastDagBlock,
+        ctx: &mut starcoin_service_registry::ServiceContext<Self>,
+    ) -> <BroadcastDagBlock as ServiceRequest>::Response {
+        self.broadcast_message(msg.header.encode()?)
+    }
+}
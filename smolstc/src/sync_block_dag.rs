@@ -1,11 +1,8 @@
 use std::{collections::HashSet, sync::Arc};
 
-use bcs_ext::{BCSCodec, Sample};
-use consensus_types::{
-    blockhash::ORIGIN,
-    header::{ConsensusHeader, Header},
-};
-use consensus::blockdag::BlockDAG;
+use bcs_ext::BCSCodec;
+use consensus_types::header::{ConsensusHeader, Header};
+use consensus::{blockdag::BlockDAG, genesis::DagGenesisConfig};
 use database::prelude::{FlexiDagStorageConfig, FlexiDagStorage};
 use serde::{Deserialize, Serialize};
 use starcoin_accumulator::{
@@ -21,7 +18,7 @@ use starcoin_storage::{
 use starcoin_types::{block::{BlockHeader, BlockHeaderExtra}, genesis_config::ChainId, account_address::AccountAddress};
 
 pub struct SyncBlockDag {
-    pub dag: Arc<BlockDAG>,
+    pub dag: BlockDAG,
     pub accumulator: MerkleAccumulator,
     pub accumulator_snapshot: Arc<SyncFlexiDagSnapshotStorage>,
 }
@@ -62,14 +59,14 @@ impl SyncBlockDag {
         return db;
     }
 
-    fn new_basic_dag_test() -> (Vec<Header>, BlockDAG) {
-        let genesis = Header::new(BlockHeader::sample(), vec![HashValue::new(ORIGIN)]);
+    fn new_basic_dag_test(genesis_config: &DagGenesisConfig) -> (Vec<Header>, BlockDAG) {
+        let genesis = genesis_config.to_header();
         let genesis_hash = genesis.hash();
 
         let db = Self::new_dag_db_test();
 
-        let k = 16;
-        let mut dag = BlockDAG::new(genesis, k, db);
+        let mut dag = BlockDAG::new(genesis, genesis_config.k, db)
+            .expect("Failed to init the dag");
 
         let b = Header::new(
             Self::new_header_test(1),
@@ -147,14 +144,14 @@ impl SyncBlockDag {
         (result, dag)
     }
 
-    fn new_dag_diff_half_leaf_test() -> (Vec<Header>, BlockDAG) {
-        let genesis = Header::new(BlockHeader::sample(), vec![HashValue::new(ORIGIN)]);
+    fn new_dag_diff_half_leaf_test(genesis_config: &DagGenesisConfig) -> (Vec<Header>, BlockDAG) {
+        let genesis = genesis_config.to_header();
         let genesis_hash = genesis.hash();
 
         let db = Self::new_dag_db_test();
 
-        let k = 16;
-        let mut dag = BlockDAG::new(genesis, k, db);
+        let mut dag = BlockDAG::new(genesis, genesis_config.k, db)
+            .expect("Failed to init the dag");
 
         let diff_b = Header::new(
             Self::new_header_test(1),
@@ -234,14 +231,14 @@ impl SyncBlockDag {
 
 
 
-    fn new_dag_diff_leaf_test() -> (Vec<Header>, BlockDAG) {
-        let genesis = Header::new(BlockHeader::sample(), vec![HashValue::new(ORIGIN)]);
+    fn new_dag_diff_leaf_test(genesis_config: &DagGenesisConfig) -> (Vec<Header>, BlockDAG) {
+        let genesis = genesis_config.to_header();
         let genesis_hash = genesis.hash();
 
         let db = Self::new_dag_db_test();
 
-        let k = 16;
-        let mut dag = BlockDAG::new(genesis, k, db);
+        let mut dag = BlockDAG::new(genesis, genesis_config.k, db)
+            .expect("Failed to init the dag");
 
         let diff_b = Header::new(
             Self::new_header_test(1001),
@@ -319,45 +316,45 @@ impl SyncBlockDag {
         (result, dag)
     }
 
-    fn new_dag_half_diff_full_for_test() -> BlockDAG {
-        let (headers, mut dag) = Self::new_dag_diff_half_leaf_test();
+    fn new_dag_half_diff_full_for_test(genesis_config: &DagGenesisConfig) -> BlockDAG {
+        let (headers, mut dag) = Self::new_dag_diff_half_leaf_test(genesis_config);
         headers.into_iter().for_each(|header| {
             dag.commit_header(&header);
         });
         dag
     }
 
-    fn new_dag_diff_full_for_test() -> BlockDAG {
-        let (headers, mut dag) = Self::new_dag_diff_leaf_test();
+    fn new_dag_diff_full_for_test(genesis_config: &DagGenesisConfig) -> BlockDAG {
+        let (headers, mut dag) = Self::new_dag_diff_leaf_test(genesis_config);
         headers.into_iter().for_each(|header| {
             dag.commit_header(&header);
         });
         dag
     }
 
-    fn new_dag_lay1_for_test() -> BlockDAG {
-        let (headers, mut dag) = Self::new_basic_dag_test();
+    fn new_dag_lay1_for_test(genesis_config: &DagGenesisConfig) -> BlockDAG {
+        let (headers, mut dag) = Self::new_basic_dag_test(genesis_config);
         headers.get(0..4).unwrap().into_iter().for_each(|header| {
             dag.commit_header(&header);
         });
         dag
     }
 
-    fn new_dag_genesis_for_test() -> BlockDAG {
-        let (_, mut dag) = Self::new_basic_dag_test();
+    fn new_dag_genesis_for_test(genesis_config: &DagGenesisConfig) -> BlockDAG {
+        let (_, mut dag) = Self::new_basic_dag_test(genesis_config);
         dag
     }
  
-    fn new_dag_full_for_test_2() -> BlockDAG {
-        let (headers, mut dag) = Self::new_basic_dag_test();
+    fn new_dag_full_for_test_2(genesis_config: &DagGenesisConfig) -> BlockDAG {
+        let (headers, mut dag) = Self::new_basic_dag_test(genesis_config);
         headers.into_iter().for_each(|header| {
             dag.commit_header(&header);
         });
         dag
     }
 
-    pub fn build_sync_block_dag(store: Arc<Storage>) -> Self {
-        let dag = Self::new_dag_half_diff_full_for_test();
+    pub fn build_sync_block_dag(store: Arc<Storage>, genesis_config: &DagGenesisConfig) -> Self {
+        let dag = Self::new_dag_half_diff_full_for_test(genesis_config);
         let accumulator_store = store.get_accumulator_store(AccumulatorStoreType::SyncDag);
         let accumulator = MerkleAccumulator::new_empty(accumulator_store.clone());
         let accumulator_snapshot = store.get_accumulator_snapshot_storage();
@@ -451,7 +448,7 @@ impl SyncBlockDag {
         println!("finish to build accumulator, its info is: {:?}", accumulator.get_info());
 
         return SyncBlockDag {
-            dag: Arc::new(dag),
+            dag,
             accumulator,
             accumulator_snapshot: accumulator_snapshot.clone(),
         };